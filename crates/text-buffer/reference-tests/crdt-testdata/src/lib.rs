@@ -1,11 +1,11 @@
+//! This file contains some simple helpers for loading test data. Its used by benchmarking and
+//! testing code.
+
 use libflate::gzip::Decoder;
 use serde::Deserialize;
 use std::fs::File;
 use std::io::{BufReader, Read};
 
-/// This file contains some simple helpers for loading test data. Its used by benchmarking and
-/// testing code.
-
 /// (position, delete length, insert content).
 #[derive(Debug, Clone, Deserialize)]
 pub struct TestPatch(pub usize, pub usize, pub String);
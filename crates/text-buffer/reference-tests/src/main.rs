@@ -37,7 +37,7 @@ fn main() -> Result<(), usize> {
                 return Ok(());
             } else if i == step - 1 {
                 debug_txn = format!("{txn:?}");
-                debug_buffer = format!("{}", buffer.to_string());
+                debug_buffer = buffer.to_string();
                 debug_internals = format!("{buffer:?}");
             }
         }
@@ -60,6 +60,7 @@ fn main() -> Result<(), usize> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use text_buffer::{Op, Replica};
     fn apply_tokens(path: &str) {
         let path = format!("{}/crdt-testdata/data/{}.json.gz", env!("CARGO_MANIFEST_DIR"), path,);
         let test_data = crdt_testdata::load_testing_data(&path);
@@ -74,6 +75,51 @@ mod test {
         assert_eq!(buffer, test_data.end_content);
     }
 
+    fn send(inboxes: &mut [Vec<Op>], from: usize, op: &Op) {
+        for (idx, inbox) in inboxes.iter_mut().enumerate() {
+            if idx != from {
+                inbox.push(op.clone());
+            }
+        }
+    }
+
+    /// Replay the trace as if the transactions were made by several replicas
+    /// taking turns. Each replica receives the ops of the others in reverse
+    /// order just before its turn, so ops regularly arrive before the ones
+    /// they depend on.
+    fn merge_tokens(path: &str) {
+        let path = format!("{}/crdt-testdata/data/{}.json.gz", env!("CARGO_MANIFEST_DIR"), path,);
+        let test_data = crdt_testdata::load_testing_data(&path);
+        let mut replicas: Vec<_> = (0..3).map(Replica::new).collect();
+        let mut inboxes: Vec<Vec<Op>> = vec![Vec::new(); replicas.len()];
+        let op = replicas[0].insert(0, &test_data.start_content);
+        send(&mut inboxes, 0, &op);
+        for (i, txn) in test_data.txns.iter().enumerate() {
+            let idx = i % replicas.len();
+            let replica = &mut replicas[idx];
+            for op in inboxes[idx].drain(..).rev() {
+                replica.apply(op);
+            }
+            for TestPatch(pos, del, ins) in &txn.patches {
+                if *del > 0 {
+                    let op = replica.delete_range(*pos, pos + del);
+                    send(&mut inboxes, idx, &op);
+                }
+                if !ins.is_empty() {
+                    let op = replica.insert(*pos, ins);
+                    send(&mut inboxes, idx, &op);
+                }
+            }
+        }
+        for (replica, inbox) in replicas.iter_mut().zip(inboxes) {
+            for op in inbox.into_iter().rev() {
+                replica.apply(op);
+            }
+            assert_eq!(replica.pending(), 0);
+            assert_eq!(replica.buffer(), test_data.end_content.as_str());
+        }
+    }
+
     // NOTE: Run these tests in release mode, or it will take forever.
     // cargo test --profile=fast-debug
 
@@ -96,4 +142,24 @@ mod test {
     fn test_svelte() {
         apply_tokens("sveltecomponent");
     }
+
+    #[test]
+    fn test_merge_seph() {
+        merge_tokens("seph-blog1");
+    }
+
+    #[test]
+    fn test_merge_rustcode() {
+        merge_tokens("rustcode");
+    }
+
+    #[test]
+    fn test_merge_automerge() {
+        merge_tokens("automerge-paper");
+    }
+
+    #[test]
+    fn test_merge_svelte() {
+        merge_tokens("sveltecomponent");
+    }
 }
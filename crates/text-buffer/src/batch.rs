@@ -0,0 +1,128 @@
+#![allow(clippy::must_use_candidate)]
+//! Applying many edits to a buffer at once.
+//!
+//! The edits are validated up front and then applied from last to first. Each
+//! edit only touches text before the previous one, so the gap moves across the
+//! buffer in a single sweep, and the positions of the edits that are still to
+//! be applied never need to be adjusted.
+use crate::{Buffer, BufferError, InsertionType};
+use std::ops::Range;
+
+/// Maps char positions from before a call to [`Buffer::apply_edits`] to
+/// positions after it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditMap {
+    /// The old start, old end and inserted length of each edit in buffer order
+    edits: Vec<(usize, usize, usize)>,
+}
+
+impl EditMap {
+    /// Map the old char position `pos` to its position after the edits. This
+    /// moves `pos` the same way the edits move a marker: a position inside or
+    /// at the end of a replaced range moves to the start of the replacement,
+    /// and `kind` decides whether it then stays before the inserted text or
+    /// advances after it.
+    pub fn map(&self, pos: usize, kind: InsertionType) -> usize {
+        // Edits that start after pos can't move it
+        let relevant = self.edits.partition_point(|(start, ..)| *start <= pos);
+        let mut pos = pos;
+        for &(start, end, len) in self.edits[..relevant].iter().rev() {
+            if pos > end {
+                pos -= end - start;
+            } else if pos > start {
+                pos = start;
+            }
+            if pos > start || (pos == start && kind == InsertionType::Advance) {
+                pos += len;
+            }
+        }
+        pos
+    }
+
+    /// The char ranges of the inserted text after the edits, in buffer order.
+    pub fn inserted(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        let (mut old_end, mut new_end) = (0, 0);
+        self.edits.iter().map(move |&(start, end, len)| {
+            let new_start = new_end + (start - old_end);
+            (old_end, new_end) = (end, new_start + len);
+            new_start..new_end
+        })
+    }
+}
+
+impl Buffer {
+    /// Replace the text in each char range with its string. All ranges refer
+    /// to positions before any edit is applied and can be given in any order,
+    /// but they must not overlap. Insertions at the same position end up in
+    /// the order they were given. The cursor is mapped like a marker that
+    /// stays before inserted text.
+    ///
+    /// Returns a map from the old positions to the new ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any range is inverted, outside of the accessible
+    /// region or overlaps another range. No edits are applied.
+    pub fn apply_edits(&mut self, edits: &[(Range<usize>, &str)]) -> Result<EditMap, BufferError> {
+        let mut order: Vec<_> = (0..edits.len()).collect();
+        order.sort_by_key(|idx| (edits[*idx].0.start, edits[*idx].0.end));
+        let mut prev_end = 0;
+        for &idx in &order {
+            let Range { start, end } = edits[idx].0;
+            self.check_char_range(start, end)?;
+            if start < prev_end {
+                return Err(BufferError::Overlap { start, end: prev_end });
+            }
+            prev_end = end;
+        }
+        let cursor = self.cursor();
+        let mut map = Vec::with_capacity(edits.len());
+        for &idx in order.iter().rev() {
+            let (Range { start, end }, text) = &edits[idx];
+            self.delete_range(*start, *end);
+            self.set_cursor(*start);
+            self.insert(text);
+            map.push((*start, *end, self.cursor() - start));
+        }
+        map.reverse();
+        let map = EditMap { edits: map };
+        self.set_cursor(map.map(cursor, InsertionType::Stay));
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Buffer, BufferError, InsertionType};
+
+    #[test]
+    fn test_apply_edits() {
+        let mut buffer = Buffer::from("hello world");
+        buffer.set_cursor(8);
+        let marker = buffer.create_marker(4, InsertionType::Advance);
+        let edits = [(6..11, "there"), (0..0, ">"), (0..5, "Θ"), (5..5, ","), (0..0, "<")];
+        let map = buffer.apply_edits(&edits).unwrap();
+        assert_eq!(buffer, "><Θ, there");
+        assert_eq!(buffer.cursor(), 5);
+        assert_eq!(map.map(8, InsertionType::Stay), 5);
+        assert_eq!(map.map(0, InsertionType::Stay), 0);
+        assert_eq!(map.map(0, InsertionType::Advance), 3);
+        assert_eq!(map.map(5, InsertionType::Stay), 0);
+        assert_eq!(map.map(5, InsertionType::Advance), 4);
+        assert_eq!(map.map(11, InsertionType::Advance), 10);
+        assert_eq!(buffer.marker_position(marker), map.map(4, InsertionType::Advance));
+        let inserted: Vec<_> = map.inserted().collect();
+        assert_eq!(inserted, [0..1, 1..2, 2..3, 3..4, 5..10]);
+    }
+
+    #[test]
+    fn test_invalid_edits() {
+        let mut buffer = Buffer::from("hello world");
+        let overlap = buffer.apply_edits(&[(3..6, "a"), (0..4, "b")]);
+        assert_eq!(overlap, Err(BufferError::Overlap { start: 3, end: 4 }));
+        let out_of_range = buffer.apply_edits(&[(0..1, "a"), (10..12, "b")]);
+        assert_eq!(out_of_range, Err(BufferError::OutOfRange { pos: 12, start: 0, end: 11 }));
+        assert_eq!(buffer, "hello world");
+        assert_eq!(buffer.apply_edits(&[]).unwrap().map(4, InsertionType::Stay), 4);
+    }
+}
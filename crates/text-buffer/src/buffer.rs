@@ -1,83 +1,199 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::must_use_candidate)]
 #![allow(clippy::missing_panics_doc)]
-use crate::metric::{BufferMetrics, Metric};
+use crate::{
+    encoding::{self, is_char_boundary, DecodeError},
+    history::{self, Edit, History},
+    intervals::{Property, PropertyList, Stickiness, TextProperties},
+    marker::{InsertionType, MarkerId, Markers},
+    metric::{BufferMetrics, Metric, Unit},
+    overlay::{OverlayId, Overlays},
+    storage::{Pieces, Storage},
+    view::{SavedView, ViewId, Views},
+};
 use std::{
     borrow::Cow,
     fmt::{Debug, Display},
     ops::{Bound, Deref, Range, RangeBounds},
 };
-use str_indices::chars;
 
 /// A Gap buffer. This represents the text of a buffer, and allows for
 /// efficient insertion and deletion of text.
+///
+/// All of the read-only methods are defined on [`Snapshot`], which the buffer
+/// derefs to.
 #[derive(Default)]
 pub struct Buffer {
+    /// The text of the buffer
+    text: Snapshot,
+    /// The current cursor.
+    cursor: Metric,
+    /// The edit journal, if undo is enabled
+    history: Option<History>,
+    markers: Markers,
+    overlays: Overlays,
+    /// The text properties of every char in the buffer
+    properties: TextProperties<Property, Property>,
+    /// Markers for the start and end of the accessible region, if the buffer
+    /// is narrowed
+    restriction: Option<(MarkerId, MarkerId)>,
+    /// The saved cursors and restrictions of the inactive views
+    views: Views,
+    /// Counts the edits made to the text
+    modified_tick: u64,
+    /// The value of `modified_tick` when the text was last marked unmodified
+    save_tick: u64,
+}
+
+/// The error returned by the fallible `try_*` methods of [`Buffer`] and
+/// [`Snapshot`]. Positions are in the unit the method was called with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BufferError {
+    /// `pos` is outside of the accessible region `start..=end`
+    OutOfRange {
+        pos: usize,
+        start: usize,
+        end: usize,
+    },
+    /// The range starts after it ends
+    Inverted { start: usize, end: usize },
+    /// The byte position is inside of a multi-byte char
+    NotCharBoundary(usize),
+    /// A range starting at `start` overlaps another range that ends at `end`
+    Overlap { start: usize, end: usize },
+    /// The text could not be decoded as a string
+    Decode(DecodeError),
+}
+
+impl Display for BufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfRange { pos, start, end } => {
+                write!(f, "position {pos} is outside of the range {start}..={end}")
+            }
+            Self::Inverted { start, end } => write!(f, "range start {start} is after end {end}"),
+            Self::NotCharBoundary(pos) => write!(f, "byte position {pos} is not a char boundary"),
+            Self::Overlap { start, end } => {
+                write!(f, "range starting at {start} overlaps a range ending at {end}")
+            }
+            Self::Decode(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for BufferError {}
+
+impl From<DecodeError> for BufferError {
+    fn from(err: DecodeError) -> Self {
+        Self::Decode(err)
+    }
+}
+
+/// An immutable view of the text of a [`Buffer`]. Taking a snapshot with
+/// [`Buffer::snapshot`] is cheap because the text and metrics are shared
+/// with the buffer. When the buffer is edited while a snapshot of it is still
+/// alive, it only makes a private copy of the chunks of text and the nodes of
+/// the metric tree that the edit touches.
+#[derive(Default, Clone)]
+pub struct Snapshot {
     /// The buffer data
-    data: Box<[u8]>,
-    /// start of the gap. Both gap_start and gap_end are the same point, but
-    /// gap_start is never a valid byte index, and gap_end is always used
+    data: Storage,
+    /// start of the gap. Both `gap_start` and `gap_end` are the same point, but
+    /// `gap_start` is never a valid byte index, and `gap_end` is always used
     /// instead.
     gap_start: usize,
     /// The end of the gap in bytes
     gap_end: usize,
     /// The number of characters until the gap
     gap_chars: usize,
-    /// The current cursor.
-    cursor: Metric,
     total: Metric,
     metrics: BufferMetrics,
+    /// The char bounds of the accessible region, if the buffer is narrowed
+    restriction: Option<(usize, usize)>,
 }
 
 impl Debug for Buffer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let start = self.to_str(..self.gap_start);
-        let end = self.to_str(self.gap_end..);
+        f.debug_struct("Buffer")
+            .field("text", &self.text)
+            .field("cursor", &self.cursor)
+            .field("history", &self.history)
+            .field("markers", &self.markers)
+            .field("overlays", &self.overlays)
+            .field("properties", &self.properties)
+            .field("restriction", &self.restriction)
+            .field("views", &self.views)
+            .field("modified_tick", &self.modified_tick)
+            .field("save_tick", &self.save_tick)
+            .finish()
+    }
+}
+
+impl Display for Buffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.text, f)
+    }
+}
+
+impl Deref for Buffer {
+    type Target = Snapshot;
+
+    fn deref(&self) -> &Self::Target {
+        &self.text
+    }
+}
+
+impl Debug for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let start = self.data.pieces(0..self.gap_start).collect::<Vec<_>>().concat();
+        let end = self.data.pieces(self.gap_end..self.data.len()).collect::<Vec<_>>().concat();
+        let (start, end) = (String::from_utf8_lossy(&start), String::from_utf8_lossy(&end));
         // repeat _ for the gap length
         let gap = "_".repeat(self.gap_len());
-        f.debug_struct("Buffer")
+        f.debug_struct("Snapshot")
             .field("data", &format!("{start}{gap}{end}"))
             .field("gap_start", &self.gap_start)
             .field("gap_end", &self.gap_end)
             .field("gap_chars", &self.gap_chars)
-            .field("cursor", &self.cursor)
             .field("metrics", &self.metrics)
             .field("total_chars", &self.total.chars)
+            .field("restriction", &self.restriction)
             .finish()
     }
 }
 
-impl Display for Buffer {
+impl Display for Snapshot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.read(0..self.len()))
+        for piece in self.pieces(0..self.len()) {
+            encoding::write_lossy(f, piece)?;
+        }
+        Ok(())
     }
 }
 
 const METRIC_SIZE: usize = crate::metric::MAX_LEAF / 2;
 struct MetricBuilder<'a> {
-    slice: &'a str,
+    slice: &'a [u8],
     start: usize,
     end: usize,
 }
 
 impl<'a> MetricBuilder<'a> {
-    fn new(slice: &'a str) -> Self {
+    fn new(slice: &'a [u8]) -> Self {
         Self { slice, start: 0, end: slice.len().min(METRIC_SIZE) }
     }
 }
 
-impl<'a> Iterator for MetricBuilder<'a> {
+impl Iterator for MetricBuilder<'_> {
     type Item = Metric;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.start == self.slice.len() {
             return None;
         }
-        let mut end = self.end;
-        if end != self.slice.len() {
-            while !self.slice.is_char_boundary(end) {
-                end -= 1;
-            }
+        let mut end = self.end.min(self.slice.len());
+        while end != self.slice.len() && !is_char_boundary(self.slice[end]) {
+            end -= 1;
         }
         let slice = &self.slice[self.start..end];
         self.start = end;
@@ -87,7 +203,7 @@ impl<'a> Iterator for MetricBuilder<'a> {
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         let len = self.slice.len() - self.start;
-        let extra = usize::from(len % METRIC_SIZE != 0);
+        let extra = usize::from(!len.is_multiple_of(METRIC_SIZE));
         let size = len / METRIC_SIZE;
         (size + extra, None)
     }
@@ -95,25 +211,15 @@ impl<'a> Iterator for MetricBuilder<'a> {
 
 impl From<&str> for Buffer {
     fn from(data: &str) -> Self {
-        let storage = {
-            let capacity = data.len() + Self::GAP_SIZE;
-            let mut storage = Vec::with_capacity(capacity);
-            storage.resize(Self::GAP_SIZE, 0);
-            storage.extend_from_slice(data.as_bytes());
-            assert_eq!(storage.len(), capacity);
-            storage.into_boxed_slice()
-        };
-        let builder = MetricBuilder::new(data);
-        let metrics = BufferMetrics::build(builder);
-        Self {
-            data: storage,
-            gap_start: 0,
-            gap_end: Self::GAP_SIZE,
-            gap_chars: 0,
-            cursor: Metric { bytes: Self::GAP_SIZE, chars: 0 },
-            total: metrics.len(),
-            metrics,
-        }
+        Self::from_internal(data.as_bytes())
+    }
+}
+
+impl From<&[u8]> for Buffer {
+    /// Create a buffer holding arbitrary bytes. Bytes that are not part of
+    /// valid UTF-8 are stored as raw byte chars.
+    fn from(data: &[u8]) -> Self {
+        Self::from_internal(&encoding::from_binary(data))
     }
 }
 
@@ -122,17 +228,36 @@ where
     T: Deref<Target = str>,
 {
     fn eq(&self, other: &T) -> bool {
-        PartialEq::eq(self, Deref::deref(other))
+        PartialEq::eq(self, &**other)
     }
 }
 
 impl PartialEq<str> for Buffer {
+    fn eq(&self, other: &str) -> bool {
+        self.text == *other
+    }
+}
+
+impl<T> PartialEq<T> for Snapshot
+where
+    T: Deref<Target = str>,
+{
+    fn eq(&self, other: &T) -> bool {
+        PartialEq::eq(self, &**other)
+    }
+}
+
+impl PartialEq<str> for Snapshot {
     fn eq(&self, other: &str) -> bool {
         if self.len() != other.len() {
             return false;
         }
-        self.to_str(..self.gap_start) == &other[..self.gap_start]
-            && self.to_str(self.gap_end..) == &other[self.gap_start..]
+        let mut other = other.as_bytes();
+        self.pieces(0..self.len()).all(|piece| {
+            let (head, tail) = other.split_at(piece.len());
+            other = tail;
+            head == piece
+        })
     }
 }
 
@@ -147,33 +272,86 @@ impl Buffer {
         Self::default()
     }
 
-    fn grow(&mut self, slice: &str) {
+    /// Create a buffer from text in the internal encoding.
+    fn from_internal(data: &[u8]) -> Self {
+        let storage = {
+            let capacity = data.len() + Self::GAP_SIZE;
+            let mut storage = Vec::with_capacity(capacity);
+            storage.resize(Self::GAP_SIZE, 0);
+            storage.extend_from_slice(data);
+            assert_eq!(storage.len(), capacity);
+            Storage::from(storage.as_slice())
+        };
+        let builder = MetricBuilder::new(data);
+        let metrics = BufferMetrics::build(builder);
+        let text = Snapshot {
+            data: storage,
+            gap_start: 0,
+            gap_end: Self::GAP_SIZE,
+            gap_chars: 0,
+            total: metrics.len(),
+            metrics,
+            restriction: None,
+        };
+        let properties = TextProperties::new(text.total.chars);
+        Self {
+            text,
+            cursor: Metric { bytes: Self::GAP_SIZE, ..Metric::default() },
+            history: None,
+            markers: Markers::default(),
+            overlays: Overlays::default(),
+            properties,
+            restriction: None,
+            views: Views::default(),
+            modified_tick: 0,
+            save_tick: 0,
+        }
+    }
+
+    fn grow(&mut self, slice: &[u8]) {
         let new_capacity = {
-            let pre_gap = self.gap_start;
-            let post_gap = self.data.len() - self.gap_end;
+            let pre_gap = self.text.gap_start;
+            let post_gap = self.text.data.len() - self.text.gap_end;
             pre_gap + slice.len() + Self::GAP_SIZE + post_gap
         };
         let new_storage = {
             let mut buffer = Vec::with_capacity(new_capacity);
             // pre-gap
-            buffer.extend_from_slice(&self.data[..self.gap_start]);
+            for piece in self.text.pieces(0..self.text.gap_start) {
+                buffer.extend_from_slice(piece);
+            }
             // new text
-            buffer.extend_from_slice(slice.as_bytes());
+            buffer.extend_from_slice(slice);
             // gap
             buffer.resize(buffer.len() + Self::GAP_SIZE, 0);
             // post-gap
-            buffer.extend_from_slice(&self.data[self.gap_end..]);
-            buffer.into_boxed_slice()
+            for piece in self.text.pieces(self.text.gap_start..self.text.len()) {
+                buffer.extend_from_slice(piece);
+            }
+            buffer
         };
         assert_eq!(new_storage.len(), new_capacity);
-        self.data = new_storage;
-        self.gap_start += slice.len();
-        self.gap_end = self.gap_start + Self::GAP_SIZE;
-        self.cursor.bytes = self.gap_end;
+        self.text.data = Storage::from(new_storage.as_slice());
+        self.text.gap_start += slice.len();
+        self.text.gap_end = self.text.gap_start + Self::GAP_SIZE;
         let new = metrics(slice);
-        self.gap_chars += new.chars;
-        self.cursor.chars = self.gap_chars;
-        self.total += new;
+        self.text.gap_chars += new.chars;
+        self.cursor = Metric { bytes: self.text.gap_end, ..self.cursor + new };
+        self.text.total += new;
+    }
+
+    /// Return a snapshot of the current text. This is cheap; while the
+    /// snapshot is alive, edits only copy the parts of the text they touch.
+    pub fn snapshot(&self) -> Snapshot {
+        self.text.clone()
+    }
+
+    fn data_mut(&mut self) -> &mut Storage {
+        &mut self.text.data
+    }
+
+    fn metrics_mut(&mut self) -> &mut BufferMetrics {
+        &mut self.text.metrics
     }
 
     pub fn insert_char(&mut self, chr: char) {
@@ -181,10 +359,48 @@ impl Buffer {
         self.insert(chr.encode_utf8(buf));
     }
 
+    /// Insert the char `code` at the cursor. Unlike [`Buffer::insert_char`]
+    /// this can insert chars that are not Unicode scalar values, such as raw
+    /// bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `code` is greater than [`MAX_CHAR`](crate::MAX_CHAR).
+    pub fn insert_char_code(&mut self, code: u32) {
+        let buf = &mut [0; 5];
+        self.insert_internal(encoding::encode_char(code, buf));
+    }
+
     pub fn insert(&mut self, slice: &str) {
-        self.metrics.insert(self.to_abs_pos(self.cursor), MetricBuilder::new(slice));
+        self.insert_internal(slice.as_bytes());
+    }
+
+    /// Insert arbitrary bytes at the cursor. Bytes that are not part of valid
+    /// UTF-8 are inserted as raw byte chars, so reading the text back with
+    /// [`Snapshot::read_binary`] returns the original bytes.
+    pub fn insert_bytes(&mut self, bytes: &[u8]) {
+        self.insert_internal(&encoding::from_binary(bytes));
+    }
+
+    /// Insert `slice` at the cursor with the sticky text properties of the
+    /// chars around it. See [`Buffer::set_property_stickiness`].
+    pub fn insert_and_inherit(&mut self, slice: &str) {
+        let start = self.cursor.chars;
+        let props = self.properties.inherited(start);
+        self.insert(slice);
+        self.properties.set_properties(start..self.cursor.chars, &props);
+    }
+
+    /// Insert text that is already in the internal encoding.
+    fn insert_internal(&mut self, slice: &[u8]) {
+        if let Some(history) = &mut self.history {
+            history.insert(self.cursor.chars, slice);
+        }
+        let start = self.cursor.chars;
+        let pos = self.to_abs_pos(self.cursor);
+        self.metrics_mut().insert(pos, MetricBuilder::new(slice));
         // if gap is not at cursor, move it there
-        if self.gap_chars != self.cursor.chars {
+        if self.text.gap_chars != self.cursor.chars {
             // TODO: we don't need to recalculate the position
             self.move_gap(self.cursor);
         }
@@ -192,18 +408,26 @@ impl Buffer {
             // TODO: grow the gap and move the cursor in one go
             self.grow(slice);
         } else {
-            let new_slice = &mut self.data[self.gap_start..(self.gap_start + slice.len())];
-            new_slice.copy_from_slice(slice.as_bytes());
-            self.gap_start += slice.len();
+            let gap_start = self.text.gap_start;
+            self.data_mut().write(gap_start, slice);
+            self.text.gap_start += slice.len();
             let new = metrics(slice);
-            self.gap_chars += new.chars;
-            self.cursor.chars += new.chars;
-            self.total += new;
+            self.text.gap_chars += new.chars;
+            self.cursor = Metric { bytes: self.cursor.bytes, ..self.cursor + new };
+            self.text.total += new;
+        }
+        let inserted = self.cursor.chars - start;
+        if inserted > 0 {
+            self.modified_tick += 1;
         }
+        self.markers.insert(start, inserted, self.text.total.chars - inserted);
+        self.overlays.insert(start, inserted);
+        self.properties.insert(start, inserted);
+        self.sync_restriction();
     }
 
     pub fn delete_backwards(&mut self, size: usize) {
-        let size = size.min(self.cursor.chars);
+        let size = size.min(self.cursor.chars - self.begv());
         self.delete_range(self.cursor.chars - size, self.cursor.chars);
     }
 
@@ -211,30 +435,55 @@ impl Buffer {
         self.delete_range(self.cursor.chars, self.cursor.chars + size);
     }
 
+    /// Like [`Buffer::delete_range`], but `beg..end` must be ordered and
+    /// inside the accessible region instead of being fixed up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range is inverted or out of range. The buffer
+    /// is not changed.
+    pub fn try_delete_range(&mut self, beg: usize, end: usize) -> Result<(), BufferError> {
+        self.check_char_range(beg, end)?;
+        self.delete_range(beg, end);
+        Ok(())
+    }
+
     pub fn delete_range(&mut self, beg: usize, end: usize) {
         let (mut beg_chars, mut end_chars) = (beg, end);
         if beg_chars > end_chars {
             (beg_chars, end_chars) = (end_chars, beg_chars);
         }
-        end_chars = end_chars.min(self.total.chars);
-        beg_chars = beg_chars.min(self.total.chars);
-        let end_bytes = self.char_to_byte(end_chars);
-        let beg_bytes = self.char_to_byte(beg_chars);
-        if end_bytes != beg_bytes {
-            let beg = Metric { bytes: beg_bytes, chars: beg_chars };
-            let end = Metric { bytes: end_bytes, chars: end_chars };
-            self.metrics.delete(self.to_abs_pos(beg), self.to_abs_pos(end));
+        end_chars = self.text.clamp(end_chars);
+        beg_chars = self.text.clamp(beg_chars);
+        let end = self.char_to_metric(end_chars);
+        let beg = self.char_to_metric(beg_chars);
+        if end.bytes != beg.bytes {
+            let deleted = self.history.is_some().then(|| {
+                let range = self.to_abs_pos(beg).bytes..self.to_abs_pos(end).bytes;
+                self.pieces(range).collect::<Vec<_>>().concat()
+            });
+            let cursor = self.cursor.chars;
+            self.markers.delete(beg_chars, end_chars, self.text.total.chars);
+            self.overlays.delete(beg_chars, end_chars);
+            self.properties.delete(beg_chars, end_chars);
+            let (abs_beg, abs_end) = (self.to_abs_pos(beg), self.to_abs_pos(end));
+            self.metrics_mut().delete(abs_beg, abs_end);
             self.delete_byte_range(beg, end);
+            self.sync_restriction();
+            self.modified_tick += 1;
+            if let (Some(history), Some(text)) = (&mut self.history, deleted) {
+                history.delete(cursor, beg_chars, text, self.cursor.chars);
+            }
         }
     }
 
     fn delete_byte_range(&mut self, beg: Metric, end: Metric) {
         // TODO: optimize this so that we count the chars deleted when calculating position
         assert!(beg.bytes <= end.bytes, "beg ({beg}) is greater then end ({end})");
-        assert!(end.bytes <= self.data.len(), "end out of bounds");
+        assert!(end.bytes <= self.text.data.len(), "end out of bounds");
         self.assert_char_boundary(beg.bytes);
         self.assert_char_boundary(end.bytes);
-        if end.bytes < self.gap_start {
+        if end.bytes < self.text.gap_start {
             // delete before gap
             //
             // hello New York City||||||||||
@@ -249,15 +498,16 @@ impl Buffer {
 
             // update character count
             let deleted = end - beg;
-            let delete_offset_chars = self.gap_chars - end.chars;
-            self.gap_chars -= deleted.chars + delete_offset_chars;
-            self.total -= deleted;
-            let new_end = self.gap_end - (self.gap_start - end.bytes);
+            let delete_offset_chars = self.text.gap_chars - end.chars;
+            self.text.gap_chars -= deleted.chars + delete_offset_chars;
+            self.text.total -= deleted;
+            let new_end = self.text.gap_end - (self.text.gap_start - end.bytes);
             // shift data
-            self.data.copy_within(end.bytes..self.gap_start, new_end);
+            let gap_start = self.text.gap_start;
+            self.data_mut().copy_within(end.bytes..gap_start, new_end);
             // update cursor
-            self.update_cursor_chars(beg.bytes, end.bytes, deleted.chars);
-            if self.cursor.bytes < self.gap_start {
+            self.update_cursor(beg, end, deleted);
+            if self.cursor.bytes < self.text.gap_start {
                 if self.cursor.bytes > end.bytes {
                     self.cursor.bytes += self.gap_len();
                 } else if self.cursor.bytes >= beg.bytes {
@@ -265,9 +515,9 @@ impl Buffer {
                 }
             }
             // update gap position
-            self.gap_end = new_end;
-            self.gap_start = beg.bytes;
-        } else if beg.bytes >= self.gap_end {
+            self.text.gap_end = new_end;
+            self.text.gap_start = beg.bytes;
+        } else if beg.bytes >= self.text.gap_end {
             // delete after gap
             //
             // ||||||||||hello New York City
@@ -283,13 +533,14 @@ impl Buffer {
             // update character count
 
             let deleted = end - beg;
-            self.total -= deleted;
-            self.gap_chars += beg.chars - self.gap_chars;
+            self.text.total -= deleted;
+            self.text.gap_chars += beg.chars - self.text.gap_chars;
             // shift data
-            self.data.copy_within(self.gap_end..beg.bytes, self.gap_start);
+            let (gap_start, gap_end) = (self.text.gap_start, self.text.gap_end);
+            self.data_mut().copy_within(gap_end..beg.bytes, gap_start);
             // update cursor
-            self.update_cursor_chars(beg.bytes, end.bytes, deleted.chars);
-            if self.cursor.bytes >= self.gap_end {
+            self.update_cursor(beg, end, deleted);
+            if self.cursor.bytes >= self.text.gap_end {
                 if self.cursor.bytes < beg.bytes {
                     self.cursor.bytes -= self.gap_len();
                 } else if self.cursor.bytes < end.bytes {
@@ -297,9 +548,9 @@ impl Buffer {
                 }
             }
             // update gap position
-            self.gap_start += beg.bytes - self.gap_end;
-            self.gap_end = end.bytes;
-        } else if beg.bytes < self.gap_start && end.bytes >= self.gap_end {
+            self.text.gap_start += beg.bytes - self.text.gap_end;
+            self.text.gap_end = end.bytes;
+        } else if beg.bytes < self.text.gap_start && end.bytes >= self.text.gap_end {
             // delete spans gap
             //
             // hello|||||||||| New York City
@@ -313,40 +564,39 @@ impl Buffer {
             //  gap_start             gap_end
 
             // update character count
-            let gap_start = Metric { bytes: self.gap_start, chars: self.gap_chars };
-            let before = gap_start - beg;
-            let gap_end = Metric { bytes: self.gap_end, chars: self.gap_chars };
-            let after = end - gap_end;
-            self.gap_chars -= before.chars;
-            self.total -= before + after;
+            let mut deleted = end - beg;
+            deleted.bytes -= self.gap_len();
+            self.text.gap_chars = beg.chars;
+            self.text.total -= deleted;
             // update gap position
-            self.gap_start = beg.bytes;
-            self.gap_end = end.bytes;
-            self.update_cursor_chars(beg.bytes, end.bytes, before.chars + after.chars);
+            self.text.gap_start = beg.bytes;
+            self.text.gap_end = end.bytes;
+            self.update_cursor(beg, end, deleted);
             if (beg.bytes..end.bytes).contains(&self.cursor.bytes) {
                 self.cursor.bytes = end.bytes;
             }
         } else {
             panic!(
                 "delete region inside gap -- gap: {}-{}, span: {beg}-{end}",
-                self.gap_start, self.gap_end
+                self.text.gap_start, self.text.gap_end
             );
         }
     }
 
-    fn update_cursor_chars(&mut self, beg: usize, end: usize, size: usize) {
-        if self.cursor.bytes > beg {
-            if self.cursor.bytes > end {
-                self.cursor.chars -= size;
+    fn update_cursor(&mut self, beg: Metric, end: Metric, deleted: Metric) {
+        if self.cursor.bytes > beg.bytes {
+            let bytes = self.cursor.bytes;
+            if bytes > end.bytes {
+                self.cursor = Metric { bytes, ..self.cursor - deleted };
             } else {
-                self.cursor.chars = self.gap_chars;
+                self.cursor = Metric { bytes, ..beg };
             }
         }
     }
 
     pub fn move_gap_out_of(&mut self, range: impl RangeBounds<usize>) {
-        if !range.contains(&self.gap_chars)
-            || range.start_bound() == Bound::Included(&self.gap_chars)
+        if !range.contains(&self.text.gap_chars)
+            || range.start_bound() == Bound::Included(&self.text.gap_chars)
         {
             return;
         }
@@ -360,59 +610,399 @@ impl Buffer {
         let end = match range.end_bound() {
             Bound::Included(_) => unimplemented!("inclusive end bound not supported"),
             Bound::Excluded(x) => *x,
-            Bound::Unbounded => self.total.chars,
+            Bound::Unbounded => self.text.total.chars,
         };
 
-        let pos = if self.gap_chars - start < end - self.gap_chars {
-            Metric { bytes: self.char_to_byte(start), chars: start }
+        let pos = if self.text.gap_chars - start < end - self.text.gap_chars {
+            self.char_to_metric(start)
         } else {
-            Metric { bytes: self.char_to_byte(end), chars: end }
+            self.char_to_metric(end)
         };
         self.move_gap(pos);
     }
 
     fn move_gap(&mut self, pos: Metric) {
-        assert!(pos.bytes <= self.data.len(), "attempt to move gap out of bounds");
+        assert!(pos.bytes <= self.text.data.len(), "attempt to move gap out of bounds");
         self.assert_char_boundary(pos.bytes);
-        if pos.bytes < self.gap_start {
+        if pos.bytes < self.text.gap_start {
             // move gap backwards
-            let shift = Metric { bytes: self.gap_start, chars: self.gap_chars } - pos;
-            self.gap_chars -= shift.chars;
+            let shift = self.text.gap_start - pos.bytes;
+            self.text.gap_chars = pos.chars;
 
-            self.data.copy_within(pos.bytes..self.gap_start, self.gap_end - shift.bytes);
+            let (gap_start, gap_end) = (self.text.gap_start, self.text.gap_end);
+            self.data_mut().copy_within(pos.bytes..gap_start, gap_end - shift);
             // if gap moves across cursor, update cursor position
-            if self.cursor.bytes < self.gap_start && self.cursor.bytes >= pos.bytes {
+            if self.cursor.bytes < self.text.gap_start && self.cursor.bytes >= pos.bytes {
                 self.cursor.bytes += self.gap_len();
             }
-            self.gap_start = pos.bytes;
-            self.gap_end -= shift.bytes;
-        } else if pos.bytes >= self.gap_end {
+            self.text.gap_start = pos.bytes;
+            self.text.gap_end -= shift;
+        } else if pos.bytes >= self.text.gap_end {
             // move gap forwards
-            self.gap_chars += pos.chars - self.gap_chars;
-            self.data.copy_within(self.gap_end..pos.bytes, self.gap_start);
-            let size = pos.bytes - self.gap_end;
+            self.text.gap_chars += pos.chars - self.text.gap_chars;
+            let (gap_start, gap_end) = (self.text.gap_start, self.text.gap_end);
+            self.data_mut().copy_within(gap_end..pos.bytes, gap_start);
+            let size = pos.bytes - self.text.gap_end;
             // if gap moves across cursor, update cursor position
-            if self.cursor.bytes >= self.gap_end && self.cursor.bytes < pos.bytes {
+            if self.cursor.bytes >= self.text.gap_end && self.cursor.bytes < pos.bytes {
                 self.cursor.bytes -= self.gap_len();
             }
-            self.gap_start += size;
-            self.gap_end = pos.bytes;
+            self.text.gap_start += size;
+            self.text.gap_end = pos.bytes;
         } else {
             panic!(
                 "move gap position byte: ({pos}) inside gap ({}-{})",
-                self.gap_start, self.gap_end
+                self.text.gap_start, self.text.gap_end
             );
         }
     }
 
+    /// The char position of the cursor.
+    pub const fn cursor(&self) -> usize {
+        self.cursor.chars
+    }
+
+    /// Move the cursor to char position `pos`. The cursor is kept inside the
+    /// accessible region.
     pub fn set_cursor(&mut self, pos: usize) {
-        let pos = pos.min(self.total.chars);
-        let byte_pos = self.char_to_byte(pos);
-        self.cursor = Metric { bytes: byte_pos, chars: pos };
+        let pos = self.text.clamp(pos);
+        self.cursor = self.char_to_metric(pos);
+    }
+
+    /// Like [`Buffer::set_cursor`], but `pos` must be inside the accessible
+    /// region instead of being clamped to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pos` is out of range. The cursor is not moved.
+    pub fn try_set_cursor(&mut self, pos: usize) -> Result<(), BufferError> {
+        self.check_char_pos(pos)?;
+        self.set_cursor(pos);
+        Ok(())
+    }
+
+    /// Restrict the accessible region to the char `range`. The cursor is moved
+    /// into the region, edits are clamped to it, and reads and searches only
+    /// see the text inside it. The bounds are adjusted as text is inserted and
+    /// deleted; text inserted at either bound goes inside the region.
+    pub fn narrow(&mut self, range: Range<usize>) {
+        let len = self.text.total.chars;
+        let (beg, end) = (range.start.min(len), range.end.min(len));
+        let (beg, end) = (beg.min(end), beg.max(end));
+        if let Some((begv, zv)) = self.restriction {
+            self.markers.set_position(begv, beg, len);
+            self.markers.set_position(zv, end, len);
+        } else {
+            let begv = self.markers.create(beg, InsertionType::Stay, len);
+            let zv = self.markers.create(end, InsertionType::Advance, len);
+            self.restriction = Some((begv, zv));
+        }
+        self.sync_restriction();
+        self.set_cursor(self.cursor.chars);
+    }
+
+    /// Remove the restriction so that the whole buffer is accessible.
+    pub fn widen(&mut self) {
+        if let Some((begv, zv)) = self.restriction.take() {
+            self.markers.remove(begv);
+            self.markers.remove(zv);
+        }
+        self.text.restriction = None;
+    }
+
+    /// Copy the positions of the restriction markers into the text.
+    fn sync_restriction(&mut self) {
+        let len = self.text.total.chars;
+        self.text.restriction = self
+            .restriction
+            .map(|(begv, zv)| (self.markers.position(begv, len), self.markers.position(zv, len)));
+    }
+
+    /// Run `f` with the restriction lifted. The restriction markers are still
+    /// adjusted by any edits `f` makes.
+    fn unrestricted<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let restriction = self.restriction.take();
+        self.text.restriction = None;
+        let result = f(self);
+        self.restriction = restriction;
+        self.sync_restriction();
+        self.set_cursor(self.cursor.chars);
+        result
+    }
+
+    /// The view that the cursor and restriction belong to.
+    pub fn view(&self) -> ViewId {
+        self.views.active()
+    }
+
+    /// Create a new view of the text that starts with the same cursor and
+    /// restriction as the active view. The active view doesn't change.
+    pub fn create_view(&mut self) -> ViewId {
+        let len = self.text.total.chars;
+        let cursor = self.markers.create(self.cursor.chars, InsertionType::Stay, len);
+        let restriction = self.text.restriction.map(|(beg, end)| {
+            let begv = self.markers.create(beg, InsertionType::Stay, len);
+            (begv, self.markers.create(end, InsertionType::Advance, len))
+        });
+        self.views.insert(SavedView { cursor, restriction })
+    }
+
+    /// Make `view` the active view. The cursor and restriction of the old
+    /// view are saved and will be adjusted by edits until it is active again.
+    pub fn set_view(&mut self, view: ViewId) {
+        if view == self.views.active() {
+            return;
+        }
+        let len = self.text.total.chars;
+        let cursor = self.markers.create(self.cursor.chars, InsertionType::Stay, len);
+        let current = SavedView { cursor, restriction: self.restriction.take() };
+        let saved = self.views.activate(view, current);
+        self.restriction = saved.restriction;
+        self.sync_restriction();
+        let pos = self.markers.position(saved.cursor, len);
+        self.markers.remove(saved.cursor);
+        self.set_cursor(pos);
+    }
+
+    /// Remove a view that is not active. The id must not be used afterwards.
+    pub fn remove_view(&mut self, view: ViewId) {
+        let saved = self.views.remove(view);
+        self.markers.remove(saved.cursor);
+        if let Some((begv, zv)) = saved.restriction {
+            self.markers.remove(begv);
+            self.markers.remove(zv);
+        }
+    }
+
+    /// Whether the text has been edited since it was last marked unmodified.
+    pub fn is_modified(&self) -> bool {
+        self.modified_tick > self.save_tick
+    }
+
+    /// Mark the text as modified or unmodified. An unmodified buffer becomes
+    /// modified again with the next edit.
+    pub fn set_modified(&mut self, modified: bool) {
+        if !modified {
+            self.save_tick = self.modified_tick;
+        } else if !self.is_modified() {
+            self.modified_tick += 1;
+        }
+    }
+
+    /// Start recording edits so that they can be undone. Does nothing if undo
+    /// is already enabled.
+    pub fn enable_undo(&mut self) {
+        self.history.get_or_insert_with(History::default);
+    }
+
+    /// Stop recording edits and discard the current history.
+    pub fn disable_undo(&mut self) {
+        self.history = None;
+    }
+
+    /// The edit history, or `None` if undo is not enabled.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Mark the end of a group of edits. The next call to [`Buffer::undo`] will
+    /// undo all edits since the previous boundary.
+    pub fn undo_boundary(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.boundary();
+        }
+    }
+
+    /// Undo the most recent group of edits and restore the cursor to where it
+    /// was before them. Returns the char range that was changed, or `None` if
+    /// there is nothing to undo.
+    pub fn undo(&mut self) -> Option<Range<usize>> {
+        let mut history = self.history.take()?;
+        let group = history.pop_undo();
+        let span = group.as_ref().map(|group| self.unrestricted(|this| this.revert(group)));
+        if let Some(group) = group {
+            history.push_redo(group);
+        }
+        self.history = Some(history);
+        span
+    }
+
+    /// Redo the most recently undone group of edits. Returns the char range
+    /// that was changed, or `None` if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<Range<usize>> {
+        let mut history = self.history.take()?;
+        let group = history.pop_redo();
+        let span = group.as_ref().map(|group| self.unrestricted(|this| this.replay(group)));
+        if let Some(group) = group {
+            history.push_undo(group);
+        }
+        self.history = Some(history);
+        span
+    }
+
+    /// Reverse the edits in `group`, latest first.
+    fn revert(&mut self, group: &[Edit]) -> Range<usize> {
+        let mut span = None;
+        for edit in group.iter().rev() {
+            match edit {
+                Edit::Insert { pos, text } => {
+                    let len = encoding::count_chars(text);
+                    self.delete_range(*pos, pos + len);
+                    span = Some(history::extend_span(span, *pos, len, 0));
+                }
+                Edit::Delete { pos, text } => {
+                    self.set_cursor(*pos);
+                    self.insert_internal(text);
+                    span = Some(history::extend_span(span, *pos, 0, encoding::count_chars(text)));
+                }
+                Edit::Cursor(pos) => self.set_cursor(*pos),
+                Edit::Boundary => {}
+            }
+        }
+        span.unwrap_or(self.cursor.chars..self.cursor.chars)
+    }
+
+    /// Apply the edits in `group` again, earliest first.
+    fn replay(&mut self, group: &[Edit]) -> Range<usize> {
+        let mut span = None;
+        for edit in group {
+            match edit {
+                Edit::Insert { pos, text } => {
+                    self.set_cursor(*pos);
+                    self.insert_internal(text);
+                    span = Some(history::extend_span(span, *pos, 0, encoding::count_chars(text)));
+                }
+                Edit::Delete { pos, text } => {
+                    let len = encoding::count_chars(text);
+                    self.delete_range(*pos, pos + len);
+                    span = Some(history::extend_span(span, *pos, len, 0));
+                }
+                Edit::Cursor(pos) => self.set_cursor(*pos),
+                Edit::Boundary => {}
+            }
+        }
+        span.unwrap_or(self.cursor.chars..self.cursor.chars)
+    }
+
+    /// Create a marker at char position `pos`. The marker will be adjusted as
+    /// text is inserted and deleted.
+    pub fn create_marker(&mut self, pos: usize, kind: InsertionType) -> MarkerId {
+        self.markers.create(pos, kind, self.text.total.chars)
+    }
+
+    /// Remove a marker from the buffer. The id must not be used afterwards.
+    pub fn remove_marker(&mut self, marker: MarkerId) {
+        self.markers.remove(marker);
+    }
+
+    /// The char position of `marker`.
+    pub fn marker_position(&self, marker: MarkerId) -> usize {
+        self.markers.position(marker, self.text.total.chars)
+    }
+
+    /// Move `marker` to char position `pos`.
+    pub fn set_marker(&mut self, marker: MarkerId, pos: usize) {
+        self.markers.set_position(marker, pos, self.text.total.chars);
+    }
+
+    pub fn marker_insertion_type(&self, marker: MarkerId) -> InsertionType {
+        self.markers.insertion_type(marker)
+    }
+
+    pub fn set_marker_insertion_type(&mut self, marker: MarkerId, kind: InsertionType) {
+        self.markers.set_insertion_type(marker, kind);
+    }
+
+    /// Create an overlay over the chars in `range`. `front` and `rear` control
+    /// whether the start and end advance when text is inserted at them.
+    pub fn create_overlay(
+        &mut self,
+        range: Range<usize>,
+        front: InsertionType,
+        rear: InsertionType,
+    ) -> OverlayId {
+        self.overlays.create(range, front, rear, self.text.total.chars)
+    }
+
+    /// Remove an overlay from the buffer. The id must not be used afterwards.
+    pub fn remove_overlay(&mut self, overlay: OverlayId) {
+        self.overlays.remove(overlay);
+    }
+
+    /// The char range covered by `overlay`.
+    pub fn overlay_range(&self, overlay: OverlayId) -> Range<usize> {
+        self.overlays.range(overlay)
+    }
+
+    /// Move `overlay` to cover the chars in `range`.
+    pub fn move_overlay(&mut self, overlay: OverlayId, range: Range<usize>) {
+        self.overlays.set_range(overlay, range, self.text.total.chars);
+    }
+
+    pub fn overlay_priority(&self, overlay: OverlayId) -> i64 {
+        self.overlays.priority(overlay)
+    }
+
+    pub fn set_overlay_priority(&mut self, overlay: OverlayId, priority: i64) {
+        self.overlays.set_priority(overlay, priority);
+    }
+
+    /// The insertion types of the start and end of `overlay`.
+    pub fn overlay_insertion_types(&self, overlay: OverlayId) -> (InsertionType, InsertionType) {
+        self.overlays.insertion_types(overlay)
+    }
+
+    /// The overlays that contain the char at `pos`, highest priority first.
+    pub fn overlays_at(&self, pos: usize) -> Vec<OverlayId> {
+        self.overlays.at(pos)
+    }
+
+    /// The overlays that overlap the chars in `range`, highest priority first.
+    /// Empty overlays are included if they are at the start of `range`, inside
+    /// it, or at its end when that is the end of the buffer.
+    pub fn overlays_in(&self, range: Range<usize>) -> Vec<OverlayId> {
+        let at_end = range.end >= self.text.total.chars;
+        self.overlays.overlapping(range, at_end)
+    }
+
+    /// The first position after `pos` where an overlay starts or ends.
+    pub fn next_overlay_change(&self, pos: usize) -> Option<usize> {
+        self.overlays.next_change(pos)
+    }
+
+    /// The text properties of the buffer. They are adjusted as text is
+    /// inserted and deleted.
+    pub fn text_properties(&self) -> &TextProperties<Property, Property> {
+        &self.properties
+    }
+
+    /// Set `key` to `value` for the chars in `range`.
+    pub fn put_text_property(&mut self, range: Range<usize>, key: Property, value: Property) {
+        self.properties.put_property(range, &key, &value);
+    }
+
+    /// Remove `key` from the chars in `range`.
+    pub fn remove_text_property(&mut self, range: Range<usize>, key: Property) {
+        self.properties.remove_property(range, &key);
     }
 
+    /// Replace the text properties of the chars in `range` with `props`.
+    pub fn set_text_properties(
+        &mut self,
+        range: Range<usize>,
+        props: &PropertyList<Property, Property>,
+    ) {
+        self.properties.set_properties(range, props);
+    }
+
+    /// Set whether `key` is inherited by [`Buffer::insert_and_inherit`].
+    pub fn set_property_stickiness(&mut self, key: Property, stickiness: Stickiness) {
+        self.properties.set_stickiness(key, stickiness);
+    }
+}
+
+impl Snapshot {
     fn to_abs_pos(&self, pos: Metric) -> Metric {
-        let chars = pos.chars;
         let bytes = if pos.bytes < self.gap_start {
             pos.bytes
         } else if pos.bytes >= self.gap_end {
@@ -420,11 +1010,10 @@ impl Buffer {
         } else {
             unreachable!()
         };
-        Metric { bytes, chars }
+        Metric { bytes, ..pos }
     }
 
     fn to_gapped_pos(&self, pos: Metric) -> Metric {
-        let chars = pos.chars;
         let bytes = if pos.bytes < self.gap_start {
             pos.bytes
         } else if pos.bytes >= self.gap_start {
@@ -432,7 +1021,7 @@ impl Buffer {
         } else {
             unreachable!()
         };
-        Metric { bytes, chars }
+        Metric { bytes, ..pos }
     }
 
     pub fn len(&self) -> usize {
@@ -448,52 +1037,269 @@ impl Buffer {
         self.total.chars == 0
     }
 
+    /// The char position of the start of the accessible region. This is 0
+    /// unless the buffer is narrowed.
+    pub fn begv(&self) -> usize {
+        self.restriction.map_or(0, |(beg, _)| beg)
+    }
+
+    /// The char position of the end of the accessible region. This is the
+    /// length of the buffer unless it is narrowed.
+    pub fn zv(&self) -> usize {
+        self.restriction.map_or(self.total.chars, |(_, end)| end)
+    }
+
+    pub const fn is_narrowed(&self) -> bool {
+        self.restriction.is_some()
+    }
+
+    /// Clamp the char position `pos` to the accessible region.
+    pub(crate) fn clamp(&self, pos: usize) -> usize {
+        pos.clamp(self.begv(), self.zv())
+    }
+
+    /// The byte bounds of the accessible region.
+    pub(crate) fn byte_bounds(&self) -> (usize, usize) {
+        match self.restriction {
+            Some((beg, end)) => (self.char_to_byte(beg), self.char_to_byte(end)),
+            None => (0, self.len()),
+        }
+    }
+
+    fn check_char_pos(&self, pos: usize) -> Result<(), BufferError> {
+        let (start, end) = (self.begv(), self.zv());
+        if pos < start || pos > end {
+            return Err(BufferError::OutOfRange { pos, start, end });
+        }
+        Ok(())
+    }
+
+    /// Check that the char range `beg..end` is ordered and inside the
+    /// accessible region.
+    pub(crate) fn check_char_range(&self, beg: usize, end: usize) -> Result<(), BufferError> {
+        if beg > end {
+            return Err(BufferError::Inverted { start: beg, end });
+        }
+        self.check_char_pos(beg)?;
+        self.check_char_pos(end)
+    }
+
+    /// Check that the byte `range` is ordered, inside the accessible region
+    /// and on char boundaries.
+    fn check_byte_range(&self, range: &Range<usize>) -> Result<(), BufferError> {
+        if range.start > range.end {
+            return Err(BufferError::Inverted { start: range.start, end: range.end });
+        }
+        let (start, end) = self.byte_bounds();
+        for pos in [range.start, range.end] {
+            if pos < start || pos > end {
+                return Err(BufferError::OutOfRange { pos, start, end });
+            }
+            let idx = if pos < self.gap_start { pos } else { pos + self.gap_len() };
+            if !self.is_char_boundary(idx) {
+                return Err(BufferError::NotCharBoundary(pos));
+            }
+        }
+        Ok(())
+    }
+
     const fn gap_len(&self) -> usize {
         self.gap_end - self.gap_start
     }
 
-    fn char_to_byte(&self, pos: usize) -> usize {
-        if pos == 0 {
-            return if self.gap_start == 0 { self.gap_end } else { 0 };
+    /// The number of lines in the buffer. This is always one more than the
+    /// number of newlines.
+    pub const fn len_lines(&self) -> usize {
+        self.total.lines + 1
+    }
+
+    /// Return the line (zero based) that contains the char at `pos`.
+    pub fn char_to_line(&self, pos: usize) -> usize {
+        self.metric_at(pos, Unit::Chars).lines
+    }
+
+    /// Return the char position of the start of `line` (zero based). If
+    /// `line` is past the last line, the end of the buffer is returned.
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.line_to_metric(line).chars
+    }
+
+    /// Return the char position of the beginning of the line containing `pos`.
+    pub fn line_start(&self, pos: usize) -> usize {
+        self.line_to_char(self.char_to_line(pos))
+    }
+
+    /// Return the char position of the end of the line containing `pos`. This
+    /// is the position of the newline, or the end of the buffer if `pos` is
+    /// on the last line.
+    pub fn line_end(&self, pos: usize) -> usize {
+        let line = self.char_to_line(pos);
+        if line == self.total.lines {
+            self.total.chars
+        } else {
+            self.line_to_char(line + 1) - 1
         }
-        if pos == self.total.chars {
-            return self.data.len();
+    }
+
+    /// Convert a char position into a byte position. Positions past the end of
+    /// the buffer are clamped to the end.
+    pub fn char_to_byte(&self, pos: usize) -> usize {
+        self.metric_at(pos, Unit::Chars).bytes
+    }
+
+    /// Convert a byte position into a char position. If `pos` is not on a char
+    /// boundary, the position of the char containing it is returned.
+    pub fn byte_to_char(&self, pos: usize) -> usize {
+        self.metric_at(pos, Unit::Bytes).chars
+    }
+
+    /// The length of the buffer in UTF-16 code units.
+    pub const fn len_utf16(&self) -> usize {
+        self.total.utf16
+    }
+
+    /// Convert a char position into a UTF-16 code unit offset.
+    pub fn char_to_utf16(&self, pos: usize) -> usize {
+        self.metric_at(pos, Unit::Chars).utf16
+    }
+
+    /// Convert a UTF-16 code unit offset into a char position. If `pos` is in
+    /// the middle of a surrogate pair, the position of that char is returned.
+    pub fn utf16_to_char(&self, pos: usize) -> usize {
+        self.metric_at(pos, Unit::Utf16).chars
+    }
+
+    /// Convert a char position into a `(line, column)` pair, where the column
+    /// is measured in UTF-16 code units. This is the position format used by
+    /// the language server protocol.
+    pub fn char_to_line_utf16(&self, pos: usize) -> (usize, usize) {
+        self.line_utf16_pair(self.metric_at(pos, Unit::Chars))
+    }
+
+    /// Convert a `(line, column)` pair with the column in UTF-16 code units
+    /// into a char position. Columns past the end of the line are clamped to
+    /// the end of the line.
+    pub fn line_utf16_to_char(&self, line: usize, column: usize) -> usize {
+        self.line_utf16_to_metric(line, column).chars
+    }
+
+    /// Convert a byte position into a `(line, column)` pair, where the column
+    /// is measured in UTF-16 code units. If `pos` is not on a char boundary,
+    /// the position of the char containing it is used.
+    pub fn byte_to_line_utf16(&self, pos: usize) -> (usize, usize) {
+        self.line_utf16_pair(self.metric_at(pos, Unit::Bytes))
+    }
+
+    /// Convert a `(line, column)` pair with the column in UTF-16 code units
+    /// into a byte position. Columns past the end of the line are clamped to
+    /// the end of the line.
+    pub fn line_utf16_to_byte(&self, line: usize, column: usize) -> usize {
+        self.line_utf16_to_metric(line, column).bytes
+    }
+
+    fn line_utf16_pair(&self, pos: Metric) -> (usize, usize) {
+        let line_start = self.line_to_metric(pos.lines);
+        (pos.lines, pos.utf16 - line_start.utf16)
+    }
+
+    fn line_utf16_to_metric(&self, line: usize, column: usize) -> Metric {
+        let start = self.line_to_metric(line);
+        let line_end = if line >= self.total.lines {
+            self.total.utf16
+        } else {
+            // don't go past the newline
+            self.line_to_metric(line + 1).utf16 - 1
+        };
+        let target = (start.utf16 + column).min(line_end);
+        self.metric_at(target, Unit::Utf16)
+    }
+
+    /// Return the metric at the start of `line`. If `line` is past the last
+    /// line, the metric of the whole buffer is returned.
+    fn line_to_metric(&self, line: usize) -> Metric {
+        if line == 0 {
+            return Metric::default();
         }
-        if pos == self.gap_chars {
-            return self.gap_end;
+        if line > self.total.lines {
+            return self.total;
         }
-        let (base, offset) = self.metrics.search_char(pos);
-        debug_assert_eq!(base.chars + offset, pos);
+        // The line starts right after the newline that ends the previous line
+        let (start, chunk, offset) = self.metrics.search(line - 1, Unit::Lines);
+        let pieces = self.pieces(start.bytes..start.bytes + chunk.bytes);
+        start + prefix_metrics(pieces, offset + 1, Unit::Lines)
+    }
 
-        let base = self.to_gapped_pos(base);
+    /// Convert a char position into a metric. The bytes of the returned metric
+    /// are an index into the gapped data.
+    fn char_to_metric(&self, pos: usize) -> Metric {
+        let metric = self.to_gapped_pos(self.metric_at(pos, Unit::Chars));
+        self.assert_char_boundary(metric.bytes);
+        metric
+    }
 
+    /// Return the metric at position `pos` measured in `unit`. Positions past
+    /// the end of the buffer return the metric of the whole buffer.
+    fn metric_at(&self, pos: usize, unit: Unit) -> Metric {
+        if pos == 0 {
+            return Metric::default();
+        }
+        if pos >= unit.of(&self.total) {
+            return self.total;
+        }
+        let (start, chunk, offset) = self.metrics.search(pos, unit);
         if offset == 0 {
-            return base.bytes;
+            return start;
         }
+        let prefix = if chunk.is_ascii() {
+            // ascii text only needs to count the newlines
+            let pieces = self.pieces(start.bytes..start.bytes + offset);
+            let lines = pieces.map(encoding::count_breaks).sum();
+            Metric { bytes: offset, chars: offset, lines, utf16: offset }
+        } else {
+            let pieces = self.pieces(start.bytes..start.bytes + chunk.bytes);
+            prefix_metrics(pieces, offset, unit)
+        };
+        start + prefix
+    }
 
-        self.assert_char_boundary(base.bytes);
-
-        if base.chars < self.gap_chars {
-            if pos < self.gap_chars {
-                let string = self.to_str(base.bytes..self.gap_start);
-                chars::to_byte_idx(string, offset) + base.bytes
-            } else {
-                // the char crosses the gap
-                let string = self.to_str(self.gap_end..);
-                self.gap_end + chars::to_byte_idx(string, pos - self.gap_chars)
-            }
+    /// Return an iterator over the text in the absolute byte `range` as
+    /// borrowed slices. The slices are split at the gap and at the boundaries
+    /// of the chunks the text is stored in, and never split a char.
+    pub(crate) fn pieces(&self, range: Range<usize>) -> Pieces<'_> {
+        let gap = self.gap_len();
+        if range.end <= self.gap_start {
+            self.data.pieces(range)
+        } else if range.start >= self.gap_start {
+            self.data.pieces(range.start + gap..range.end + gap)
         } else {
-            let string = self.to_str(base.bytes..);
-            chars::to_byte_idx(string, offset) + base.bytes
+            let front = range.start..self.gap_start;
+            self.data.pieces_around_gap(front, self.gap_end..range.end + gap)
         }
     }
 
-    fn to_str(&self, range: impl std::slice::SliceIndex<[u8], Output = [u8]>) -> &str {
-        // TODO: remove this check once we are confident the code is correct
-        std::str::from_utf8(&self.data[range]).unwrap()
+    /// Read the text in the byte `range` as a string. This will only copy the
+    /// text if the range spans the gap or the boundary between two chunks of
+    /// storage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text contains chars that are not Unicode scalar
+    /// values, like raw bytes.
+    pub fn read(&self, byte_range: Range<usize>) -> Result<Cow<'_, str>, DecodeError> {
+        encoding::into_str(self.read_bytes(byte_range))
     }
 
-    pub fn read(&self, byte_range: Range<usize>) -> Cow<'_, str> {
+    /// Read the text in the byte `range` in the internal encoding, which is
+    /// UTF-8 extended to hold every char a buffer can. This never fails, and
+    /// will only copy the text if the range spans the gap or the boundary
+    /// between two chunks of storage.
+    pub fn read_bytes(&self, byte_range: Range<usize>) -> Cow<'_, [u8]> {
+        let byte_range = if self.is_narrowed() {
+            let (beg, end) = self.byte_bounds();
+            byte_range.start.clamp(beg, end)..byte_range.end.clamp(beg, end)
+        } else {
+            byte_range
+        };
         // if past gap_start, add gap_len to range
         let mut range = byte_range.clone();
         if range.start >= self.gap_start {
@@ -504,13 +1310,13 @@ impl Buffer {
         }
         assert!(range.end <= self.data.len(), "range end out of bounds");
         assert!(range.start <= self.data.len(), "range start out of bounds");
-        for i in 0..4 {
+        for i in 0..5 {
             if self.is_char_boundary(range.end - i) {
                 range.end -= i;
                 break;
             }
         }
-        for i in 0..4 {
+        for i in 0..5 {
             if self.is_char_boundary(range.start + i) {
                 range.start += i;
                 break;
@@ -520,18 +1326,93 @@ impl Buffer {
         assert!(range.start >= self.gap_end || range.start < self.gap_start);
         assert!(range.end >= self.gap_end || range.end < self.gap_start);
 
-        // the range straddles the gap, so we need to copy the two halves
-        if range.start < self.gap_start && self.gap_start < range.end {
-            let mut string = String::with_capacity(range.len());
-            string.push_str(self.to_str(range.start..self.gap_start));
-            string.push_str(self.to_str(self.gap_end..range.end));
-            assert_eq!(string.len(), byte_range.len());
-            Cow::Owned(string)
+        let mut pieces = if range.start < self.gap_start && self.gap_start < range.end {
+            self.data
+                .pieces_around_gap(range.start..self.gap_start, self.gap_end..range.end)
         } else {
-            Cow::Borrowed(self.to_str(range))
+            self.data.pieces(range)
+        };
+        match (pieces.next(), pieces.next()) {
+            (None, _) => Cow::Borrowed(&[]),
+            (Some(piece), None) => Cow::Borrowed(piece),
+            // the range is split into several pieces, so we need to copy them
+            (Some(first), Some(second)) => {
+                let bytes = [first, second].into_iter().chain(pieces).collect::<Vec<_>>().concat();
+                assert_eq!(bytes.len(), byte_range.len());
+                Cow::Owned(bytes)
+            }
+        }
+    }
+
+    /// Read the text in the byte `range` as the bytes it was created from.
+    /// Raw byte chars are converted back to the byte they represent, so text
+    /// inserted with [`Buffer::insert_bytes`] round-trips unchanged.
+    pub fn read_binary(&self, byte_range: Range<usize>) -> Cow<'_, [u8]> {
+        match self.read_bytes(byte_range) {
+            Cow::Borrowed(bytes) => encoding::to_binary(bytes),
+            Cow::Owned(bytes) => Cow::Owned(encoding::to_binary(&bytes).into_owned()),
         }
     }
 
+    /// Read the text in the char `range` as a string. Like [`Snapshot::read`]
+    /// this will only copy the text if the range is split in storage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text contains chars that are not Unicode scalar
+    /// values, like raw bytes.
+    pub fn read_chars(&self, range: Range<usize>) -> Result<Cow<'_, str>, DecodeError> {
+        self.read(self.char_to_byte(range.start)..self.char_to_byte(range.end))
+    }
+
+    /// Like [`Snapshot::read`], but `byte_range` must be ordered, inside the
+    /// accessible region and on char boundaries instead of being fixed up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range is not valid or the text can not be
+    /// decoded as a string.
+    pub fn try_read(&self, byte_range: Range<usize>) -> Result<Cow<'_, str>, BufferError> {
+        self.check_byte_range(&byte_range)?;
+        Ok(self.read(byte_range)?)
+    }
+
+    /// Like [`Snapshot::read_chars`], but `range` must be ordered and inside
+    /// the accessible region instead of being clamped to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range is not valid or the text can not be
+    /// decoded as a string.
+    pub fn try_read_chars(&self, range: Range<usize>) -> Result<Cow<'_, str>, BufferError> {
+        self.check_char_range(range.start, range.end)?;
+        Ok(self.read_chars(range)?)
+    }
+
+    /// Return an iterator over the text in the char `range` as borrowed
+    /// slices in the internal encoding. The text is split at the gap and at
+    /// the boundaries of the chunks it is stored in, but a char is never split
+    /// between two slices.
+    pub fn chunks(&self, range: Range<usize>) -> Chunks<'_> {
+        let (start, end) = (self.clamp(range.start), self.clamp(range.end));
+        Chunks { pieces: self.pieces(self.char_to_byte(start)..self.char_to_byte(end)) }
+    }
+
+    /// Return a cursor over the chars of the buffer starting at char position
+    /// `pos`. [`Iterator::next`] returns the char after the cursor and
+    /// [`Chars::prev`] returns the char before it.
+    pub fn chars_at(&self, pos: usize) -> Chars<'_> {
+        Chars { bytes: self.bytes_at(self.char_to_byte(self.clamp(pos))) }
+    }
+
+    /// Return a cursor over the bytes of the buffer starting at byte position
+    /// `pos`. [`Iterator::next`] returns the byte after the cursor and
+    /// [`Bytes::prev`] returns the byte before it.
+    pub fn bytes_at(&self, pos: usize) -> Bytes<'_> {
+        let (start, end) = self.byte_bounds();
+        Bytes { text: self, pos: pos.clamp(start, end), start, end }
+    }
+
     fn assert_char_boundary(&self, pos: usize) {
         if cfg!(debug_assertions) {
             if pos == self.gap_start {
@@ -543,21 +1424,194 @@ impl Buffer {
 
     fn is_char_boundary(&self, pos: usize) -> bool {
         match self.data.get(pos) {
-            Some(byte) => is_char_boundary(*byte),
+            Some(byte) => is_char_boundary(byte),
             None => pos == self.data.len(),
         }
     }
 }
 
-fn metrics(slice: &str) -> Metric {
-    let chars = chars::count(slice);
-    Metric { bytes: slice.len(), chars }
+/// An iterator over borrowed slices of the buffer text. Created by
+/// [`Buffer::chunks`].
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    pieces: Pieces<'a>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pieces.next()
+    }
+}
+
+impl DoubleEndedIterator for Chunks<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.pieces.next_back()
+    }
+}
+
+/// A cursor over the bytes of the buffer that can move in both directions.
+/// Created by [`Snapshot::bytes_at`].
+#[derive(Debug, Clone)]
+pub struct Bytes<'a> {
+    text: &'a Snapshot,
+    /// The byte position of the cursor, ignoring the gap
+    pos: usize,
+    /// The byte bounds of the accessible region, ignoring the gap. `end`
+    /// moves backwards as bytes are taken from the back.
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Bytes<'a> {
+    /// The byte position of the cursor.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Return the byte before the cursor and move the cursor backwards.
+    pub fn prev(&mut self) -> Option<u8> {
+        let byte = self.slice_before()?.last().copied();
+        self.pos -= 1;
+        byte
+    }
+
+    /// Return the slice that contains the byte after the cursor, starting at
+    /// that byte.
+    fn slice_after(&self) -> Option<&'a [u8]> {
+        self.text.pieces(self.pos..self.end).next()
+    }
+
+    /// Return the slice that contains the byte before the cursor, ending at
+    /// that byte.
+    fn slice_before(&self) -> Option<&'a [u8]> {
+        self.slice_ending_at(self.start, self.pos)
+    }
+
+    /// Return the slice that contains the byte before `end`, ending at that
+    /// byte and starting no earlier than `start`.
+    fn slice_ending_at(&self, start: usize, end: usize) -> Option<&'a [u8]> {
+        self.text.pieces(start..end).next_back()
+    }
+}
+
+impl Iterator for Bytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = self.slice_after()?[0];
+        self.pos += 1;
+        Some(byte)
+    }
+}
+
+impl DoubleEndedIterator for Bytes<'_> {
+    /// Return the last byte of the accessible region that has not been
+    /// returned yet. The cursor stays where it is.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let byte = self.slice_ending_at(self.pos, self.end)?.last().copied();
+        self.end -= 1;
+        byte
+    }
+}
+
+/// A cursor over the chars of the buffer that can move in both directions.
+/// Created by [`Snapshot::chars_at`]. Chars are returned as their code because
+/// a buffer can hold chars that are not Unicode scalar values; use
+/// [`char::from_u32`] to convert them.
+#[derive(Debug, Clone)]
+pub struct Chars<'a> {
+    bytes: Bytes<'a>,
+}
+
+impl Chars<'_> {
+    /// The byte position of the cursor.
+    pub fn byte_pos(&self) -> usize {
+        self.bytes.pos
+    }
+
+    /// Return the char before the cursor and move the cursor backwards.
+    pub fn prev(&mut self) -> Option<u32> {
+        let (width, chr) = last_char(self.bytes.slice_before()?);
+        self.bytes.pos -= width;
+        Some(chr)
+    }
+}
+
+impl Iterator for Chars<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slice = self.bytes.slice_after()?;
+        let width = encoding::char_width(slice[0]);
+        self.bytes.pos += width;
+        Some(encoding::decode_char(&slice[..width]))
+    }
+}
+
+impl DoubleEndedIterator for Chars<'_> {
+    /// Return the last char of the accessible region that has not been
+    /// returned yet. The cursor stays where it is.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let bytes = &mut self.bytes;
+        let (width, chr) = last_char(bytes.slice_ending_at(bytes.pos, bytes.end)?);
+        bytes.end -= width;
+        Some(chr)
+    }
+}
+
+/// Decode the char at the end of `slice`, returning its width in bytes and its
+/// code.
+fn last_char(slice: &[u8]) -> (usize, u32) {
+    let start = (0..slice.len()).rev().find(|i| is_char_boundary(slice[*i])).unwrap();
+    (slice.len() - start, encoding::decode_char(&slice[start..]))
+}
+
+fn metrics(slice: &[u8]) -> Metric {
+    let chars = encoding::count_chars(slice);
+    let lines = encoding::count_breaks(slice);
+    let utf16 = encoding::count_utf16(slice);
+    Metric { bytes: slice.len(), chars, lines, utf16 }
 }
 
-#[allow(clippy::cast_possible_wrap)]
-const fn is_char_boundary(byte: u8) -> bool {
-    // This is bit magic equivalent to: b < 128 || b >= 192
-    (byte as i8) >= -0x40
+impl Unit {
+    /// Convert a position in this unit to a byte index in `text`. Positions
+    /// that fall inside a char are rounded down to the start of that char.
+    fn to_byte_idx(self, text: &[u8], pos: usize) -> usize {
+        match self {
+            Unit::Bytes => {
+                let mut idx = pos.min(text.len());
+                while idx < text.len() && !is_char_boundary(text[idx]) {
+                    idx -= 1;
+                }
+                idx
+            }
+            Unit::Chars => encoding::char_to_byte_idx(text, pos),
+            Unit::Lines => encoding::line_to_byte_idx(text, pos),
+            Unit::Utf16 => encoding::utf16_to_byte_idx(text, pos),
+        }
+    }
+}
+
+/// Calculate the metrics of the text split between `pieces` up to position
+/// `pos` measured in `unit`.
+fn prefix_metrics<'a>(
+    pieces: impl Iterator<Item = &'a [u8]>,
+    mut pos: usize,
+    unit: Unit,
+) -> Metric {
+    let mut prefix = Metric::default();
+    for piece in pieces {
+        let metric = metrics(piece);
+        let len = unit.of(&metric);
+        if pos <= len {
+            return prefix + metrics(&piece[..unit.to_byte_idx(piece, pos)]);
+        }
+        prefix += metric;
+        pos -= len;
+    }
+    prefix
 }
 
 #[cfg(test)]
@@ -639,7 +1693,7 @@ mod test {
         assert_eq!(buffer.gap_end, hello.len() + Buffer::GAP_SIZE);
         buffer.move_gap_out_of(..);
         buffer.move_gap_out_of(..);
-        buffer.move_gap(Metric { bytes: buffer.char_to_byte(7), chars: 7 });
+        buffer.move_gap(buffer.char_to_metric(7));
         buffer.move_gap_out_of(..);
         assert_eq!(buffer, "heworld");
     }
@@ -754,10 +1808,218 @@ mod test {
     fn test_read() {
         let mut buffer = Buffer::from("hello world");
         buffer.set_cursor(5);
-        assert_eq!(buffer.read(0..0), Cow::Borrowed(""));
-        assert_eq!(buffer.read(0..5), Cow::Borrowed("hello"));
-        assert_eq!(buffer.read(5..11), Cow::Borrowed(" world"));
-        assert_eq!(buffer.read(4..6), Cow::<str>::Owned(String::from("o ")));
+        assert_eq!(buffer.read(0..0), Ok(Cow::Borrowed("")));
+        assert_eq!(buffer.read(0..5), Ok(Cow::Borrowed("hello")));
+        assert_eq!(buffer.read(5..11), Ok(Cow::Borrowed(" world")));
+        assert_eq!(buffer.read(4..6), Ok(Cow::<str>::Owned(String::from("o "))));
+    }
+
+    #[test]
+    fn test_raw_bytes() {
+        let mut buffer = Buffer::from(&b"a\xFF\nb"[..]);
+        buffer.enable_undo();
+        assert_eq!(buffer.len_chars(), 4);
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(buffer.len_lines(), 2);
+        assert_eq!(buffer.len_utf16(), 4);
+        assert_eq!(buffer.chars_at(1).next(), Some(crate::raw_byte_to_char(0xFF)));
+        assert_eq!(buffer.read_chars(1..2).unwrap_err().valid_up_to(), 0);
+        assert_eq!(buffer.read_chars(2..4).unwrap(), "\nb");
+        buffer.set_cursor(4);
+        buffer.insert_char_code(0x11_0000);
+        buffer.insert_char_code(crate::MAX_CHAR);
+        assert_eq!(buffer.len_chars(), 6);
+        assert_eq!(buffer.char_to_byte(5), 9);
+        assert_eq!(buffer.chars_at(4).next(), Some(0x11_0000));
+        assert_eq!(buffer.to_string(), "a\\377\nb\u{FFFD}\\377");
+        assert_eq!(buffer.read_binary(0..buffer.len()), &b"a\xFF\nb\xF4\x90\x80\x80\xFF"[..]);
+        buffer.delete_range(1, 5);
+        assert_eq!(buffer.read_binary(0..buffer.len()), &b"a\xFF"[..]);
+        buffer.undo();
+        assert_eq!(buffer.len_chars(), 4);
+        assert_eq!(buffer.read_binary(0..buffer.len()), &b"a\xFF\nb"[..]);
+    }
+
+    #[test]
+    fn test_try() {
+        let mut buffer = Buffer::from("aΘbc");
+        buffer.narrow(1..3);
+        let out_of_range = |pos| Err(BufferError::OutOfRange { pos, start: 1, end: 3 });
+        assert_eq!(buffer.try_set_cursor(4), out_of_range(4));
+        assert_eq!(buffer.try_set_cursor(0), out_of_range(0));
+        assert_eq!(buffer.try_set_cursor(3), Ok(()));
+        assert_eq!(buffer.cursor(), 3);
+        assert_eq!(buffer.try_delete_range(2, 1), Err(BufferError::Inverted { start: 2, end: 1 }));
+        assert_eq!(buffer.try_delete_range(2, 4), out_of_range(4));
+        assert_eq!(buffer, "aΘbc");
+        assert_eq!(buffer.try_read_chars(1..3).unwrap(), "Θb");
+        let err = buffer.try_read_chars(0..3).unwrap_err();
+        assert_eq!(err, BufferError::OutOfRange { pos: 0, start: 1, end: 3 });
+        assert_eq!(buffer.try_read(1..4).unwrap(), "Θb");
+        assert_eq!(buffer.try_read(2..4), Err(BufferError::NotCharBoundary(2)));
+        assert_eq!(
+            buffer.try_read(1..5),
+            Err(BufferError::OutOfRange { pos: 5, start: 1, end: 4 })
+        );
+        buffer.widen();
+        buffer.set_cursor(4);
+        buffer.insert_bytes(b"\xFF");
+        assert!(matches!(buffer.try_read_chars(3..5), Err(BufferError::Decode(_))));
+        assert_eq!(buffer.try_delete_range(1, 3), Ok(()));
+        assert_eq!(buffer.try_read_chars(0..2).unwrap(), "ac");
+    }
+
+    #[test]
+    fn test_byte_char_conversion() {
+        let mut buffer = Buffer::from("aΘb😀c");
+        assert_eq!(buffer.char_to_byte(0), 0);
+        assert_eq!(buffer.char_to_byte(2), 3);
+        assert_eq!(buffer.char_to_byte(4), 8);
+        assert_eq!(buffer.char_to_byte(5), 9);
+        assert_eq!(buffer.char_to_byte(100), 9);
+        assert_eq!(buffer.byte_to_char(3), 2);
+        // inside a multi-byte char
+        assert_eq!(buffer.byte_to_char(2), 1);
+        assert_eq!(buffer.byte_to_char(6), 3);
+        assert_eq!(buffer.byte_to_char(100), 5);
+
+        buffer.set_cursor(2);
+        buffer.insert("ƽ");
+        assert_eq!(buffer.char_to_byte(3), 5);
+        assert_eq!(buffer.byte_to_char(10), 5);
+        assert_eq!(buffer.read_chars(1..4).unwrap(), "Θƽb");
+        assert_eq!(buffer.read_chars(0..2).unwrap(), Cow::Borrowed("aΘ"));
+        assert_eq!(buffer.read_chars(4..6).unwrap(), "😀c");
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut buffer = Buffer::from("hello world");
+        buffer.set_cursor(5);
+        buffer.insert("Θ");
+        let chunks: Vec<_> = buffer.chunks(0..12).collect();
+        assert_eq!(chunks, ["helloΘ".as_bytes(), b" world"]);
+        let chunks: Vec<_> = buffer.chunks(0..12).rev().collect();
+        assert_eq!(chunks, [b" world", "helloΘ".as_bytes()]);
+        let chunks: Vec<_> = buffer.chunks(1..3).collect();
+        assert_eq!(chunks, [b"el"]);
+        let chunks: Vec<_> = buffer.chunks(7..9).collect();
+        assert_eq!(chunks, [b"wo"]);
+        assert_eq!(buffer.chunks(3..3).count(), 0);
+        assert_eq!(buffer.to_string(), "helloΘ world");
+    }
+
+    #[test]
+    fn test_chars_at() {
+        let mut buffer = Buffer::from("aΘ😀b");
+        buffer.set_cursor(2);
+        buffer.insert("ƽ");
+        let mut chars = buffer.chars_at(2);
+        assert_eq!(chars.next(), Some('ƽ'.into()));
+        assert_eq!(chars.next(), Some('😀'.into()));
+        assert_eq!(chars.next(), Some('b'.into()));
+        assert_eq!(chars.next(), None);
+        assert_eq!(chars.prev(), Some('b'.into()));
+        assert_eq!(chars.prev(), Some('😀'.into()));
+        assert_eq!(chars.prev(), Some('ƽ'.into()));
+        assert_eq!(chars.byte_pos(), 3);
+        assert_eq!(chars.prev(), Some('Θ'.into()));
+        assert_eq!(chars.prev(), Some('a'.into()));
+        assert_eq!(chars.prev(), None);
+        let chars: String = buffer.chars_at(0).map(|x| char::from_u32(x).unwrap()).collect();
+        assert_eq!(chars, "aΘƽ😀b");
+        let chars: String = buffer.chars_at(0).rev().map(|x| char::from_u32(x).unwrap()).collect();
+        assert_eq!(chars, "b😀ƽΘa");
+        // the two ends meet without crossing
+        let mut chars = buffer.chars_at(1);
+        assert_eq!(chars.next_back(), Some('b'.into()));
+        assert_eq!(chars.next(), Some('Θ'.into()));
+        assert_eq!(chars.next_back(), Some('😀'.into()));
+        assert_eq!(chars.next(), Some('ƽ'.into()));
+        assert_eq!(chars.next_back(), None);
+        assert_eq!(chars.next(), None);
+        assert_eq!(buffer.chars_at(100).prev(), Some('b'.into()));
+    }
+
+    #[test]
+    fn test_bytes_at() {
+        let mut buffer = Buffer::from("abcd");
+        buffer.set_cursor(2);
+        buffer.insert("x");
+        let mut bytes = buffer.bytes_at(2);
+        assert_eq!(bytes.next(), Some(b'x'));
+        assert_eq!(bytes.next(), Some(b'c'));
+        assert_eq!(bytes.pos(), 4);
+        assert_eq!(bytes.prev(), Some(b'c'));
+        assert_eq!(bytes.prev(), Some(b'x'));
+        assert_eq!(bytes.prev(), Some(b'b'));
+        assert_eq!(buffer.bytes_at(0).collect::<Vec<_>>(), b"abxcd");
+        assert_eq!(buffer.bytes_at(0).rev().collect::<Vec<_>>(), b"dcxba");
+        let mut bytes = buffer.bytes_at(4);
+        assert_eq!(bytes.next_back(), Some(b'd'));
+        assert_eq!(bytes.next_back(), None);
+        assert_eq!(bytes.prev(), Some(b'c'));
+        assert_eq!(buffer.bytes_at(5).next(), None);
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut buffer = Buffer::from("hello\nΘ world\n\nend");
+        assert_eq!(buffer.len_lines(), 4);
+        assert_eq!(buffer.char_to_line(0), 0);
+        assert_eq!(buffer.char_to_line(5), 0);
+        assert_eq!(buffer.char_to_line(6), 1);
+        assert_eq!(buffer.char_to_line(14), 2);
+        assert_eq!(buffer.char_to_line(100), 3);
+        assert_eq!(buffer.line_to_char(0), 0);
+        assert_eq!(buffer.line_to_char(1), 6);
+        assert_eq!(buffer.line_to_char(2), 14);
+        assert_eq!(buffer.line_to_char(3), 15);
+        assert_eq!(buffer.line_to_char(4), 18);
+        assert_eq!(buffer.line_start(10), 6);
+        assert_eq!(buffer.line_end(10), 13);
+        assert_eq!(buffer.line_end(16), 18);
+
+        buffer.set_cursor(8);
+        buffer.insert("\nnew\n");
+        assert_eq!(buffer, "hello\nΘ \nnew\nworld\n\nend");
+        assert_eq!(buffer.len_lines(), 6);
+        assert_eq!(buffer.line_to_char(2), 9);
+        assert_eq!(buffer.line_to_char(3), 13);
+        assert_eq!(buffer.char_to_line(12), 2);
+        buffer.delete_range(3, 10);
+        assert_eq!(buffer, "helew\nworld\n\nend");
+        assert_eq!(buffer.len_lines(), 4);
+        assert_eq!(buffer.line_to_char(1), 6);
+        assert_eq!(buffer.char_to_line(6), 1);
+    }
+
+    #[test]
+    fn test_utf16() {
+        let mut buffer = Buffer::from("a😀b\nΘ😀\nend");
+        assert_eq!(buffer.len_utf16(), 12);
+        assert_eq!(buffer.char_to_utf16(2), 3);
+        assert_eq!(buffer.utf16_to_char(3), 2);
+        // middle of a surrogate pair
+        assert_eq!(buffer.utf16_to_char(2), 1);
+        assert_eq!(buffer.char_to_line_utf16(3), (0, 4));
+        assert_eq!(buffer.char_to_line_utf16(6), (1, 3));
+        assert_eq!(buffer.char_to_line_utf16(7), (2, 0));
+        assert_eq!(buffer.line_utf16_to_char(1, 3), 6);
+        assert_eq!(buffer.line_utf16_to_char(1, 100), 6);
+        assert_eq!(buffer.line_utf16_to_char(2, 100), 10);
+        assert_eq!(buffer.byte_to_line_utf16(7), (1, 0));
+        assert_eq!(buffer.byte_to_line_utf16(11), (1, 1));
+        assert_eq!(buffer.line_utf16_to_byte(1, 1), 9);
+        assert_eq!(buffer.line_utf16_to_byte(1, 3), 13);
+
+        buffer.set_cursor(1);
+        buffer.insert("😀");
+        assert_eq!(buffer.len_utf16(), 14);
+        assert_eq!(buffer.char_to_line_utf16(4), (0, 6));
+        buffer.delete_range(0, 3);
+        assert_eq!(buffer.len_utf16(), 9);
+        assert_eq!(buffer.char_to_line_utf16(3), (1, 1));
     }
 
     #[test]
@@ -810,4 +2072,116 @@ mod test {
         buffer.insert("AAAAAA\0\0AAAAAA");
         buffer.set_cursor(26);
     }
+
+    #[test]
+    fn test_snapshot() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let mut buffer = Buffer::from("hello\nworld");
+        let snapshot = buffer.snapshot();
+        assert_send_sync(&snapshot);
+        buffer.set_cursor(5);
+        buffer.insert(" there");
+        buffer.delete_range(0, 1);
+        assert_eq!(buffer, "ello there\nworld");
+        assert_eq!(snapshot, "hello\nworld");
+        assert_eq!(snapshot.len_chars(), 11);
+        assert_eq!(snapshot.char_to_line(7), 1);
+        assert_eq!(snapshot.read_chars(6..11).unwrap(), "world");
+        assert_eq!(snapshot.chars_at(5).next(), Some('\n'.into()));
+        let handle = std::thread::spawn(move || snapshot.to_string());
+        assert_eq!(handle.join().unwrap(), "hello\nworld");
+    }
+
+    #[test]
+    fn test_views() {
+        let mut buffer = Buffer::from("hello world");
+        buffer.set_cursor(6);
+        buffer.narrow(6..11);
+        let base = buffer.view();
+        let other = buffer.create_view();
+        assert_eq!(buffer.view(), base);
+        buffer.set_view(other);
+        assert_eq!(buffer.cursor(), 6);
+        assert_eq!((buffer.begv(), buffer.zv()), (6, 11));
+        buffer.widen();
+        buffer.set_cursor(0);
+        buffer.insert("oh, ");
+        buffer.set_view(base);
+        assert_eq!(buffer.cursor(), 10);
+        assert_eq!((buffer.begv(), buffer.zv()), (10, 15));
+        buffer.set_cursor(15);
+        buffer.insert("!");
+        buffer.set_view(other);
+        assert!(!buffer.is_narrowed());
+        assert_eq!(buffer.cursor(), 4);
+        assert_eq!(buffer, "oh, hello world!");
+        buffer.set_view(base);
+        assert_eq!((buffer.begv(), buffer.zv()), (10, 16));
+        buffer.remove_view(other);
+        let third = buffer.create_view();
+        assert_eq!(third, other);
+    }
+
+    #[test]
+    fn test_narrow() {
+        let mut buffer = Buffer::from("hello Θ world");
+        buffer.enable_undo();
+        buffer.narrow(6..8);
+        assert!(buffer.is_narrowed());
+        assert_eq!((buffer.begv(), buffer.zv()), (6, 8));
+        assert_eq!(buffer.cursor(), 6);
+        buffer.set_cursor(100);
+        assert_eq!(buffer.cursor(), 8);
+        assert_eq!(buffer.read_chars(0..13).unwrap(), "Θ ");
+        assert_eq!(buffer.chunks(0..13).collect::<Vec<_>>().concat(), "Θ ".as_bytes());
+        let mut chars = buffer.chars_at(0);
+        assert_eq!(chars.prev(), None);
+        assert_eq!(chars.collect::<Vec<_>>(), [u32::from('Θ'), u32::from(' ')]);
+        assert_eq!(buffer.search_forward("o", 0..13), None);
+        // text inserted at either bound goes inside the region
+        buffer.insert("!");
+        buffer.set_cursor(6);
+        buffer.insert("<");
+        assert_eq!(buffer, "hello <Θ !world");
+        assert_eq!((buffer.begv(), buffer.zv()), (6, 10));
+        buffer.undo_boundary();
+        buffer.delete_range(0, 100);
+        assert_eq!(buffer, "hello world");
+        assert_eq!((buffer.begv(), buffer.zv()), (6, 6));
+        buffer.delete_backwards(3);
+        assert_eq!(buffer, "hello world");
+        // undo is not limited by the restriction
+        buffer.undo();
+        assert_eq!(buffer, "hello <Θ !world");
+        assert_eq!((buffer.begv(), buffer.zv()), (6, 10));
+        buffer.widen();
+        assert!(!buffer.is_narrowed());
+        assert_eq!(buffer.zv(), 15);
+        assert_eq!(buffer.read_chars(0..5).unwrap(), "hello");
+        assert_eq!(buffer.search_forward("o", 0..15), Some(4..5));
+    }
+
+    #[test]
+    fn test_modified() {
+        let mut buffer = Buffer::from("hello");
+        assert!(!buffer.is_modified());
+        buffer.insert("");
+        assert!(!buffer.is_modified());
+        buffer.insert("x");
+        assert!(buffer.is_modified());
+        buffer.set_modified(false);
+        assert!(!buffer.is_modified());
+        buffer.delete_range(0, 0);
+        assert!(!buffer.is_modified());
+        buffer.delete_range(0, 1);
+        assert!(buffer.is_modified());
+        buffer.set_modified(false);
+        buffer.set_modified(true);
+        assert!(buffer.is_modified());
+        // Moving around is not an edit
+        buffer.set_modified(false);
+        buffer.set_cursor(3);
+        buffer.narrow(1..4);
+        assert!(!buffer.is_modified());
+    }
 }
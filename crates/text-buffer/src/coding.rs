@@ -0,0 +1,271 @@
+#![allow(clippy::must_use_candidate)]
+//! Loading and saving buffer text.
+//!
+//! Files are decoded as UTF-8. Bytes that are not valid UTF-8 are kept as raw
+//! byte chars so they are written back unchanged. Like Emacs, the line ending
+//! convention is detected from the first line ending in the file and converted
+//! to `\n` in the buffer, then converted back when the text is written.
+use crate::{encoding, Buffer, Snapshot};
+use memchr::memchr2;
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+    ops::Range,
+};
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+const READ_SIZE: usize = 64 * 1024;
+
+/// The line ending convention of a file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Eol {
+    /// Lines end with `\n`
+    #[default]
+    Unix,
+    /// Lines end with `\r\n`
+    Dos,
+    /// Lines end with `\r`
+    Mac,
+}
+
+/// How the text of a file is encoded. [`Buffer::load_from`] returns the coding
+/// it detected, which can be passed to [`Snapshot::write_to`] to save the text
+/// the same way.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Coding {
+    pub eol: Eol,
+    /// The text starts with a UTF-8 byte order mark
+    pub bom: bool,
+}
+
+/// Find the line ending convention from the first line ending in `bytes`.
+fn detect_eol(bytes: &[u8]) -> Option<Eol> {
+    let idx = memchr2(b'\r', b'\n', bytes)?;
+    Some(match (bytes[idx], bytes.get(idx + 1)) {
+        (b'\n', _) => Eol::Unix,
+        (_, Some(b'\n')) => Eol::Dos,
+        _ => Eol::Mac,
+    })
+}
+
+/// Convert the line endings in `bytes` to `\n`.
+fn decode_eol(bytes: &[u8], eol: Eol) -> Cow<'_, [u8]> {
+    match eol {
+        Eol::Unix => Cow::Borrowed(bytes),
+        Eol::Dos => {
+            let mut decoded = Vec::with_capacity(bytes.len());
+            for (idx, byte) in bytes.iter().enumerate() {
+                if !(*byte == b'\r' && bytes.get(idx + 1) == Some(&b'\n')) {
+                    decoded.push(*byte);
+                }
+            }
+            Cow::Owned(decoded)
+        }
+        Eol::Mac => {
+            Cow::Owned(bytes.iter().map(|x| if *x == b'\r' { b'\n' } else { *x }).collect())
+        }
+    }
+}
+
+/// Convert the `\n` line endings in `bytes` to `eol`.
+fn encode_eol(bytes: &[u8], eol: Eol) -> Cow<'_, [u8]> {
+    match eol {
+        Eol::Unix => Cow::Borrowed(bytes),
+        Eol::Dos => {
+            let mut encoded = Vec::with_capacity(bytes.len() + bytes.len() / 32);
+            for byte in bytes {
+                if *byte == b'\n' {
+                    encoded.push(b'\r');
+                }
+                encoded.push(*byte);
+            }
+            Cow::Owned(encoded)
+        }
+        Eol::Mac => {
+            Cow::Owned(bytes.iter().map(|x| if *x == b'\n' { b'\r' } else { *x }).collect())
+        }
+    }
+}
+
+impl Buffer {
+    /// Read all of `reader` and insert it at the cursor, leaving the cursor
+    /// before the inserted text. The text is read in chunks, so the whole
+    /// file is never held in memory twice. A byte order mark is removed and
+    /// line endings are converted to `\n`.
+    ///
+    /// Returns the coding that was detected.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from `reader`. Text that was read before the error
+    /// stays in the buffer.
+    pub fn load_from(&mut self, mut reader: impl Read) -> io::Result<Coding> {
+        let start = self.cursor();
+        let mut coding = Coding::default();
+        let mut eol = None;
+        let mut at_start = true;
+        let mut pending = Vec::new();
+        let mut buf = vec![0; READ_SIZE];
+        loop {
+            let len = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.insert_decoded(&pending, &mut eol);
+                    self.set_cursor(start);
+                    return Err(err);
+                }
+            };
+            pending.extend_from_slice(&buf[..len]);
+            if at_start {
+                if pending.len() < BOM.len() && BOM.starts_with(&pending) {
+                    continue;
+                }
+                if pending.starts_with(BOM) {
+                    coding.bom = true;
+                    pending.drain(..BOM.len());
+                }
+                at_start = false;
+            }
+            // hold back a \r that might start a \r\n and any partial char
+            let keep = if pending.last() == Some(&b'\r') && eol != Some(Eol::Mac) {
+                1
+            } else {
+                encoding::incomplete_utf8_tail(&pending)
+            };
+            let split = pending.len() - keep;
+            self.insert_decoded(&pending[..split], &mut eol);
+            pending.drain(..split);
+        }
+        self.insert_decoded(&pending, &mut eol);
+        self.set_cursor(start);
+        coding.eol = eol.unwrap_or_default();
+        Ok(coding)
+    }
+
+    /// Insert bytes read from a file, detecting the line ending convention if
+    /// it is not yet known.
+    fn insert_decoded(&mut self, bytes: &[u8], eol: &mut Option<Eol>) {
+        if eol.is_none() {
+            *eol = detect_eol(bytes);
+        }
+        self.insert_bytes(&decode_eol(bytes, eol.unwrap_or_default()));
+    }
+}
+
+impl Snapshot {
+    /// Write the text in the char `range` to `writer` using `coding`. Raw byte
+    /// chars are written as the byte they represent.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from `writer`.
+    pub fn write_to(
+        &self,
+        range: Range<usize>,
+        mut writer: impl Write,
+        coding: Coding,
+    ) -> io::Result<()> {
+        if coding.bom {
+            writer.write_all(BOM)?;
+        }
+        for chunk in self.chunks(range) {
+            writer.write_all(&encode_eol(&encoding::to_binary(chunk), coding.eol))?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// A reader that returns at most `size` bytes at a time
+    struct Trickle<'a> {
+        data: &'a [u8],
+        size: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.size.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn round_trip(data: &[u8], expect: &str, coding: Coding) {
+        for size in 1..=data.len().max(1) {
+            let mut buffer = Buffer::new();
+            let reader = Trickle { data, size };
+            assert_eq!(buffer.load_from(reader).unwrap(), coding, "size {size}");
+            assert_eq!(buffer.to_string(), expect, "size {size}");
+            assert_eq!(buffer.cursor(), 0);
+            let mut written = Vec::new();
+            buffer.write_to(0..buffer.len_chars(), &mut written, coding).unwrap();
+            assert_eq!(written, data);
+        }
+    }
+
+    #[test]
+    fn test_load_and_write() {
+        let unix = Coding::default();
+        let dos = Coding { eol: Eol::Dos, bom: false };
+        let mac = Coding { eol: Eol::Mac, bom: false };
+        round_trip(b"", "", unix);
+        round_trip(b"a\nb\n", "a\nb\n", unix);
+        round_trip(b"a\r\nb\r\n", "a\nb\n", dos);
+        round_trip(b"a\rb\r", "a\nb\n", mac);
+        round_trip("\u{FEFF}Θ\r\n😀".as_bytes(), "Θ\n😀", Coding { eol: Eol::Dos, bom: true });
+        round_trip(b"\xEF\xBB", "\\357\\273", unix);
+    }
+
+    #[test]
+    fn test_load_binary() {
+        let mut buffer = Buffer::from("<>");
+        buffer.set_cursor(1);
+        let data = b"\xFF\xCE\xB8\r\n";
+        let coding = buffer.load_from(Trickle { data, size: 2 }).unwrap();
+        assert_eq!(coding.eol, Eol::Dos);
+        assert_eq!(buffer.len_chars(), 5);
+        assert_eq!(buffer.cursor(), 1);
+        let mut written = Vec::new();
+        buffer.write_to(1..4, &mut written, coding).unwrap();
+        assert_eq!(written, data);
+    }
+
+    fn check_coding(lines: &[Vec<u8>], eol: u8, bom: bool, chunk: usize) {
+        let eol = [Eol::Unix, Eol::Dos, Eol::Mac][eol as usize % 3];
+        let newline: &[u8] = match eol {
+            Eol::Unix => b"\n",
+            Eol::Dos => b"\r\n",
+            Eol::Mac => b"\r",
+        };
+        let mut file = if bom { b"\xEF\xBB\xBF".to_vec() } else { Vec::new() };
+        file.extend(lines.join(newline));
+        let mut buffer = Buffer::new();
+        let coding = buffer.load_from(Trickle { data: &file, size: chunk }).unwrap();
+        // text that happens to start with a BOM is indistinguishable from one
+        assert_eq!(coding.bom, bom || file.starts_with(b"\xEF\xBB\xBF"));
+        if coding.bom != bom {
+            return;
+        }
+        if lines.len() > 1 {
+            assert_eq!(coding.eol, eol);
+        }
+        assert_eq!(buffer.read_binary(0..buffer.len()), lines.join(&b'\n'));
+        let mut written = Vec::new();
+        buffer.write_to(0..buffer.len_chars(), &mut written, coding).unwrap();
+        assert_eq!(written, file);
+    }
+
+    proptest! {
+        #[test]
+        fn pt_coding(ref lines in prop::collection::vec(prop::collection::vec(any::<u8>().prop_filter("no eol", |x| !matches!(x, b'\r' | b'\n')), 0..10), 0..10), eol in any::<u8>(), bom in any::<bool>(), chunk in 1..8usize) {
+            check_coding(lines, eol, bom, chunk);
+        }
+    }
+}
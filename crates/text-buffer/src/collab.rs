@@ -0,0 +1,518 @@
+#![allow(clippy::must_use_candidate)]
+#![allow(clippy::missing_panics_doc)]
+//! Collaborative editing.
+//!
+//! A [`Replica`] is one copy of a document that is edited concurrently with
+//! other copies. Local edits return an [`Op`] that has to be sent to every
+//! other replica, and once every replica has applied every op they all hold
+//! the same text, regardless of the order the ops arrived in.
+//!
+//! This is a sequence CRDT in the style of RGA. Every char that was ever
+//! inserted has a unique [`OpId`] made of a Lamport clock and the replica
+//! that inserted it, and remembers the char it was inserted after, its
+//! origin. Deleted chars are kept as tombstones so that later ops can still
+//! refer to them. A char is placed after its origin, but after any chars
+//! following the origin that have a larger id, which orders concurrent
+//! inserts at the same position the same way on every replica. Chars with
+//! consecutive clocks that were inserted after each other are stored as a
+//! single run.
+use crate::Buffer;
+use std::collections::BTreeMap;
+
+/// The unique id of an inserted char. Ids are ordered by Lamport clock and
+/// then by replica.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpId {
+    pub clock: u64,
+    pub replica: u32,
+}
+
+impl OpId {
+    fn offset(self, offset: usize) -> Self {
+        Self { clock: self.clock + offset as u64, replica: self.replica }
+    }
+}
+
+/// An edit made on one replica that needs to be applied to the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Insert `text` after the char `origin`, or at the start of the text if
+    /// it is `None`. The chars of `text` get consecutive clocks starting with
+    /// `id`.
+    Insert {
+        id: OpId,
+        origin: Option<OpId>,
+        text: String,
+    },
+    /// Delete the chars in each span, given as the id of the first char and
+    /// the number of chars with consecutive clocks.
+    Delete { spans: Vec<(OpId, usize)> },
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Run {
+    /// The id of the first char. The other chars have the following clocks.
+    id: OpId,
+    len: usize,
+    deleted: bool,
+}
+
+impl Run {
+    fn visible(&self) -> usize {
+        if self.deleted {
+            0
+        } else {
+            self.len
+        }
+    }
+
+    /// The offset of the char `id` in this run, if it has it.
+    #[allow(clippy::cast_possible_truncation)]
+    fn offset(&self, id: OpId) -> Option<usize> {
+        let end = self.id.clock + self.len as u64;
+        (id.replica == self.id.replica && (self.id.clock..end).contains(&id.clock))
+            .then(|| (id.clock - self.id.clock) as usize)
+    }
+}
+
+/// One copy of a collaboratively edited [`Buffer`].
+#[derive(Debug)]
+pub struct Replica {
+    id: u32,
+    /// The Lamport clock, the largest clock this replica has seen
+    clock: u64,
+    buffer: Buffer,
+    /// Every char in document order, including the deleted ones
+    runs: Vec<Run>,
+    /// A run index and the number of visible chars before it. Edits tend to be
+    /// close to each other, so searches start from the last edit.
+    hint: (usize, usize),
+    /// The clock ranges of the chars inserted by each replica, sorted and
+    /// merged when they touch
+    known: BTreeMap<u32, Vec<(u64, u64)>>,
+    /// Remote ops that refer to chars that have not arrived yet
+    pending: Vec<Op>,
+}
+
+impl Replica {
+    /// Create a replica with an empty buffer. Every replica of a document
+    /// must have a different `id`.
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            clock: 0,
+            buffer: Buffer::new(),
+            runs: Vec::new(),
+            hint: (0, 0),
+            known: BTreeMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The merged text. It can only be changed through the replica, so that
+    /// every edit is turned into an op.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn into_buffer(self) -> Buffer {
+        self.buffer
+    }
+
+    /// The number of remote ops that are waiting for the ops they depend on.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Insert `text` at the char position `pos` and return the op that
+    /// applies the insertion to the other replicas.
+    ///
+    /// # Panics
+    ///
+    /// If `pos` is past the end of the buffer.
+    pub fn insert(&mut self, pos: usize, text: &str) -> Op {
+        assert!(pos <= self.buffer.len_chars(), "insert position {pos} is out of bounds");
+        let origin = pos.checked_sub(1).map(|pos| {
+            let (idx, start) = self.find_visible(pos);
+            self.runs[idx].id.offset(pos - start)
+        });
+        let id = OpId { clock: self.clock + 1, replica: self.id };
+        let op = Op::Insert { id, origin, text: text.to_owned() };
+        // Nothing has a larger clock than the new chars, so they are placed
+        // right after the origin, before any tombstones that follow it
+        self.integrate_insert(id, origin, text);
+        op
+    }
+
+    /// Delete the chars between the positions `beg` and `end` and return the
+    /// op that applies the deletion to the other replicas.
+    ///
+    /// # Panics
+    ///
+    /// If the range is past the end of the buffer.
+    pub fn delete_range(&mut self, beg: usize, end: usize) -> Op {
+        let (beg, end) = (beg.min(end), beg.max(end));
+        assert!(end <= self.buffer.len_chars(), "delete range {beg}..{end} is out of bounds");
+        let mut spans = Vec::new();
+        let mut remaining = end - beg;
+        while remaining > 0 {
+            let (idx, start) = self.find_visible(beg);
+            let idx = self.isolate(idx, beg - start, remaining);
+            remaining -= self.runs[idx].len;
+            spans.push((self.runs[idx].id, self.runs[idx].len));
+            self.delete_run(idx, beg);
+        }
+        Op::Delete { spans }
+    }
+
+    /// Apply an op from another replica. Ops can arrive in any order and more
+    /// than once. An op that depends on ops that have not been applied yet
+    /// is kept until they arrive.
+    pub fn apply(&mut self, op: Op) {
+        if !self.try_apply(&op) {
+            self.pending.push(op);
+            return;
+        }
+        // Applying an op can make pending ops ready
+        let mut idx = 0;
+        while idx < self.pending.len() {
+            let op = self.pending[idx].clone();
+            if self.try_apply(&op) {
+                self.pending.swap_remove(idx);
+                idx = 0;
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    /// Apply `op` if everything it refers to is known. Returns false if it
+    /// has to wait.
+    fn try_apply(&mut self, op: &Op) -> bool {
+        match op {
+            Op::Insert { id, origin, text } => {
+                if origin.is_some_and(|origin| !self.is_known(origin, 1)) {
+                    return false;
+                }
+                if !self.is_known(*id, 1) {
+                    self.integrate_insert(*id, *origin, text);
+                }
+            }
+            Op::Delete { spans } => {
+                if !spans.iter().all(|&(id, len)| self.is_known(id, len)) {
+                    return false;
+                }
+                for &(id, len) in spans {
+                    let mut done = 0;
+                    while done < len {
+                        let id = id.offset(done);
+                        let (idx, start) = self.find_id(id).expect("span should be known");
+                        let offset = self.runs[idx].offset(id).unwrap();
+                        let idx = self.isolate(idx, offset, len - done);
+                        done += self.runs[idx].len;
+                        if !self.runs[idx].deleted {
+                            let pos = if offset > 0 {
+                                start + self.runs[idx - 1].visible()
+                            } else {
+                                start
+                            };
+                            self.delete_run(idx, pos);
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether all chars with the `len` clocks starting at `id` are known.
+    fn is_known(&self, id: OpId, len: usize) -> bool {
+        let Some(ranges) = self.known.get(&id.replica) else { return false };
+        let idx = ranges.partition_point(|(start, _)| *start <= id.clock);
+        idx > 0 && id.clock + len as u64 <= ranges[idx - 1].1
+    }
+
+    fn add_known(&mut self, id: OpId, len: usize) {
+        let ranges = self.known.entry(id.replica).or_default();
+        let (start, end) = (id.clock, id.clock + len as u64);
+        let idx = ranges.partition_point(|range| range.0 < start);
+        let touches_prev = idx > 0 && ranges[idx - 1].1 == start;
+        let touches_next = ranges.get(idx).is_some_and(|range| range.0 == end);
+        match (touches_prev, touches_next) {
+            (true, true) => ranges[idx - 1].1 = ranges.remove(idx).1,
+            (true, false) => ranges[idx - 1].1 = end,
+            (false, true) => ranges[idx].0 = start,
+            (false, false) => ranges.insert(idx, (start, end)),
+        }
+    }
+
+    /// Place the chars of `text` after `origin` and insert them into the
+    /// buffer.
+    fn integrate_insert(&mut self, id: OpId, origin: Option<OpId>, text: &str) {
+        let len = text.chars().count();
+        if len == 0 {
+            return;
+        }
+        self.clock = self.clock.max(id.clock + len as u64 - 1);
+        self.add_known(id, len);
+        let (mut idx, mut pos) = match origin {
+            None => (0, 0),
+            Some(origin) => {
+                let (idx, start) = self.find_id(origin).expect("origin should be known");
+                let offset = self.runs[idx].offset(origin).unwrap();
+                if offset + 1 < self.runs[idx].len {
+                    self.split(idx, offset + 1);
+                }
+                (idx + 1, start + self.runs[idx].visible())
+            }
+        };
+        // Skip the chars inserted concurrently after the same origin that
+        // sort before this one, along with everything inserted after them
+        while self.runs.get(idx).is_some_and(|run| run.id > id) {
+            pos += self.runs[idx].visible();
+            idx += 1;
+        }
+        self.buffer
+            .apply_edits(&[(pos..pos, text)])
+            .expect("position should be in the buffer");
+        let prev = idx.checked_sub(1).map(|prev| self.runs[prev]);
+        if let Some(prev) = prev.filter(|prev| {
+            !prev.deleted
+                && Some(prev.id.offset(prev.len - 1)) == origin
+                && prev.id.offset(prev.len) == id
+        }) {
+            // Typing continues the previous run
+            self.runs[idx - 1].len += len;
+            self.hint = (idx - 1, pos - prev.len);
+        } else {
+            self.runs.insert(idx, Run { id, len, deleted: false });
+            self.hint = (idx, pos);
+        }
+    }
+
+    /// Split the run at `idx` so that it ends after `len` chars.
+    fn split(&mut self, idx: usize, len: usize) {
+        let run = &mut self.runs[idx];
+        debug_assert!(0 < len && len < run.len);
+        let rest = Run { id: run.id.offset(len), len: run.len - len, deleted: run.deleted };
+        run.len = len;
+        self.runs.insert(idx + 1, rest);
+        if self.hint.0 > idx {
+            self.hint.0 += 1;
+        }
+    }
+
+    /// Split the run at `idx` so that a run starts at `offset` and has at
+    /// most `len` chars, and return its index.
+    fn isolate(&mut self, mut idx: usize, offset: usize, len: usize) -> usize {
+        if offset > 0 {
+            self.split(idx, offset);
+            idx += 1;
+        }
+        if len < self.runs[idx].len {
+            self.split(idx, len);
+        }
+        idx
+    }
+
+    /// Mark the visible run at `idx`, which starts at position `pos`, as
+    /// deleted and delete it from the buffer.
+    fn delete_run(&mut self, idx: usize, pos: usize) {
+        let run = &mut self.runs[idx];
+        debug_assert!(!run.deleted);
+        run.deleted = true;
+        let range = pos..pos + run.len;
+        self.buffer.apply_edits(&[(range, "")]).expect("range should be in the buffer");
+        self.hint = (idx, pos);
+    }
+
+    /// Find the run with the visible char at `pos`. Returns its index and the
+    /// position of its start.
+    fn find_visible(&self, pos: usize) -> (usize, usize) {
+        let (mut idx, mut start) = self.hint;
+        while start > pos || (idx == self.runs.len() && idx > 0) {
+            idx -= 1;
+            start -= self.runs[idx].visible();
+        }
+        while self.runs[idx].deleted || start + self.runs[idx].len <= pos {
+            start += self.runs[idx].visible();
+            idx += 1;
+        }
+        (idx, start)
+    }
+
+    /// Find the run that contains the char `id`, even if it was deleted.
+    /// Returns its index and the position of its start.
+    fn find_id(&self, id: OpId) -> Option<(usize, usize)> {
+        let (hint, hint_start) = self.hint;
+        let (mut back, mut back_start) = (hint, hint_start);
+        let (mut fwd, mut fwd_start) = (hint, hint_start);
+        // Search outwards from the hint in both directions
+        while back > 0 || fwd < self.runs.len() {
+            if let Some(run) = self.runs.get(fwd) {
+                if run.offset(id).is_some() {
+                    return Some((fwd, fwd_start));
+                }
+                fwd_start += run.visible();
+                fwd += 1;
+            }
+            if back > 0 {
+                back -= 1;
+                let run = &self.runs[back];
+                back_start -= run.visible();
+                if run.offset(id).is_some() {
+                    return Some((back, back_start));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn sync(replicas: &mut [Replica], ops: &[Op]) {
+        for replica in replicas {
+            for op in ops {
+                replica.apply(op.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_edits() {
+        let mut replica = Replica::new(1);
+        replica.insert(0, "hello world");
+        replica.insert(5, ",");
+        replica.delete_range(0, 1);
+        replica.insert(0, "H");
+        replica.delete_range(6, 12);
+        replica.insert(6, "!");
+        assert_eq!(replica.buffer(), "Hello,!");
+        let op = replica.insert(6, " there");
+        let origin = OpId { clock: 12, replica: 1 };
+        assert_eq!(
+            op,
+            Op::Insert {
+                id: OpId { clock: 15, replica: 1 },
+                origin: Some(origin),
+                text: " there".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let mut replicas = [Replica::new(1), Replica::new(2), Replica::new(3)];
+        let op = replicas[0].insert(0, "ac");
+        sync(&mut replicas[1..], &[op]);
+        let ops = [
+            replicas[0].insert(1, "x"),
+            replicas[1].insert(1, "y"),
+            replicas[2].insert(1, "z"),
+        ];
+        sync(&mut replicas, &ops);
+        // Ties are broken by replica, with larger ids first
+        for replica in &replicas {
+            assert_eq!(replica.buffer(), "azyxc");
+        }
+    }
+
+    #[test]
+    fn test_concurrent_deletes() {
+        let mut replicas = [Replica::new(1), Replica::new(2)];
+        let op = replicas[0].insert(0, "hello world");
+        sync(&mut replicas[1..], &[op]);
+        let ops = [
+            replicas[0].delete_range(2, 8),
+            replicas[1].delete_range(4, 11),
+            replicas[1].insert(4, "!"),
+        ];
+        sync(&mut replicas, &ops);
+        for replica in &replicas {
+            assert_eq!(replica.buffer(), "he!");
+        }
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut source = Replica::new(1);
+        let mut ops = vec![source.insert(0, "abc")];
+        ops.push(source.insert(3, "def"));
+        ops.push(source.delete_range(2, 4));
+        ops.push(source.insert(2, "X"));
+        let mut replica = Replica::new(2);
+        for op in ops.iter().rev() {
+            replica.apply(op.clone());
+        }
+        assert_eq!(replica.pending(), 0);
+        assert_eq!(replica.buffer(), "abXef");
+        // Applying ops again does nothing
+        for op in ops {
+            replica.apply(op);
+        }
+        assert_eq!(replica.buffer(), "abXef");
+    }
+
+    fn check_collab(ops: &[(u8, u8, usize, usize, String)]) {
+        let mut replicas: Vec<_> = (0..3).map(Replica::new).collect();
+        let mut inboxes: Vec<Vec<Op>> = vec![Vec::new(); replicas.len()];
+        for (replica, kind, a, b, ins) in ops {
+            let idx = usize::from(*replica) % replicas.len();
+            let replica = &mut replicas[idx];
+            let mut chars: Vec<char> = replica.buffer().to_string().chars().collect();
+            let len = replica.buffer().len_chars();
+            let op = match kind % 3 {
+                0 => {
+                    let pos = a % (len + 1);
+                    chars.splice(pos..pos, ins.chars());
+                    replica.insert(pos, ins)
+                }
+                1 => {
+                    let beg = a % (len + 1);
+                    let end = (beg + b % 5).min(len);
+                    chars.drain(beg..end);
+                    replica.delete_range(beg, end)
+                }
+                _ => {
+                    // Deliver one of the waiting ops, in any order
+                    let inbox = &mut inboxes[idx];
+                    if !inbox.is_empty() {
+                        replica.apply(inbox.remove(a % inbox.len()));
+                    }
+                    continue;
+                }
+            };
+            assert_eq!(replica.buffer(), &chars.iter().collect::<String>());
+            for (other, inbox) in inboxes.iter_mut().enumerate() {
+                if other != idx {
+                    inbox.push(op.clone());
+                }
+            }
+        }
+        for (replica, inbox) in replicas.iter_mut().zip(inboxes) {
+            for op in inbox.into_iter().rev() {
+                replica.apply(op);
+            }
+            assert_eq!(replica.pending(), 0);
+        }
+        let text = replicas[0].buffer().to_string();
+        for replica in &replicas[1..] {
+            assert_eq!(replica.buffer(), &text);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pt_collab(ref ops in prop::collection::vec((any::<u8>(), any::<u8>(), any::<usize>(), any::<usize>(), "\\PC{0,4}"), 0..50)) {
+            check_collab(ops);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+#![allow(clippy::must_use_candidate)]
+//! Display columns.
+//!
+//! Columns are counted the way Emacs displays text in a terminal: a tab
+//! advances to the next tab stop, East Asian wide and fullwidth chars take
+//! two columns and combining marks take none. Control chars are shown as `^X`
+//! and raw bytes as octal escapes like `\200`.
+use crate::{encoding, Snapshot};
+use unicode_width::UnicodeWidthChar;
+
+/// The number of columns the char `code` takes when it is displayed at
+/// `column`.
+fn char_columns(code: u32, column: usize, tab_width: usize) -> usize {
+    match code {
+        0x09 => tab_width - column % tab_width,
+        0x00..=0x1F | 0x7F => 2,
+        0x80..=0x9F => 4,
+        _ => match char::from_u32(code) {
+            Some(chr) => chr.width().unwrap_or(1),
+            None if encoding::char_to_raw_byte(code).is_some() => 4,
+            None => 1,
+        },
+    }
+}
+
+impl Snapshot {
+    /// Return the display column of the char position `pos`, counted from
+    /// the start of its line. Tab stops are every `tab_width` columns, and a
+    /// width of 0 is treated as 1.
+    pub fn char_to_column(&self, pos: usize, tab_width: usize) -> usize {
+        let tab_width = tab_width.max(1);
+        let pos = self.clamp(pos);
+        let start = self.clamp(self.line_start(pos));
+        self.chars_at(start)
+            .take(pos - start)
+            .fold(0, |column, code| column + char_columns(code, column, tab_width))
+    }
+
+    /// Return the char position of `column` on `line` (zero based). If a char
+    /// spans `column`, the position after it is returned. If the line is
+    /// shorter than `column`, the end of the line is returned.
+    pub fn line_column_to_char(&self, line: usize, column: usize, tab_width: usize) -> usize {
+        let tab_width = tab_width.max(1);
+        let mut pos = self.clamp(self.line_to_char(line));
+        let mut current = 0;
+        let mut chars = self.chars_at(pos);
+        while current < column {
+            match chars.next() {
+                Some(code) if code != u32::from(b'\n') => {
+                    current += char_columns(code, current, tab_width);
+                    pos += 1;
+                }
+                _ => break,
+            }
+        }
+        pos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Buffer;
+
+    #[test]
+    fn test_columns() {
+        let buffer = Buffer::from("ab\tc\n日本e\u{301}x\n\u{1}\t");
+        let columns: Vec<_> = (0..=13).map(|pos| buffer.char_to_column(pos, 8)).collect();
+        assert_eq!(columns, [0, 1, 2, 8, 9, 0, 2, 4, 5, 5, 6, 0, 2, 8]);
+        assert_eq!(buffer.char_to_column(3, 4), 4);
+        assert_eq!(buffer.char_to_column(3, 0), 3);
+        assert_eq!(buffer.line_column_to_char(0, 5, 8), 3);
+        assert_eq!(buffer.line_column_to_char(0, 8, 8), 3);
+        assert_eq!(buffer.line_column_to_char(0, 9, 8), 4);
+        assert_eq!(buffer.line_column_to_char(0, 100, 8), 4);
+        assert_eq!(buffer.line_column_to_char(1, 1, 8), 6);
+        assert_eq!(buffer.line_column_to_char(1, 5, 8), 8);
+        assert_eq!(buffer.line_column_to_char(2, 1, 8), 12);
+        assert_eq!(buffer.line_column_to_char(5, 1, 8), 13);
+    }
+
+    #[test]
+    fn test_raw_byte_columns() {
+        let mut buffer = Buffer::from(&b"a\xFF\xC2\x80b"[..]);
+        assert_eq!(buffer.char_to_column(2, 8), 5);
+        assert_eq!(buffer.char_to_column(4, 8), 10);
+        buffer.narrow(1..4);
+        assert_eq!(buffer.char_to_column(3, 8), 8);
+        assert_eq!(buffer.line_column_to_char(0, 1, 8), 2);
+    }
+}
@@ -0,0 +1,379 @@
+#![allow(clippy::must_use_candidate)]
+//! The internal encoding of buffer text.
+//!
+//! Like Emacs, the buffer stores text in an extension of UTF-8 so that it can
+//! hold every char Emacs can, not just Unicode scalar values:
+//!
+//! - Chars up to `0x10FFFF`, including surrogates, use their UTF-8 encoding.
+//! - Chars from `0x110000` to `0x1FFFFF` use the 4 byte form, and chars up to
+//!   `0x3FFF7F` use a 5 byte form that starts with `0xF8`.
+//! - Raw bytes `0x80..=0xFF` from text that could not be decoded are the chars
+//!   `0x3FFF80..=0x3FFFFF`. They are stored as the overlong sequences
+//!   `C0 80..=C1 BF`, which never appear in the encoding of any other char.
+//!
+//! Every char starts with a byte that is not a UTF-8 continuation byte, so
+//! chars are counted and located the same way as in UTF-8. Text made only of
+//! Unicode scalar values is plain UTF-8 and can be borrowed as a `&str`.
+use memchr::{memchr2, memchr_iter};
+use std::{borrow::Cow, fmt};
+
+/// The largest Unicode scalar value.
+pub const MAX_UNICODE_CHAR: u32 = 0x10_FFFF;
+/// The largest char that is not a raw byte.
+const MAX_5_BYTE_CHAR: u32 = 0x3F_FF7F;
+/// The largest char a buffer can hold.
+pub const MAX_CHAR: u32 = 0x3F_FFFF;
+/// Raw byte `b` is the char `b + BYTE8_OFFSET`
+const BYTE8_OFFSET: u32 = 0x3F_FF00;
+
+/// The error returned when text that contains chars that are not Unicode
+/// scalar values is decoded as a `&str`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    valid_up_to: usize,
+}
+
+impl DecodeError {
+    /// The number of bytes of valid UTF-8 before the first char that could not
+    /// be decoded.
+    pub fn valid_up_to(&self) -> usize {
+        self.valid_up_to
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "text is not valid unicode after byte {}", self.valid_up_to)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<std::str::Utf8Error> for DecodeError {
+    fn from(err: std::str::Utf8Error) -> Self {
+        Self { valid_up_to: err.valid_up_to() }
+    }
+}
+
+/// Return the char that represents the raw byte `byte`. Bytes below `0x80`
+/// are ASCII and represent themselves.
+pub const fn raw_byte_to_char(byte: u8) -> u32 {
+    if byte < 0x80 {
+        byte as u32
+    } else {
+        byte as u32 + BYTE8_OFFSET
+    }
+}
+
+/// Return the raw byte represented by `code`, or `None` if it is not a raw
+/// byte char.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn char_to_raw_byte(code: u32) -> Option<u8> {
+    if code > MAX_5_BYTE_CHAR && code <= MAX_CHAR {
+        Some((code - BYTE8_OFFSET) as u8)
+    } else {
+        None
+    }
+}
+
+/// Encode `code` into `buf` and return the encoded bytes.
+///
+/// # Panics
+///
+/// Panics if `code` is greater than [`MAX_CHAR`].
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn encode_char(code: u32, buf: &mut [u8; 5]) -> &[u8] {
+    let cont = |shift: u32| 0x80 | ((code >> shift) & 0x3F) as u8;
+    let len = match code {
+        0..=0x7F => {
+            buf[0] = code as u8;
+            1
+        }
+        0x80..=0x7FF => {
+            buf[..2].copy_from_slice(&[0xC0 | (code >> 6) as u8, cont(0)]);
+            2
+        }
+        0x800..=0xFFFF => {
+            buf[..3].copy_from_slice(&[0xE0 | (code >> 12) as u8, cont(6), cont(0)]);
+            3
+        }
+        0x1_0000..=0x1F_FFFF => {
+            buf[..4].copy_from_slice(&[0xF0 | (code >> 18) as u8, cont(12), cont(6), cont(0)]);
+            4
+        }
+        0x20_0000..=MAX_5_BYTE_CHAR => {
+            let lead = 0x80 | ((code >> 18) & 0x0F) as u8;
+            buf.copy_from_slice(&[0xF8, lead, cont(12), cont(6), cont(0)]);
+            5
+        }
+        _ => {
+            let byte = char_to_raw_byte(code).expect("char out of range");
+            buf[..2].copy_from_slice(&[0xC0 | ((byte >> 6) & 1), 0x80 | (byte & 0x3F)]);
+            2
+        }
+    };
+    &buf[..len]
+}
+
+/// The length of the encoded char starting with `lead`.
+pub(crate) const fn char_width(lead: u8) -> usize {
+    match lead {
+        0x00..=0xBF => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        0xF8..=0xFF => 5,
+    }
+}
+
+/// Decode the char at the start of `bytes`, which must hold exactly its
+/// encoding.
+pub(crate) fn decode_char(bytes: &[u8]) -> u32 {
+    let cont = |idx: usize| u32::from(bytes[idx] & 0x3F);
+    let lead = bytes[0];
+    match lead {
+        0x00..=0xBF => u32::from(lead),
+        0xC0 | 0xC1 => raw_byte_to_char((lead & 1) << 6 | 0x80 | (bytes[1] & 0x3F)),
+        0xC2..=0xDF => u32::from(lead & 0x1F) << 6 | cont(1),
+        0xE0..=0xEF => u32::from(lead & 0x0F) << 12 | cont(1) << 6 | cont(2),
+        0xF0..=0xF7 => u32::from(lead & 0x07) << 18 | cont(1) << 12 | cont(2) << 6 | cont(3),
+        0xF8..=0xFF => (cont(1) & 0x0F) << 18 | cont(2) << 12 | cont(3) << 6 | cont(4),
+    }
+}
+
+/// Convert arbitrary bytes to the internal encoding. Valid UTF-8 is kept as
+/// is and every other byte becomes a raw byte char.
+pub(crate) fn from_binary(bytes: &[u8]) -> Cow<'_, [u8]> {
+    if std::str::from_utf8(bytes).is_ok() {
+        return Cow::Borrowed(bytes);
+    }
+    let mut encoded = Vec::with_capacity(bytes.len() + bytes.len() / 8);
+    for chunk in bytes.utf8_chunks() {
+        encoded.extend_from_slice(chunk.valid().as_bytes());
+        for byte in chunk.invalid() {
+            encoded.extend_from_slice(encode_char(raw_byte_to_char(*byte), &mut [0; 5]));
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// Convert text in the internal encoding back to bytes. Raw byte chars become
+/// the byte they represent, so this reverses [`from_binary`].
+pub(crate) fn to_binary(bytes: &[u8]) -> Cow<'_, [u8]> {
+    let Some(first) = memchr2(0xC0, 0xC1, bytes) else { return Cow::Borrowed(bytes) };
+    let mut decoded = bytes[..first].to_vec();
+    let mut rest = &bytes[first..];
+    while let Some(idx) = memchr2(0xC0, 0xC1, rest) {
+        decoded.extend_from_slice(&rest[..idx]);
+        decoded.push(char_to_raw_byte(decode_char(&rest[idx..idx + 2])).unwrap());
+        rest = &rest[idx + 2..];
+    }
+    decoded.extend_from_slice(rest);
+    Cow::Owned(decoded)
+}
+
+/// Borrow or convert `bytes` as a string if they only contain Unicode scalar
+/// values.
+pub(crate) fn into_str(bytes: Cow<'_, [u8]>) -> Result<Cow<'_, str>, DecodeError> {
+    match bytes {
+        Cow::Borrowed(bytes) => Ok(Cow::Borrowed(std::str::from_utf8(bytes)?)),
+        Cow::Owned(bytes) => match String::from_utf8(bytes) {
+            Ok(string) => Ok(Cow::Owned(string)),
+            Err(err) => Err(err.utf8_error().into()),
+        },
+    }
+}
+
+/// Write `bytes` to `f`, showing raw bytes as octal escapes like Emacs does and
+/// other chars that are not Unicode as the replacement character.
+pub(crate) fn write_lossy(f: &mut fmt::Formatter<'_>, mut bytes: &[u8]) -> fmt::Result {
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(string) => return f.write_str(string),
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                f.write_str(std::str::from_utf8(valid).unwrap())?;
+                let width = char_width(rest[0]);
+                match char_to_raw_byte(decode_char(&rest[..width])) {
+                    Some(byte) => write!(f, "\\{byte:o}")?,
+                    None => f.write_str("\u{FFFD}")?,
+                }
+                bytes = &rest[width..];
+            }
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_wrap)]
+pub(crate) const fn is_char_boundary(byte: u8) -> bool {
+    // This is bit magic equivalent to: b < 128 || b >= 192
+    (byte as i8) >= -0x40
+}
+
+/// The number of UTF-16 code units needed for the char starting with `lead`
+/// followed by `next`. Chars that are not Unicode count as one unit, which is
+/// what they would be replaced with.
+fn utf16_width(lead: u8, next: Option<&u8>) -> usize {
+    match (lead, next) {
+        (0xF0..=0xF3, _) => 2,
+        (0xF4, Some(next)) if *next < 0x90 => 2,
+        _ => 1,
+    }
+}
+
+/// The length of the incomplete UTF-8 sequence at the end of `bytes`, which
+/// might be completed by the bytes that follow it.
+pub(crate) fn incomplete_utf8_tail(bytes: &[u8]) -> usize {
+    let start = bytes.len().saturating_sub(3);
+    for idx in (start..bytes.len()).rev() {
+        if is_char_boundary(bytes[idx]) {
+            let width = match bytes[idx] {
+                0xC2..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF4 => 4,
+                _ => 1,
+            };
+            let len = bytes.len() - idx;
+            return if len < width { len } else { 0 };
+        }
+    }
+    0
+}
+
+pub(crate) fn count_chars(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|byte| is_char_boundary(**byte)).count()
+}
+
+pub(crate) fn count_breaks(bytes: &[u8]) -> usize {
+    memchr_iter(b'\n', bytes).count()
+}
+
+pub(crate) fn count_utf16(bytes: &[u8]) -> usize {
+    let mut count = 0;
+    for (idx, byte) in bytes.iter().enumerate() {
+        if is_char_boundary(*byte) {
+            count += utf16_width(*byte, bytes.get(idx + 1));
+        }
+    }
+    count
+}
+
+/// Return the byte index of char `pos`, or the length of `bytes` if it is past
+/// the end.
+pub(crate) fn char_to_byte_idx(bytes: &[u8], pos: usize) -> usize {
+    let mut starts = bytes.iter().enumerate().filter(|(_, byte)| is_char_boundary(**byte));
+    starts.nth(pos).map_or(bytes.len(), |(idx, _)| idx)
+}
+
+/// Return the byte index of the start of `line`, or the length of `bytes` if
+/// there are not that many lines.
+pub(crate) fn line_to_byte_idx(bytes: &[u8], line: usize) -> usize {
+    match line.checked_sub(1) {
+        None => 0,
+        Some(breaks) => memchr_iter(b'\n', bytes).nth(breaks).map_or(bytes.len(), |idx| idx + 1),
+    }
+}
+
+/// Return the byte index of UTF-16 position `pos`. Positions inside a
+/// surrogate pair are rounded down to the start of the char.
+pub(crate) fn utf16_to_byte_idx(bytes: &[u8], pos: usize) -> usize {
+    let mut count = 0;
+    for (idx, byte) in bytes.iter().enumerate() {
+        if is_char_boundary(*byte) {
+            let width = utf16_width(*byte, bytes.get(idx + 1));
+            if count + width > pos {
+                return idx;
+            }
+            count += width;
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buffer;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_round_trip() {
+        let codes = [0, 0x7F, 0x80, 0x7FF, 0x800, 0xD800, 0xFFFF, 0x1_0000, 0x10_FFFF];
+        let codes = codes.into_iter().chain([0x11_0000, 0x1F_FFFF, 0x20_0000, MAX_5_BYTE_CHAR]);
+        for code in codes.chain([0x3F_FF80, MAX_CHAR]) {
+            let buf = &mut [0; 5];
+            let bytes = encode_char(code, buf);
+            assert_eq!(bytes.len(), char_width(bytes[0]), "{code:#x}");
+            assert_eq!(decode_char(bytes), code, "{code:#x}");
+            assert_eq!(count_chars(bytes), 1);
+            if let Some(chr) = char::from_u32(code) {
+                assert_eq!(bytes, chr.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+        assert_eq!(raw_byte_to_char(0xFF), MAX_CHAR);
+        assert_eq!(char_to_raw_byte(0x3F_FF80), Some(0x80));
+        assert_eq!(char_to_raw_byte(0xFF), None);
+    }
+
+    #[test]
+    fn test_binary() {
+        let binary = b"a\xFF\xC0\xCE\xB8\xF8\x80b";
+        let encoded = from_binary(binary);
+        assert_eq!(count_chars(&encoded), 7);
+        assert_eq!(count_utf16(&encoded), 7);
+        assert_eq!(to_binary(&encoded), &binary[..]);
+        assert!(into_str(encoded).is_err());
+        assert!(matches!(from_binary("aΘ".as_bytes()), Cow::Borrowed(_)));
+        let err = into_str(Cow::Borrowed(&b"ab\xC1\x80"[..])).unwrap_err();
+        assert_eq!(err.valid_up_to(), 2);
+    }
+
+    #[test]
+    fn test_utf16() {
+        let mut text = "a😀".as_bytes().to_vec();
+        text.extend_from_slice(encode_char(0x11_0000, &mut [0; 5]));
+        text.push(b'b');
+        assert_eq!(count_utf16(&text), 5);
+        assert_eq!(utf16_to_byte_idx(&text, 2), 1);
+        assert_eq!(utf16_to_byte_idx(&text, 3), 5);
+        assert_eq!(utf16_to_byte_idx(&text, 4), 9);
+        assert_eq!(char_to_byte_idx(&text, 3), 9);
+        assert_eq!(line_to_byte_idx(b"a\nb\n", 2), 4);
+        assert_eq!(line_to_byte_idx(b"a\nb\n", 3), 4);
+    }
+
+    fn check_binary(data: &[u8], pos: usize, ins: &[u8]) {
+        // every byte that is not part of valid utf8 is a char of its own
+        let char_starts = |bytes: &[u8]| {
+            let mut starts = Vec::new();
+            let mut offset = 0;
+            for chunk in bytes.utf8_chunks() {
+                starts.extend(chunk.valid().char_indices().map(|(idx, _)| offset + idx));
+                offset += chunk.valid().len();
+                starts.extend(offset..offset + chunk.invalid().len());
+                offset += chunk.invalid().len();
+            }
+            starts
+        };
+        let starts = char_starts(data);
+        let mut buffer = Buffer::from(data);
+        assert_eq!(buffer.len_chars(), starts.len());
+        assert_eq!(buffer.read_binary(0..buffer.len()), data);
+        let pos = pos % (starts.len() + 1);
+        let split = starts.get(pos).copied().unwrap_or(data.len());
+        buffer.set_cursor(pos);
+        buffer.insert_bytes(ins);
+        let expect = [&data[..split], ins, &data[split..]].concat();
+        assert_eq!(buffer.read_binary(0..buffer.len()), expect);
+        assert_eq!(buffer.len_chars(), starts.len() + char_starts(ins).len());
+        let unicode = std::str::from_utf8(data).is_ok() && std::str::from_utf8(ins).is_ok();
+        assert_eq!(buffer.read(0..buffer.len()).is_ok(), unicode);
+    }
+
+    proptest! {
+        #[test]
+        fn pt_binary(ref data in prop::collection::vec(any::<u8>(), 0..50), pos in any::<usize>(), ref ins in prop::collection::vec(any::<u8>(), 0..10)) {
+            check_binary(data, pos, ins);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+#![allow(clippy::must_use_candidate)]
+use crate::encoding;
+use std::ops::Range;
+
+/// A single entry in the edit history of a buffer. All positions are in chars
+/// and all text is in the buffer's internal encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// `text` was inserted at `pos`
+    Insert { pos: usize, text: Vec<u8> },
+    /// `text` was deleted from `pos`
+    Delete { pos: usize, text: Vec<u8> },
+    /// The cursor was at this position before the edits that follow it
+    Cursor(usize),
+    /// Separates the edits that are undone together
+    Boundary,
+}
+
+/// A journal of the edits made to a buffer. Edits are grouped between
+/// boundaries, and each call to undo or redo will apply a whole group.
+/// Consecutive insertions or deletions at the same point are amalgamated into
+/// a single entry.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Vec<Edit>>,
+    /// The position of the cursor after the last recorded edit. This is `None`
+    /// at the start of a group.
+    last_cursor: Option<usize>,
+}
+
+impl History {
+    /// The recorded edits, oldest first.
+    pub fn entries(&self) -> &[Edit] {
+        &self.undo
+    }
+
+    /// Return true if there is an undone group that can be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Start a new group of edits.
+    pub(crate) fn boundary(&mut self) {
+        if !matches!(self.undo.last(), None | Some(Edit::Boundary)) {
+            self.undo.push(Edit::Boundary);
+        }
+        self.last_cursor = None;
+    }
+
+    /// Record that `text` was inserted at `cursor`.
+    pub(crate) fn insert(&mut self, cursor: usize, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
+        let contiguous = self.record_cursor(cursor);
+        match self.undo.last_mut() {
+            Some(Edit::Insert { text: prev, .. }) if contiguous => prev.extend_from_slice(text),
+            _ => self.undo.push(Edit::Insert { pos: cursor, text: text.to_owned() }),
+        }
+        self.last_cursor = Some(cursor + encoding::count_chars(text));
+    }
+
+    /// Record that `text` was deleted from `pos`. `cursor` is the position of
+    /// the cursor before the deletion and `new_cursor` is the position after.
+    pub(crate) fn delete(&mut self, cursor: usize, pos: usize, text: Vec<u8>, new_cursor: usize) {
+        let contiguous = self.record_cursor(cursor);
+        let end = pos + encoding::count_chars(&text);
+        match self.undo.last_mut() {
+            // deleting forward
+            Some(Edit::Delete { pos: prev_pos, text: prev }) if contiguous && *prev_pos == pos => {
+                prev.extend_from_slice(&text);
+            }
+            // deleting backward
+            Some(Edit::Delete { pos: prev_pos, text: prev }) if contiguous && *prev_pos == end => {
+                prev.splice(0..0, text);
+                *prev_pos = pos;
+            }
+            _ => self.undo.push(Edit::Delete { pos, text }),
+        }
+        self.last_cursor = Some(new_cursor);
+    }
+
+    /// Record the cursor position if it has moved since the last edit. Returns
+    /// true if the cursor has not moved.
+    fn record_cursor(&mut self, cursor: usize) -> bool {
+        self.redo.clear();
+        if self.last_cursor == Some(cursor) {
+            true
+        } else {
+            self.undo.push(Edit::Cursor(cursor));
+            self.last_cursor = Some(cursor);
+            false
+        }
+    }
+
+    /// Remove the most recent group of edits so that it can be undone.
+    pub(crate) fn pop_undo(&mut self) -> Option<Vec<Edit>> {
+        while matches!(self.undo.last(), Some(Edit::Boundary)) {
+            self.undo.pop();
+        }
+        if self.undo.is_empty() {
+            return None;
+        }
+        let start = self.undo.iter().rposition(|x| *x == Edit::Boundary).map_or(0, |x| x + 1);
+        self.last_cursor = None;
+        Some(self.undo.split_off(start))
+    }
+
+    /// Save a group of edits that was undone so that it can be redone.
+    pub(crate) fn push_redo(&mut self, group: Vec<Edit>) {
+        self.redo.push(group);
+    }
+
+    /// Remove the most recently undone group of edits so that it can be
+    /// redone.
+    pub(crate) fn pop_redo(&mut self) -> Option<Vec<Edit>> {
+        self.redo.pop()
+    }
+
+    /// Add a group of edits that was redone back to the undo history.
+    pub(crate) fn push_undo(&mut self, group: Vec<Edit>) {
+        self.boundary();
+        self.undo.extend(group);
+        self.boundary();
+    }
+}
+
+/// Update the range covered by a series of edits after `deleted` chars were
+/// replaced with `inserted` chars at `pos`.
+pub(crate) fn extend_span(
+    span: Option<Range<usize>>,
+    pos: usize,
+    deleted: usize,
+    inserted: usize,
+) -> Range<usize> {
+    let Some(span) = span else { return pos..pos + inserted };
+    let shift = |x: usize| {
+        if x <= pos {
+            x
+        } else if x >= pos + deleted {
+            x - deleted + inserted
+        } else {
+            pos
+        }
+    };
+    shift(span.start).min(pos)..shift(span.end).max(pos + inserted)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Buffer, Edit};
+
+    #[test]
+    fn test_undo_redo() {
+        let mut buffer = Buffer::from("hello world");
+        buffer.enable_undo();
+        buffer.set_cursor(5);
+        buffer.insert(" there");
+        buffer.undo_boundary();
+        buffer.delete_range(0, 6);
+        assert_eq!(buffer, "there world");
+
+        assert_eq!(buffer.undo(), Some(0..6));
+        assert_eq!(buffer, "hello there world");
+        assert_eq!(buffer.cursor(), 11);
+        assert_eq!(buffer.undo(), Some(5..5));
+        assert_eq!(buffer, "hello world");
+        assert_eq!(buffer.cursor(), 5);
+        assert_eq!(buffer.undo(), None);
+
+        assert_eq!(buffer.redo(), Some(5..11));
+        assert_eq!(buffer, "hello there world");
+        assert_eq!(buffer.redo(), Some(0..0));
+        assert_eq!(buffer, "there world");
+        assert_eq!(buffer.redo(), None);
+
+        // a new edit clears the redo history
+        buffer.undo();
+        buffer.insert("x");
+        assert_eq!(buffer.redo(), None);
+    }
+
+    #[test]
+    fn test_amalgamate() {
+        let mut buffer = Buffer::from("abc");
+        buffer.enable_undo();
+        buffer.set_cursor(3);
+        for chr in "def".chars() {
+            buffer.insert_char(chr);
+        }
+        buffer.delete_backwards(1);
+        buffer.delete_backwards(1);
+        buffer.set_cursor(0);
+        buffer.delete_forwards(1);
+        buffer.delete_forwards(1);
+        assert_eq!(buffer, "cd");
+        let entries = buffer.history().unwrap().entries();
+        assert_eq!(
+            entries,
+            [
+                Edit::Cursor(3),
+                Edit::Insert { pos: 3, text: "def".into() },
+                Edit::Delete { pos: 4, text: "ef".into() },
+                Edit::Cursor(0),
+                Edit::Delete { pos: 0, text: "ab".into() },
+            ]
+        );
+        assert_eq!(buffer.undo(), Some(0..3));
+        assert_eq!(buffer, "abc");
+        assert_eq!(buffer.cursor(), 3);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut buffer = Buffer::from("abc");
+        buffer.insert("x");
+        assert!(buffer.history().is_none());
+        assert_eq!(buffer.undo(), None);
+        assert_eq!(buffer, "xabc");
+    }
+}
@@ -0,0 +1,621 @@
+#![allow(clippy::must_use_candidate)]
+//! Text properties stored as intervals over char positions.
+//!
+//! The text is divided into runs of chars that share the same property list.
+//! The runs are kept in a treap ordered by position, where each node stores
+//! the length of its subtree, so looking up the run at a position is
+//! logarithmic. Adjacent runs with equal properties are always merged, which
+//! means that the end of a run is the next position where the properties
+//! change.
+use std::ops::Range;
+
+/// A list of properties and their values. Order does not matter when
+/// comparing two lists.
+#[derive(Debug, Clone)]
+pub struct PropertyList<K, V>(Vec<(K, V)>);
+
+impl<K, V> Default for PropertyList<K, V> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<K: PartialEq, V> PropertyList<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Set the value of `key`, replacing any previous value.
+    pub fn put(&mut self, key: K, value: V) {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.swap_remove(idx).1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K: PartialEq, V: PartialEq> PartialEq for PropertyList<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+/// Whether a property is inherited by text inserted next to it with
+/// [`TextProperties::insert_and_inherit`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Stickiness {
+    /// Inherited by text inserted before the char
+    pub front: bool,
+    /// Inherited by text inserted after the char
+    pub rear: bool,
+}
+
+impl Default for Stickiness {
+    /// Like Emacs, properties are rear-sticky by default.
+    fn default() -> Self {
+        Self { front: false, rear: true }
+    }
+}
+
+type Tree<K, V> = Option<Box<Node<K, V>>>;
+
+#[derive(Debug)]
+struct Node<K, V> {
+    /// The length of this run
+    len: usize,
+    /// The length of this subtree
+    total: usize,
+    priority: u32,
+    props: PropertyList<K, V>,
+    left: Tree<K, V>,
+    right: Tree<K, V>,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(len: usize, props: PropertyList<K, V>, priority: u32) -> Self {
+        Self { len, total: len, priority, props, left: None, right: None }
+    }
+
+    fn update(&mut self) {
+        self.total = self.len + total(&self.left) + total(&self.right);
+    }
+}
+
+fn total<K, V>(tree: &Tree<K, V>) -> usize {
+    tree.as_ref().map_or(0, |x| x.total)
+}
+
+fn total_opt<K, V>(tree: &Tree<K, V>) -> Option<usize> {
+    tree.as_ref().map(|x| x.total)
+}
+
+/// Find the run that contains `pos`. Returns the start of the run and the run.
+fn run_at<K, V>(tree: &Tree<K, V>, pos: usize) -> Option<(usize, &Node<K, V>)> {
+    let mut node = tree.as_deref()?;
+    let mut start = 0;
+    loop {
+        let left = total(&node.left);
+        if pos < start + left {
+            node = node.left.as_deref()?;
+        } else if pos < start + left + node.len {
+            return Some((start + left, node));
+        } else {
+            start += left + node.len;
+            node = node.right.as_deref()?;
+        }
+    }
+}
+
+fn merge<K, V>(left: Tree<K, V>, right: Tree<K, V>) -> Tree<K, V> {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// Visit every run in order.
+fn for_each_run<K, V>(tree: &Tree<K, V>, func: &mut impl FnMut(&Node<K, V>)) {
+    if let Some(node) = tree {
+        for_each_run(&node.left, func);
+        func(node);
+        for_each_run(&node.right, func);
+    }
+}
+
+/// Take every run out of the tree in order.
+fn into_runs<K, V>(tree: Tree<K, V>, runs: &mut Vec<Node<K, V>>) {
+    if let Some(mut node) = tree {
+        into_runs(node.left.take(), runs);
+        let right = node.right.take();
+        node.update();
+        runs.push(*node);
+        into_runs(right, runs);
+    }
+}
+
+/// The keys and values of the text properties of a [`Buffer`](crate::Buffer).
+/// They are opaque handles that the owner of the buffer maps to its own
+/// objects, the same way it does with [`MarkerId`](crate::MarkerId).
+pub type Property = u64;
+
+/// Text properties for a buffer. The owner is responsible for calling
+/// [`insert`](Self::insert) and [`delete`](Self::delete) whenever the text
+/// changes so that the properties stay in sync with it. A
+/// [`Buffer`](crate::Buffer) does this for the properties it stores.
+#[derive(Debug)]
+pub struct TextProperties<K, V> {
+    root: Tree<K, V>,
+    sticky: Vec<(K, Stickiness)>,
+    /// State for the priority generator
+    seed: u32,
+}
+
+impl<K, V> Default for TextProperties<K, V> {
+    fn default() -> Self {
+        Self { root: None, sticky: Vec::new(), seed: 0x9E37_79B9 }
+    }
+}
+
+impl<K, V> TextProperties<K, V>
+where
+    K: PartialEq + Clone,
+    V: PartialEq + Clone,
+{
+    /// Create text properties for `len` chars of text with no properties.
+    pub fn new(len: usize) -> Self {
+        let mut new = Self::default();
+        if len != 0 {
+            let priority = new.priority();
+            new.root = Some(Box::new(Node::new(len, PropertyList::new(), priority)));
+        }
+        new
+    }
+
+    /// The number of chars covered.
+    pub fn len(&self) -> usize {
+        total(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    fn priority(&mut self) -> u32 {
+        // xorshift
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        x
+    }
+
+    /// Split the tree so that the left side contains exactly `pos` chars.
+    fn split(&mut self, tree: Tree<K, V>, pos: usize) -> (Tree<K, V>, Tree<K, V>) {
+        let Some(mut node) = tree else { return (None, None) };
+        let left_len = total(&node.left);
+        if pos <= left_len {
+            let (left, right) = self.split(node.left.take(), pos);
+            node.left = right;
+            node.update();
+            (left, Some(node))
+        } else if pos >= left_len + node.len {
+            let (left, right) = self.split(node.right.take(), pos - left_len - node.len);
+            node.right = left;
+            node.update();
+            (Some(node), right)
+        } else {
+            // split this run in two
+            let offset = pos - left_len;
+            let priority = self.priority();
+            let rest = Box::new(Node::new(node.len - offset, node.props.clone(), priority));
+            node.len = offset;
+            let right = merge(Some(rest), node.right.take());
+            node.update();
+            (Some(node), right)
+        }
+    }
+
+    /// Merge two trees, combining the runs at the seam if they have the same
+    /// properties.
+    fn join(&mut self, left: Tree<K, V>, right: Tree<K, V>) -> Tree<K, V> {
+        let (Some(left_len), Some(right_len)) = (total_opt(&left), total_opt(&right)) else {
+            return merge(left, right);
+        };
+        let (last_start, last) = run_at(&left, left_len - 1).unwrap();
+        let (_, first) = run_at(&right, 0).unwrap();
+        if last.props != first.props {
+            return merge(left, right);
+        }
+        let first_len = first.len;
+        let (left, last) = self.split(left, last_start);
+        let (first, right) = self.split(right, first_len);
+        let (mut last, first) = (last.unwrap(), first.unwrap());
+        debug_assert!(last.left.is_none() && last.right.is_none());
+        debug_assert!(right_len >= first.len);
+        last.len += first.len;
+        last.update();
+        merge(merge(left, Some(last)), right)
+    }
+
+    /// Split out the runs in `range`, apply `func` to them, and put them back
+    /// together.
+    fn modify(&mut self, range: Range<usize>, mut func: impl FnMut(&mut PropertyList<K, V>)) {
+        let end = range.end.min(self.len());
+        let beg = range.start.min(end);
+        if beg == end {
+            return;
+        }
+        let root = self.root.take();
+        let (left, rest) = self.split(root, beg);
+        let (middle, right) = self.split(rest, end - beg);
+        let mut runs = Vec::new();
+        into_runs(middle, &mut runs);
+        let mut middle = None;
+        for mut run in runs {
+            func(&mut run.props);
+            middle = self.join(middle, Some(Box::new(run)));
+        }
+        let left = self.join(left, middle);
+        self.root = self.join(left, right);
+    }
+
+    /// Set the stickiness of `key` for [`insert_and_inherit`](Self::insert_and_inherit).
+    pub fn set_stickiness(&mut self, key: K, stickiness: Stickiness) {
+        match self.sticky.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, s)) => *s = stickiness,
+            None => self.sticky.push((key, stickiness)),
+        }
+    }
+
+    pub fn stickiness(&self, key: &K) -> Stickiness {
+        self.sticky
+            .iter()
+            .find(|(k, _)| k == key)
+            .map_or_else(Stickiness::default, |(_, s)| *s)
+    }
+
+    /// Insert `len` chars with `props` at `pos`.
+    fn insert_with(&mut self, pos: usize, len: usize, props: PropertyList<K, V>) {
+        if len == 0 {
+            return;
+        }
+        let pos = pos.min(self.len());
+        let root = self.root.take();
+        let (left, right) = self.split(root, pos);
+        let priority = self.priority();
+        let left = self.join(left, Some(Box::new(Node::new(len, props, priority))));
+        self.root = self.join(left, right);
+    }
+
+    /// Insert `len` chars without any properties at `pos`.
+    pub fn insert(&mut self, pos: usize, len: usize) {
+        self.insert_with(pos, len, PropertyList::new());
+    }
+
+    /// Insert `len` chars at `pos` that inherit the sticky properties of the
+    /// surrounding text. Rear-sticky properties of the preceding char take
+    /// precedence over front-sticky properties of the following char.
+    pub fn insert_and_inherit(&mut self, pos: usize, len: usize) {
+        let props = self.inherited(pos);
+        self.insert_with(pos, len, props);
+    }
+
+    /// The properties that text inserted at `pos` would inherit.
+    pub(crate) fn inherited(&self, pos: usize) -> PropertyList<K, V> {
+        let mut props = PropertyList::new();
+        if let Some((_, before)) = pos.checked_sub(1).and_then(|x| run_at(&self.root, x)) {
+            for (key, value) in before.props.iter() {
+                if self.stickiness(key).rear {
+                    props.put(key.clone(), value.clone());
+                }
+            }
+        }
+        if let Some((_, after)) = run_at(&self.root, pos) {
+            for (key, value) in after.props.iter() {
+                if self.stickiness(key).front && props.get(key).is_none() {
+                    props.put(key.clone(), value.clone());
+                }
+            }
+        }
+        props
+    }
+
+    /// Delete the chars in `beg..end`.
+    pub fn delete(&mut self, beg: usize, end: usize) {
+        let (beg, end) = if beg > end { (end, beg) } else { (beg, end) };
+        let end = end.min(self.len());
+        if beg >= end {
+            return;
+        }
+        let root = self.root.take();
+        let (left, rest) = self.split(root, beg);
+        let (_, right) = self.split(rest, end - beg);
+        self.root = self.join(left, right);
+    }
+
+    /// The properties of the char at `pos`.
+    pub fn properties_at(&self, pos: usize) -> Option<&PropertyList<K, V>> {
+        run_at(&self.root, pos).map(|(_, run)| &run.props)
+    }
+
+    /// The value of `key` for the char at `pos`.
+    pub fn get(&self, pos: usize, key: &K) -> Option<&V> {
+        self.properties_at(pos)?.get(key)
+    }
+
+    /// Set `key` to `value` for all chars in `range`.
+    pub fn put_property(&mut self, range: Range<usize>, key: &K, value: &V) {
+        self.modify(range, |props| props.put(key.clone(), value.clone()));
+    }
+
+    /// Remove `key` from all chars in `range`.
+    pub fn remove_property(&mut self, range: Range<usize>, key: &K) {
+        self.modify(range, |props| {
+            props.remove(key);
+        });
+    }
+
+    /// Replace the properties of all chars in `range` with `props`.
+    pub fn set_properties(&mut self, range: Range<usize>, props: &PropertyList<K, V>) {
+        self.modify(range, |x| *x = props.clone());
+    }
+
+    /// Return the first position after `pos` where the properties change, or
+    /// `None` if they don't change before the end of the text.
+    pub fn next_change(&self, pos: usize) -> Option<usize> {
+        let (start, run) = run_at(&self.root, pos)?;
+        let end = start + run.len;
+        (end < self.len()).then_some(end)
+    }
+
+    /// Return the last position before `pos` where the properties change, or
+    /// `None` if they don't change after the start of the text.
+    pub fn previous_change(&self, pos: usize) -> Option<usize> {
+        let (start, _) = run_at(&self.root, pos.checked_sub(1)?)?;
+        (start > 0).then_some(start)
+    }
+
+    /// Return the first position after `pos` where the value of `key`
+    /// changes, or `None` if it doesn't change before the end of the text.
+    pub fn next_single_change(&self, pos: usize, key: &K) -> Option<usize> {
+        let value = self.get(pos, key);
+        let mut pos = self.next_change(pos)?;
+        while self.get(pos, key) == value {
+            pos = self.next_change(pos)?;
+        }
+        Some(pos)
+    }
+
+    /// Return the last position before `pos` where the value of `key`
+    /// changes, or `None` if it doesn't change after the start of the text.
+    pub fn previous_single_change(&self, pos: usize, key: &K) -> Option<usize> {
+        let value = self.get(pos.checked_sub(1)?, key);
+        let mut pos = self.previous_change(pos)?;
+        while self.get(pos - 1, key) == value {
+            pos = self.previous_change(pos)?;
+        }
+        Some(pos)
+    }
+
+    /// Return the runs of text as ranges with their properties.
+    pub fn runs(&self) -> Vec<(Range<usize>, PropertyList<K, V>)> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for_each_run(&self.root, &mut |run| {
+            runs.push((start..start + run.len, run.props.clone()));
+            start += run.len;
+        });
+        runs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buffer;
+    use proptest::prelude::*;
+
+    fn props(pairs: &[(&'static str, i32)]) -> PropertyList<&'static str, i32> {
+        let mut list = PropertyList::new();
+        for (k, v) in pairs {
+            list.put(*k, *v);
+        }
+        list
+    }
+
+    #[test]
+    fn test_put_and_merge() {
+        let mut text = TextProperties::new(10);
+        text.put_property(2..5, &"face", &1);
+        assert_eq!(text.get(1, &"face"), None);
+        assert_eq!(text.get(2, &"face"), Some(&1));
+        assert_eq!(text.get(4, &"face"), Some(&1));
+        assert_eq!(text.get(5, &"face"), None);
+        text.put_property(5..7, &"face", &1);
+        assert_eq!(text.runs().len(), 3);
+        assert_eq!(text.next_change(0), Some(2));
+        assert_eq!(text.next_change(3), Some(7));
+        assert_eq!(text.next_change(8), None);
+        assert_eq!(text.previous_change(7), Some(2));
+        assert_eq!(text.previous_change(2), None);
+        text.remove_property(0..10, &"face");
+        assert_eq!(text.runs().len(), 1);
+    }
+
+    #[test]
+    fn test_single_change() {
+        let mut text = TextProperties::new(10);
+        text.put_property(0..6, &"face", &1);
+        text.put_property(3..8, &"invisible", &2);
+        assert_eq!(text.next_change(0), Some(3));
+        assert_eq!(text.next_single_change(0, &"face"), Some(6));
+        assert_eq!(text.next_single_change(0, &"invisible"), Some(3));
+        assert_eq!(text.next_single_change(6, &"face"), None);
+        assert_eq!(text.previous_single_change(10, &"face"), Some(6));
+        assert_eq!(text.previous_single_change(6, &"face"), None);
+        assert_eq!(text.previous_single_change(7, &"invisible"), Some(3));
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut text = TextProperties::new(10);
+        text.put_property(2..6, &"face", &1);
+        text.insert(4, 3);
+        assert_eq!(text.len(), 13);
+        assert_eq!(text.get(3, &"face"), Some(&1));
+        assert_eq!(text.get(4, &"face"), None);
+        assert_eq!(text.get(7, &"face"), Some(&1));
+        assert_eq!(text.get(9, &"face"), None);
+        text.delete(3, 8);
+        assert_eq!(text.len(), 8);
+        assert_eq!(
+            text.runs(),
+            [(0..2, props(&[])), (2..4, props(&[("face", 1)])), (4..8, props(&[]))]
+        );
+        text.delete(0, 100);
+        assert!(text.is_empty());
+        text.insert(0, 5);
+        assert_eq!(text.runs(), [(0..5, props(&[]))]);
+    }
+
+    #[test]
+    fn test_stickiness() {
+        let mut text = TextProperties::new(6);
+        text.put_property(0..3, &"face", &1);
+        text.put_property(3..6, &"keymap", &2);
+        text.set_stickiness("keymap", Stickiness { front: true, rear: false });
+        // between the two runs
+        text.insert_and_inherit(3, 2);
+        assert_eq!(text.properties_at(3), Some(&props(&[("face", 1), ("keymap", 2)])));
+        // at the end
+        text.insert_and_inherit(8, 1);
+        assert_eq!(text.properties_at(8), Some(&props(&[])));
+        // at the start
+        text.insert_and_inherit(0, 1);
+        assert_eq!(text.properties_at(0), Some(&props(&[])));
+        text.set_stickiness("face", Stickiness { front: true, rear: true });
+        text.insert_and_inherit(1, 1);
+        assert_eq!(text.properties_at(1), Some(&props(&[("face", 1)])));
+    }
+
+    #[test]
+    fn test_buffer_edits() {
+        const FACE: u64 = 1;
+        let mut buffer = Buffer::from("hello world");
+        buffer.enable_undo();
+        buffer.put_text_property(6..11, FACE, 7);
+        buffer.set_cursor(0);
+        buffer.insert(">> ");
+        buffer.undo_boundary();
+        assert_eq!(buffer.text_properties().get(8, &FACE), None);
+        assert_eq!(buffer.text_properties().get(9, &FACE), Some(&7));
+        buffer.delete_range(0, 6);
+        buffer.undo_boundary();
+        assert_eq!(buffer.text_properties().next_change(0), Some(3));
+        buffer.apply_edits(&[(0..0, "<"), (4..6, "Θ")]).unwrap();
+        assert_eq!(buffer, "<lo wΘld");
+        assert_eq!(buffer.text_properties().len(), 8);
+        assert_eq!(buffer.text_properties().get(3, &FACE), None);
+        assert_eq!(buffer.text_properties().get(4, &FACE), Some(&7));
+        assert_eq!(buffer.text_properties().get(5, &FACE), None);
+        assert_eq!(buffer.text_properties().get(6, &FACE), Some(&7));
+
+        buffer.undo();
+        assert_eq!(buffer, "lo world");
+        assert_eq!(buffer.text_properties().len(), 8);
+        assert_eq!(buffer.text_properties().previous_change(8), Some(6));
+        buffer.undo();
+        assert_eq!(buffer, ">> hello world");
+        assert_eq!(buffer.text_properties().len(), 14);
+        // the restored text has no properties
+        assert_eq!(buffer.text_properties().next_change(0), Some(9));
+        buffer.undo();
+        assert_eq!(buffer, "hello world");
+        assert_eq!(buffer.text_properties().next_change(0), Some(6));
+        assert_eq!(buffer.text_properties().get(6, &FACE), Some(&7));
+    }
+
+    #[test]
+    fn test_buffer_inherit() {
+        const FACE: u64 = 1;
+        let mut buffer = Buffer::from("hello");
+        buffer.put_text_property(0..5, FACE, 2);
+        buffer.set_cursor(5);
+        buffer.insert_and_inherit(" world");
+        buffer.insert("!");
+        assert_eq!(buffer.text_properties().next_single_change(0, &FACE), Some(11));
+        buffer.set_property_stickiness(FACE, Stickiness { front: false, rear: false });
+        buffer.insert_and_inherit("?");
+        assert_eq!(buffer.text_properties().get(11, &FACE), None);
+    }
+
+    fn check_properties(len: usize, edits: &[(u8, usize, usize, u8)]) {
+        let mut props = TextProperties::new(len);
+        let mut expect = vec![None; len];
+        for &(kind, beg, end, value) in edits {
+            let len = expect.len();
+            let (beg, end) = (beg % (len + 1), end % (len + 1));
+            let (beg, end) = (beg.min(end), beg.max(end));
+            match kind % 4 {
+                0 => {
+                    props.put_property(beg..end, &0, &value);
+                    expect[beg..end].fill(Some(value));
+                }
+                1 => {
+                    props.remove_property(beg..end, &0);
+                    expect[beg..end].fill(None);
+                }
+                2 => {
+                    props.insert(beg, end - beg);
+                    expect.splice(beg..beg, std::iter::repeat_n(None, end - beg));
+                }
+                _ => {
+                    props.delete(beg, end);
+                    expect.drain(beg..end);
+                }
+            }
+            assert_eq!(props.len(), expect.len());
+            for (pos, value) in expect.iter().enumerate() {
+                assert_eq!(props.get(pos, &0), value.as_ref());
+                let next = (pos + 1..expect.len()).find(|x| expect[*x] != *value);
+                assert_eq!(props.next_change(pos), next);
+                assert_eq!(props.next_single_change(pos, &0), next);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pt_properties(len in 0..50usize, ref edits in prop::collection::vec((any::<u8>(), any::<usize>(), any::<usize>(), 0..3u8), 0..20)) {
+            check_properties(len, edits);
+        }
+    }
+}
@@ -0,0 +1,770 @@
+#![allow(clippy::must_use_candidate)]
+//! A buffer for files that are too large to load.
+//!
+//! The file is memory-mapped read-only and never copied. Edits are kept in a
+//! piece table: the text is a list of pieces, each of which is a span of
+//! either the file or an append-only buffer of inserted text. The file is
+//! indexed one chunk at a time the first time a position inside of it is
+//! needed, so working near the start of a file never reads the rest of it.
+//! The pieces are kept in a B-tree that sums their sizes, so finding the piece
+//! at a position doesn't scan all of the pieces before it.
+//!
+//! The file is decoded like [`Buffer::insert_bytes`](crate::Buffer::insert_bytes):
+//! valid UTF-8 is kept as is and every other byte is a raw byte char. Chunks
+//! are split on char boundaries, so each piece can be decoded on its own.
+use crate::{encoding, DecodeError};
+use memmap2::Mmap;
+use smallvec::SmallVec;
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Write},
+    iter::Sum,
+    mem,
+    ops::{AddAssign, Range},
+    path::Path,
+};
+
+#[cfg(test)]
+const CHUNK_SIZE: usize = 16;
+#[cfg(not(test))]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Source {
+    /// The mapped file
+    File,
+    /// The inserted text, in the internal encoding
+    Added,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Piece {
+    source: Source,
+    start: usize,
+    end: usize,
+    chars: usize,
+    /// The number of newlines
+    lines: usize,
+}
+
+impl Piece {
+    fn summary(&self) -> Summary {
+        Summary { chars: self.chars, lines: self.lines }
+    }
+}
+
+/// The max number of children of a node in the piece tree
+const MAX: usize = 6;
+
+/// The summed size of a run of pieces.
+#[derive(Debug, Default, Copy, Clone)]
+struct Summary {
+    chars: usize,
+    lines: usize,
+}
+
+impl AddAssign for Summary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.chars += rhs.chars;
+        self.lines += rhs.lines;
+    }
+}
+
+impl Sum for Summary {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut acc, x| {
+            acc += x;
+            acc
+        })
+    }
+}
+
+/// The pieces of the buffer in order. Like the metric tree of a
+/// [`Buffer`](crate::Buffer), each internal node holds the summed size of each
+/// of its children, so finding the piece at a char or line position is
+/// logarithmic.
+///
+/// Nodes are not merged when they become underfull, only removed once they are
+/// empty, so the tree is never deeper than it was when it held the most
+/// pieces.
+#[derive(Debug, Default)]
+struct PieceTree {
+    root: Node,
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(SmallVec<[Piece; MAX]>),
+    Internal(SmallVec<[(Summary, Box<Node>); MAX]>),
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node::Leaf(SmallVec::new())
+    }
+}
+
+/// Return the index of the first of `items` that ends after char `pos`, and
+/// the number of chars before it. If there is none, the index is `len`.
+fn locate<T>(items: &[T], pos: usize, chars: impl Fn(&T) -> usize) -> (usize, usize) {
+    let mut before = 0;
+    for (idx, item) in items.iter().enumerate() {
+        if pos < before + chars(item) {
+            return (idx, before);
+        }
+        before += chars(item);
+    }
+    (items.len(), before)
+}
+
+/// Insert `item` into `items` at `idx`. If `items` is full it is split in
+/// half first, and the right half is returned.
+fn insert_or_split<T>(
+    items: &mut SmallVec<[T; MAX]>,
+    idx: usize,
+    item: T,
+) -> Option<SmallVec<[T; MAX]>> {
+    if items.len() < MAX {
+        items.insert(idx, item);
+        return None;
+    }
+    let middle = MAX / 2;
+    let mut right: SmallVec<_> = items.drain(middle..).collect();
+    if idx <= middle {
+        items.insert(idx, item);
+    } else {
+        right.insert(idx - middle, item);
+    }
+    Some(right)
+}
+
+impl PieceTree {
+    /// Find the piece that contains `needle` measured by `key`, along with the
+    /// size of the pieces before it.
+    fn search(&self, needle: usize, key: fn(&Summary) -> usize) -> Option<(Piece, Summary)> {
+        let mut before = Summary::default();
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(pieces) => {
+                    for piece in pieces {
+                        if needle < key(&before) + key(&piece.summary()) {
+                            return Some((*piece, before));
+                        }
+                        before += piece.summary();
+                    }
+                    return None;
+                }
+                Node::Internal(children) => {
+                    let mut found = None;
+                    for (sum, child) in children {
+                        if needle < key(&before) + key(sum) {
+                            found = Some(child);
+                            break;
+                        }
+                        before += *sum;
+                    }
+                    node = found?;
+                }
+            }
+        }
+    }
+
+    /// Insert `piece` at char position `pos`, which must be at the start or end
+    /// of a piece.
+    fn insert(&mut self, pos: usize, piece: Piece) {
+        if let Some(right) = self.root.insert(pos, piece) {
+            let left = Box::new(mem::take(&mut self.root));
+            let children = [(left.summary(), left), (right.summary(), right)];
+            self.root = Node::Internal(children.into_iter().collect());
+        }
+    }
+
+    /// Change the piece that contains char position `pos` with `func`.
+    fn update(&mut self, pos: usize, func: impl FnOnce(&mut Piece)) {
+        self.root.update(pos, func);
+    }
+
+    /// Remove the piece that starts at char position `pos`.
+    fn remove(&mut self, pos: usize) -> Piece {
+        let piece = self.root.remove(pos);
+        while let Node::Internal(children) = &mut self.root {
+            match children.len() {
+                0 => self.root = Node::default(),
+                1 => self.root = *children.pop().unwrap().1,
+                _ => break,
+            }
+        }
+        piece
+    }
+
+    /// Call `func` with each piece that ends after char position `pos` and
+    /// the position it starts at, in order, until it returns false.
+    fn visit(&self, pos: usize, mut func: impl FnMut(usize, &Piece) -> bool) {
+        self.root.visit(pos, 0, &mut func);
+    }
+}
+
+impl Node {
+    fn summary(&self) -> Summary {
+        match self {
+            Node::Leaf(pieces) => pieces.iter().map(Piece::summary).sum(),
+            Node::Internal(children) => children.iter().map(|(sum, _)| *sum).sum(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Node::Leaf(pieces) => pieces.is_empty(),
+            Node::Internal(children) => children.is_empty(),
+        }
+    }
+
+    /// Insert `piece` at `pos`. If the node has to be split, the new right
+    /// half is returned.
+    fn insert(&mut self, pos: usize, piece: Piece) -> Option<Box<Node>> {
+        match self {
+            Node::Leaf(pieces) => {
+                let (idx, before) = locate(pieces, pos, |piece| piece.chars);
+                debug_assert_eq!(before, pos, "insert inside of a piece");
+                let right = insert_or_split(pieces, idx, piece)?;
+                Some(Box::new(Node::Leaf(right)))
+            }
+            Node::Internal(children) => {
+                let (mut idx, mut before) = locate(children, pos, |(sum, _)| sum.chars);
+                if idx == children.len() {
+                    // insert at the end of the last child
+                    idx -= 1;
+                    before -= children[idx].0.chars;
+                }
+                let (sum, child) = &mut children[idx];
+                let new = child.insert(pos - before, piece);
+                *sum = child.summary();
+                let new = new?;
+                let right = insert_or_split(children, idx + 1, (new.summary(), new))?;
+                Some(Box::new(Node::Internal(right)))
+            }
+        }
+    }
+
+    fn update(&mut self, pos: usize, func: impl FnOnce(&mut Piece)) {
+        match self {
+            Node::Leaf(pieces) => {
+                let (idx, _) = locate(pieces, pos, |piece| piece.chars);
+                func(&mut pieces[idx]);
+            }
+            Node::Internal(children) => {
+                let (idx, before) = locate(children, pos, |(sum, _)| sum.chars);
+                let (sum, child) = &mut children[idx];
+                child.update(pos - before, func);
+                *sum = child.summary();
+            }
+        }
+    }
+
+    fn remove(&mut self, pos: usize) -> Piece {
+        match self {
+            Node::Leaf(pieces) => {
+                let (idx, before) = locate(pieces, pos, |piece| piece.chars);
+                debug_assert_eq!(before, pos, "remove from inside of a piece");
+                pieces.remove(idx)
+            }
+            Node::Internal(children) => {
+                let (idx, before) = locate(children, pos, |(sum, _)| sum.chars);
+                let (sum, child) = &mut children[idx];
+                let piece = child.remove(pos - before);
+                *sum = child.summary();
+                if child.is_empty() {
+                    children.remove(idx);
+                }
+                piece
+            }
+        }
+    }
+
+    fn visit(
+        &self,
+        pos: usize,
+        start: usize,
+        func: &mut impl FnMut(usize, &Piece) -> bool,
+    ) -> bool {
+        let mut start = start;
+        match self {
+            Node::Leaf(pieces) => {
+                for piece in pieces {
+                    if start + piece.chars > pos && !func(start, piece) {
+                        return false;
+                    }
+                    start += piece.chars;
+                }
+            }
+            Node::Internal(children) => {
+                for (sum, child) in children {
+                    if start + sum.chars > pos && !child.visit(pos, start, func) {
+                        return false;
+                    }
+                    start += sum.chars;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// A text buffer backed by a memory-mapped file. Unlike [`Buffer`], opening
+/// a file does not copy it or compute any metrics up front.
+///
+/// Methods that need a position in the file take `&mut self`, because they
+/// index the file up to that position if it has not been indexed yet.
+///
+/// [`Buffer`]: crate::Buffer
+#[derive(Debug)]
+pub struct LargeBuffer {
+    file: Mmap,
+    added: Vec<u8>,
+    pieces: PieceTree,
+    /// The start of the part of the file that has not been indexed. It comes
+    /// after all of the pieces.
+    indexed: usize,
+    /// The number of chars in the pieces
+    chars: usize,
+    /// The number of newlines in the pieces
+    lines: usize,
+    cursor: usize,
+}
+
+/// The number of chars in `bytes` read from the file.
+fn file_chars(bytes: &[u8]) -> usize {
+    bytes
+        .utf8_chunks()
+        .map(|chunk| encoding::count_chars(chunk.valid().as_bytes()) + chunk.invalid().len())
+        .sum()
+}
+
+/// The byte index of char `pos` in `bytes` read from the file.
+fn file_char_to_byte_idx(bytes: &[u8], mut pos: usize) -> usize {
+    let mut offset = 0;
+    for chunk in bytes.utf8_chunks() {
+        let valid = chunk.valid().as_bytes();
+        let chars = encoding::count_chars(valid);
+        if pos < chars {
+            return offset + encoding::char_to_byte_idx(valid, pos);
+        }
+        pos -= chars;
+        offset += valid.len();
+        if pos < chunk.invalid().len() {
+            return offset + pos;
+        }
+        pos -= chunk.invalid().len();
+        offset += chunk.invalid().len();
+    }
+    bytes.len()
+}
+
+impl LargeBuffer {
+    /// Map the file at `path`.
+    ///
+    /// The file must not be modified while the buffer is open. Like any memory
+    /// map, changes made by other processes show up in the buffer and can
+    /// corrupt it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be opened or mapped.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: The map is read-only and the caller is told not to modify
+        // the file. We never create references into it that outlive `self`.
+        let file = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            file,
+            added: Vec::new(),
+            pieces: PieceTree::default(),
+            indexed: 0,
+            chars: 0,
+            lines: 0,
+            cursor: 0,
+        })
+    }
+
+    /// The number of bytes of the file that have been indexed so far.
+    pub fn indexed_bytes(&self) -> usize {
+        self.indexed
+    }
+
+    /// The number of chars in the buffer. This indexes the whole file.
+    pub fn len_chars(&mut self) -> usize {
+        while self.index_chunk() {}
+        self.chars
+    }
+
+    /// The number of lines in the buffer. This indexes the whole file.
+    pub fn len_lines(&mut self) -> usize {
+        while self.index_chunk() {}
+        self.lines + 1
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Move the cursor to `pos`, clamped to the end of the buffer.
+    pub fn set_cursor(&mut self, pos: usize) {
+        self.cursor = self.index_to(pos);
+    }
+
+    /// The char position of the start of `line`, or the end of the buffer if
+    /// there are not that many lines.
+    pub fn line_to_char(&mut self, line: usize) -> usize {
+        while self.lines < line && self.index_chunk() {}
+        let Some(breaks) = line.checked_sub(1) else { return 0 };
+        let Some((piece, before)) = self.pieces.search(breaks, |sum| sum.lines) else {
+            return self.chars;
+        };
+        let bytes = self.bytes(&piece);
+        let idx = encoding::line_to_byte_idx(bytes, breaks - before.lines + 1);
+        before.chars + self.count_chars(piece.source, &bytes[..idx])
+    }
+
+    /// Insert `text` at the cursor and move the cursor after it.
+    pub fn insert(&mut self, text: &str) {
+        self.insert_internal(text.as_bytes());
+    }
+
+    /// Insert arbitrary bytes at the cursor. Bytes that are not part of valid
+    /// UTF-8 are inserted as raw byte chars.
+    pub fn insert_bytes(&mut self, bytes: &[u8]) {
+        self.insert_internal(&encoding::from_binary(bytes));
+    }
+
+    fn insert_internal(&mut self, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
+        let chars = encoding::count_chars(text);
+        let lines = encoding::count_breaks(text);
+        self.split_at(self.cursor);
+        let start = self.added.len();
+        self.added.extend_from_slice(text);
+        let end = self.added.len();
+        let prev = self.cursor.checked_sub(1);
+        match prev.and_then(|pos| self.pieces.search(pos, |sum| sum.chars)) {
+            // Typing extends the piece of the previous insertion
+            Some((prev, _)) if prev.source == Source::Added && prev.end == start => {
+                self.pieces.update(self.cursor - 1, |prev| {
+                    prev.end = end;
+                    prev.chars += chars;
+                    prev.lines += lines;
+                });
+            }
+            _ => {
+                let piece = Piece { source: Source::Added, start, end, chars, lines };
+                self.pieces.insert(self.cursor, piece);
+            }
+        }
+        self.chars += chars;
+        self.lines += lines;
+        self.cursor += chars;
+    }
+
+    /// Delete the text between the char positions `beg` and `end`, which can
+    /// be given in either order and are clamped to the end of the buffer.
+    pub fn delete_range(&mut self, beg: usize, end: usize) {
+        let (beg, end) = if beg > end { (end, beg) } else { (beg, end) };
+        let end = self.index_to(end);
+        let beg = beg.min(end);
+        if beg == end {
+            return;
+        }
+        self.split_at(beg);
+        self.split_at(end);
+        let mut deleted = 0;
+        while deleted < end - beg {
+            let piece = self.pieces.remove(beg);
+            deleted += piece.chars;
+            self.chars -= piece.chars;
+            self.lines -= piece.lines;
+        }
+        if self.cursor > end {
+            self.cursor -= end - beg;
+        } else if self.cursor > beg {
+            self.cursor = beg;
+        }
+    }
+
+    /// Read the text in the char `range` as bytes. Raw byte chars are returned
+    /// as the byte they represent.
+    pub fn read_binary(&mut self, range: Range<usize>) -> Vec<u8> {
+        let mut text = Vec::new();
+        self.for_each_slice(range, |source, bytes| match source {
+            Source::File => text.extend_from_slice(bytes),
+            Source::Added => text.extend_from_slice(&encoding::to_binary(bytes)),
+        });
+        text
+    }
+
+    /// Read the text in the char `range` as a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text contains raw bytes or other chars that are
+    /// not Unicode.
+    pub fn read_chars(&mut self, range: Range<usize>) -> Result<String, DecodeError> {
+        let mut text = Vec::new();
+        self.for_each_slice(range, |source, bytes| match source {
+            Source::File => text.extend_from_slice(&encoding::from_binary(bytes)),
+            Source::Added => text.extend_from_slice(bytes),
+        });
+        encoding::into_str(Cow::Owned(text)).map(Cow::into_owned)
+    }
+
+    /// Write the whole text to `writer` as bytes. Unedited parts of the file
+    /// are copied straight from the map.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from `writer`.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut result = Ok(());
+        self.pieces.visit(0, |_, piece| {
+            result = match piece.source {
+                Source::File => writer.write_all(self.bytes(piece)),
+                Source::Added => writer.write_all(&encoding::to_binary(self.bytes(piece))),
+            };
+            result.is_ok()
+        });
+        result?;
+        writer.write_all(&self.file[self.indexed..])?;
+        writer.flush()
+    }
+
+    /// Call `func` with the bytes of each piece that overlaps the char
+    /// `range`.
+    fn for_each_slice(&mut self, range: Range<usize>, mut func: impl FnMut(Source, &[u8])) {
+        let end = self.index_to(range.end);
+        let start = range.start.min(end);
+        self.pieces.visit(start, |pos, piece| {
+            if pos >= end {
+                return false;
+            }
+            let bytes = self.bytes(piece);
+            let beg = self.char_to_byte_idx(piece.source, bytes, start.saturating_sub(pos));
+            let end = self.char_to_byte_idx(piece.source, bytes, end - pos);
+            func(piece.source, &bytes[beg..end]);
+            true
+        });
+    }
+
+    /// Index the file until `pos` is inside the pieces. Returns `pos` clamped
+    /// to the end of the buffer.
+    fn index_to(&mut self, pos: usize) -> usize {
+        while self.chars < pos && self.index_chunk() {}
+        pos.min(self.chars)
+    }
+
+    /// Index the next chunk of the file. Returns false if the whole file has
+    /// been indexed.
+    fn index_chunk(&mut self) -> bool {
+        let start = self.indexed;
+        if start == self.file.len() {
+            return false;
+        }
+        let mut end = (start + CHUNK_SIZE).min(self.file.len());
+        if end < self.file.len() {
+            // Don't split a char
+            end -= encoding::incomplete_utf8_tail(&self.file[start..end]);
+        }
+        let bytes = &self.file[start..end];
+        let chars = file_chars(bytes);
+        let lines = encoding::count_breaks(bytes);
+        self.pieces
+            .insert(self.chars, Piece { source: Source::File, start, end, chars, lines });
+        self.indexed = end;
+        self.chars += chars;
+        self.lines += lines;
+        true
+    }
+
+    /// Split the pieces so that one starts at char `pos`, which must already
+    /// be indexed.
+    fn split_at(&mut self, pos: usize) {
+        debug_assert!(pos <= self.chars);
+        let Some((piece, before)) = self.pieces.search(pos, |sum| sum.chars) else { return };
+        let offset = pos - before.chars;
+        if offset == 0 {
+            return;
+        }
+        let bytes = self.bytes(&piece);
+        let split = self.char_to_byte_idx(piece.source, bytes, offset);
+        let lines = encoding::count_breaks(&bytes[..split]);
+        let front = Piece { end: piece.start + split, chars: offset, lines, ..piece };
+        let back = Piece {
+            start: piece.start + split,
+            chars: piece.chars - offset,
+            lines: piece.lines - lines,
+            ..piece
+        };
+        self.pieces.update(pos, |piece| *piece = front);
+        self.pieces.insert(pos, back);
+    }
+
+    fn bytes(&self, piece: &Piece) -> &[u8] {
+        match piece.source {
+            Source::File => &self.file[piece.start..piece.end],
+            Source::Added => &self.added[piece.start..piece.end],
+        }
+    }
+
+    #[allow(clippy::unused_self)]
+    fn count_chars(&self, source: Source, bytes: &[u8]) -> usize {
+        match source {
+            Source::File => file_chars(bytes),
+            Source::Added => encoding::count_chars(bytes),
+        }
+    }
+
+    #[allow(clippy::unused_self)]
+    fn char_to_byte_idx(&self, source: Source, bytes: &[u8], pos: usize) -> usize {
+        match source {
+            Source::File => file_char_to_byte_idx(bytes, pos),
+            Source::Added => encoding::char_to_byte_idx(bytes, pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buffer;
+    use proptest::prelude::*;
+
+    fn open(name: &str, data: &[u8]) -> LargeBuffer {
+        let path = std::env::temp_dir().join(format!("text-buffer-{}-{name}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let buffer = LargeBuffer::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_lazy_index() {
+        let data = "line Θ\n".repeat(100);
+        let mut buffer = open("lazy", data.as_bytes());
+        assert_eq!(buffer.indexed_bytes(), 0);
+        buffer.set_cursor(3);
+        buffer.insert("<>");
+        assert_eq!(buffer.read_chars(0..10).unwrap(), "lin<>e Θ\nl");
+        assert!(buffer.indexed_bytes() <= 2 * CHUNK_SIZE);
+        assert_eq!(buffer.line_to_char(2), 16);
+        assert!(buffer.indexed_bytes() < data.len());
+        assert_eq!(buffer.len_chars(), 702);
+        assert_eq!(buffer.len_lines(), 101);
+        assert_eq!(buffer.indexed_bytes(), data.len());
+    }
+
+    #[test]
+    fn test_edit() {
+        let data = "hello world, ΘΘΘΘΘΘΘΘΘΘ 😀😀😀😀 goodbye\n";
+        let mut buffer = open("edit", data.as_bytes());
+        let mut expect = data.to_owned();
+        buffer.set_cursor(6);
+        buffer.insert("big ");
+        buffer.insert("wide ");
+        expect.insert_str(6, "big wide ");
+        assert_eq!(buffer.cursor(), 15);
+        buffer.delete_range(30, 13);
+        expect.replace_range(13..38, "");
+        assert_eq!(buffer.cursor(), 13);
+        assert_eq!(buffer.read_chars(0..100).unwrap(), expect);
+        buffer.set_cursor(1000);
+        assert_eq!(buffer.cursor(), expect.chars().count());
+        let mut written = Vec::new();
+        buffer.write_to(&mut written).unwrap();
+        assert_eq!(written, expect.as_bytes());
+    }
+
+    #[test]
+    fn test_many_pieces() {
+        let data = "0123456789\n".repeat(40);
+        let mut buffer = open("pieces", data.as_bytes());
+        let mut expect: Vec<char> = data.chars().collect();
+        for i in 0..100 {
+            let pos = (i * 37) % (expect.len() + 1);
+            buffer.set_cursor(pos);
+            buffer.insert(if i % 3 == 0 { "Θ\n" } else { "ab" });
+            let text = if i % 3 == 0 { ['Θ', '\n'] } else { ['a', 'b'] };
+            expect.splice(pos..pos, text);
+            if i % 4 == 0 {
+                let end = (pos + i % 13).min(expect.len());
+                buffer.delete_range(pos / 2, end);
+                expect.drain(pos / 2..end);
+            }
+        }
+        let expect: String = expect.into_iter().collect();
+        assert_eq!(buffer.len_chars(), expect.chars().count());
+        assert_eq!(buffer.read_chars(0..expect.len()).unwrap(), expect);
+        assert_eq!(
+            buffer.read_chars(100..150).unwrap(),
+            expect.chars().skip(100).take(50).collect::<String>()
+        );
+        let mut line_start = 0;
+        for (line, text) in expect.split('\n').enumerate() {
+            assert_eq!(buffer.line_to_char(line), line_start, "line {line}");
+            line_start += text.chars().count() + 1;
+        }
+        buffer.delete_range(0, expect.len());
+        assert_eq!(buffer.len_chars(), 0);
+        assert_eq!(buffer.line_to_char(3), 0);
+    }
+
+    #[test]
+    fn test_binary() {
+        let data = b"ab\xFFcd\xCE\xB8\xCEef\xF0\x9F\x98\x80\x80\x80\x80\x80\x80gh";
+        let mut buffer = open("binary", data);
+        buffer.set_cursor(2);
+        buffer.insert_bytes(b"\xFE");
+        assert_eq!(buffer.len_chars(), 18);
+        assert!(buffer.read_chars(0..2).is_ok());
+        assert!(buffer.read_chars(0..3).is_err());
+        let mut expect = data.to_vec();
+        expect.insert(2, 0xFE);
+        assert_eq!(buffer.read_binary(0..18), expect);
+        buffer.delete_range(4, 6);
+        expect.drain(4..6);
+        let mut written = Vec::new();
+        buffer.write_to(&mut written).unwrap();
+        assert_eq!(written, expect);
+    }
+
+    fn check_large(data: &[u8], edits: &[(usize, usize, Vec<u8>)]) {
+        let mut large = open("proptest", data);
+        let mut buffer = Buffer::from(data);
+        for (pos, end, text) in edits {
+            let len = buffer.len_chars() + 1;
+            buffer.set_cursor(pos % len);
+            large.set_cursor(pos % len);
+            buffer.insert_bytes(text);
+            large.insert_bytes(text);
+            let len = buffer.len_chars() + 1;
+            buffer.delete_range(pos % len, end % len);
+            large.delete_range(pos % len, end % len);
+            assert_eq!(large.cursor(), buffer.cursor());
+            assert_eq!(large.line_to_char(end % len), buffer.line_to_char(end % len));
+        }
+        assert_eq!(large.len_chars(), buffer.len_chars());
+        assert_eq!(large.len_lines(), buffer.len_lines());
+        let text = buffer.read_binary(0..buffer.len());
+        assert_eq!(large.read_binary(0..buffer.len_chars()), text);
+        let mut written = Vec::new();
+        large.write_to(&mut written).unwrap();
+        assert_eq!(written, text);
+    }
+
+    proptest! {
+        #[test]
+        fn pt_large(ref data in prop::collection::vec(any::<u8>(), 0..100), ref edits in prop::collection::vec((any::<usize>(), any::<usize>(), prop::collection::vec(any::<u8>(), 0..5)), 0..10)) {
+            check_large(data, edits);
+        }
+    }
+}
@@ -16,7 +16,32 @@
     unreachable_pub,
     unused_lifetimes
 )]
+mod batch;
 mod buffer;
+mod coding;
+mod collab;
+mod column;
+mod encoding;
+mod history;
+mod intervals;
+mod large;
+mod marker;
 mod metric;
+mod overlay;
+mod search;
+mod segment;
+mod storage;
+mod view;
 
+pub use batch::EditMap;
 pub use buffer::*;
+pub use coding::{Coding, Eol};
+pub use collab::{Op, OpId, Replica};
+pub use encoding::{char_to_raw_byte, raw_byte_to_char, DecodeError, MAX_CHAR, MAX_UNICODE_CHAR};
+pub use history::{Edit, History};
+pub use intervals::{Property, PropertyList, Stickiness, TextProperties};
+pub use large::LargeBuffer;
+pub use marker::{InsertionType, MarkerId};
+pub use overlay::OverlayId;
+pub use search::{BuildError, MatchError, Regex};
+pub use view::ViewId;
@@ -0,0 +1,278 @@
+#![allow(clippy::must_use_candidate)]
+//! Markers are positions in the buffer that are automatically adjusted when
+//! text is inserted or deleted.
+//!
+//! Markers are stored the same way as the text; there is a gap, and markers
+//! before the gap store their char position while markers after the gap store
+//! their distance from the end of the buffer. An edit at the gap only has to
+//! touch the markers at the edit point or inside the deleted region. Moving
+//! the gap only touches the markers between the old and new gap position.
+
+/// A handle to a marker in a [`Buffer`](crate::Buffer).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MarkerId(usize);
+
+/// What a marker does when text is inserted at its position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum InsertionType {
+    /// Stay before the inserted text
+    #[default]
+    Stay,
+    /// Advance to after the inserted text
+    Advance,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Loc {
+    /// Before the gap, stored as the char position
+    Before(usize),
+    /// After the gap, stored as the number of chars to the end of the buffer
+    After(usize),
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Slot {
+    kind: InsertionType,
+    loc: Loc,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Markers {
+    slots: Vec<Option<Slot>>,
+    free: Vec<usize>,
+    /// ids of markers before the gap, sorted by position
+    before: Vec<usize>,
+    /// ids of markers after the gap, sorted by distance from the end. The
+    /// marker closest to the gap is last.
+    after: Vec<usize>,
+    /// The char position of the gap. Markers before the gap have a position
+    /// less than or equal to this, and markers after the gap have a greater
+    /// position.
+    gap: usize,
+}
+
+impl Markers {
+    fn slot(&self, id: usize) -> &Slot {
+        self.slots[id].as_ref().expect("marker was removed")
+    }
+
+    fn slot_mut(&mut self, id: usize) -> &mut Slot {
+        self.slots[id].as_mut().expect("marker was removed")
+    }
+
+    fn before_pos(&self, id: usize) -> usize {
+        match self.slot(id).loc {
+            Loc::Before(pos) => pos,
+            Loc::After(_) => unreachable!("marker {id} is not before the gap"),
+        }
+    }
+
+    fn after_dist(&self, id: usize) -> usize {
+        match self.slot(id).loc {
+            Loc::After(dist) => dist,
+            Loc::Before(_) => unreachable!("marker {id} is not after the gap"),
+        }
+    }
+
+    /// Return the position of the marker in a buffer that is `len` chars long.
+    pub(crate) fn position(&self, id: MarkerId, len: usize) -> usize {
+        match self.slot(id.0).loc {
+            Loc::Before(pos) => pos,
+            Loc::After(dist) => len - dist,
+        }
+    }
+
+    pub(crate) fn insertion_type(&self, id: MarkerId) -> InsertionType {
+        self.slot(id.0).kind
+    }
+
+    pub(crate) fn set_insertion_type(&mut self, id: MarkerId, kind: InsertionType) {
+        self.slot_mut(id.0).kind = kind;
+    }
+
+    pub(crate) fn create(&mut self, pos: usize, kind: InsertionType, len: usize) -> MarkerId {
+        let slot = Slot { kind, loc: Loc::Before(pos) };
+        let id = if let Some(id) = self.free.pop() {
+            self.slots[id] = Some(slot);
+            id
+        } else {
+            self.slots.push(Some(slot));
+            self.slots.len() - 1
+        };
+        self.place(id, pos, len);
+        MarkerId(id)
+    }
+
+    pub(crate) fn remove(&mut self, id: MarkerId) {
+        self.unplace(id.0);
+        self.slots[id.0] = None;
+        self.free.push(id.0);
+    }
+
+    pub(crate) fn set_position(&mut self, id: MarkerId, pos: usize, len: usize) {
+        self.unplace(id.0);
+        self.place(id.0, pos, len);
+    }
+
+    /// Put the marker into the sorted lists at `pos`.
+    fn place(&mut self, id: usize, pos: usize, len: usize) {
+        let pos = pos.min(len);
+        if pos <= self.gap {
+            let idx = self.before.partition_point(|x| self.before_pos(*x) <= pos);
+            self.slot_mut(id).loc = Loc::Before(pos);
+            self.before.insert(idx, id);
+        } else {
+            let dist = len - pos;
+            let idx = self.after.partition_point(|x| self.after_dist(*x) <= dist);
+            self.slot_mut(id).loc = Loc::After(dist);
+            self.after.insert(idx, id);
+        }
+    }
+
+    /// Take the marker out of the sorted lists.
+    fn unplace(&mut self, id: usize) {
+        let (list, idx) = match self.slot(id).loc {
+            Loc::Before(pos) => {
+                let start = self.before.partition_point(|x| self.before_pos(*x) < pos);
+                (&mut self.before, start)
+            }
+            Loc::After(dist) => {
+                let start = self.after.partition_point(|x| self.after_dist(*x) < dist);
+                (&mut self.after, start)
+            }
+        };
+        let offset = list[idx..].iter().position(|x| *x == id).unwrap();
+        list.remove(idx + offset);
+    }
+
+    /// Move the gap to `pos` in a buffer that is `len` chars long.
+    fn move_gap(&mut self, pos: usize, len: usize) {
+        if pos > self.gap {
+            while let Some(&id) = self.after.last() {
+                let marker_pos = len - self.after_dist(id);
+                if marker_pos > pos {
+                    break;
+                }
+                self.after.pop();
+                self.slot_mut(id).loc = Loc::Before(marker_pos);
+                self.before.push(id);
+            }
+        } else {
+            while let Some(&id) = self.before.last() {
+                let marker_pos = self.before_pos(id);
+                if marker_pos <= pos {
+                    break;
+                }
+                self.before.pop();
+                self.slot_mut(id).loc = Loc::After(len - marker_pos);
+                self.after.push(id);
+            }
+        }
+        self.gap = pos;
+    }
+
+    /// Adjust the markers for `size` chars inserted at `pos` in a buffer that
+    /// was `len` chars long before the insertion.
+    pub(crate) fn insert(&mut self, pos: usize, size: usize, len: usize) {
+        if size == 0 {
+            return;
+        }
+        self.move_gap(pos, len);
+        // Markers at the insertion point are at the end of the before list.
+        // Put the ones that stay first so the list remains sorted.
+        let start = self.before.partition_point(|x| self.before_pos(*x) < pos);
+        let mut at_point = self.before.split_off(start);
+        at_point.sort_by_key(|x| self.slot(*x).kind == InsertionType::Advance);
+        for &id in &at_point {
+            let slot = self.slot_mut(id);
+            if slot.kind == InsertionType::Advance {
+                slot.loc = Loc::Before(pos + size);
+            }
+        }
+        self.before.append(&mut at_point);
+        self.gap = pos + size;
+    }
+
+    /// Adjust the markers for the chars in `beg..end` being deleted from a
+    /// buffer that was `len` chars long before the deletion.
+    pub(crate) fn delete(&mut self, beg: usize, end: usize, len: usize) {
+        if beg == end {
+            return;
+        }
+        self.move_gap(end, len);
+        // Markers inside the deleted region are at the end of the before list
+        let start = self.before.partition_point(|x| self.before_pos(*x) <= beg);
+        for idx in start..self.before.len() {
+            let id = self.before[idx];
+            self.slot_mut(id).loc = Loc::Before(beg);
+        }
+        self.gap = beg;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Buffer, InsertionType};
+
+    #[test]
+    fn test_insert() {
+        let mut buffer = Buffer::from("hello world");
+        let stay = buffer.create_marker(5, InsertionType::Stay);
+        let advance = buffer.create_marker(5, InsertionType::Advance);
+        let before = buffer.create_marker(2, InsertionType::Advance);
+        let after = buffer.create_marker(8, InsertionType::Stay);
+        buffer.set_cursor(5);
+        buffer.insert(" there");
+        assert_eq!(buffer.marker_position(stay), 5);
+        assert_eq!(buffer.marker_position(advance), 11);
+        assert_eq!(buffer.marker_position(before), 2);
+        assert_eq!(buffer.marker_position(after), 14);
+        buffer.set_cursor(0);
+        buffer.insert("Θ");
+        assert_eq!(buffer.marker_position(stay), 6);
+        assert_eq!(buffer.marker_position(advance), 12);
+        assert_eq!(buffer.marker_position(before), 3);
+        assert_eq!(buffer.marker_position(after), 15);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut buffer = Buffer::from("hello world");
+        let start = buffer.create_marker(0, InsertionType::Stay);
+        let inside = buffer.create_marker(4, InsertionType::Stay);
+        let edge = buffer.create_marker(7, InsertionType::Advance);
+        let end = buffer.create_marker(11, InsertionType::Stay);
+        buffer.delete_range(2, 7);
+        assert_eq!(buffer, "heorld");
+        assert_eq!(buffer.marker_position(start), 0);
+        assert_eq!(buffer.marker_position(inside), 2);
+        assert_eq!(buffer.marker_position(edge), 2);
+        assert_eq!(buffer.marker_position(end), 6);
+        buffer.set_cursor(6);
+        buffer.delete_backwards(2);
+        assert_eq!(buffer.marker_position(end), 4);
+        buffer.set_cursor(0);
+        buffer.delete_forwards(1);
+        assert_eq!(buffer.marker_position(start), 0);
+        assert_eq!(buffer.marker_position(inside), 1);
+        assert_eq!(buffer.marker_position(end), 3);
+    }
+
+    #[test]
+    fn test_set_and_remove() {
+        let mut buffer = Buffer::from("hello world");
+        let marker = buffer.create_marker(3, InsertionType::Stay);
+        let other = buffer.create_marker(3, InsertionType::Stay);
+        buffer.set_marker(marker, 100);
+        assert_eq!(buffer.marker_position(marker), 11);
+        buffer.set_marker_insertion_type(marker, InsertionType::Advance);
+        assert_eq!(buffer.marker_insertion_type(marker), InsertionType::Advance);
+        buffer.set_cursor(11);
+        buffer.insert("!");
+        assert_eq!(buffer.marker_position(marker), 12);
+        buffer.remove_marker(marker);
+        buffer.set_cursor(0);
+        buffer.insert("x");
+        assert_eq!(buffer.marker_position(other), 4);
+    }
+}
@@ -4,6 +4,7 @@ use std::{
     iter::Sum,
     mem,
     ops::{Add, AddAssign, RangeBounds, Sub, SubAssign},
+    sync::Arc,
 };
 
 const MAX: usize = 6;
@@ -16,10 +17,10 @@ pub(crate) const MAX_LEAF: usize = 8000;
 
 type Metrics = SmallVec<[Metric; MAX]>;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct Internal {
     metrics: Metrics,
-    children: SmallVec<[Box<Node>; MAX]>,
+    children: SmallVec<[Arc<Node>; MAX]>,
 }
 
 impl Internal {
@@ -28,13 +29,13 @@ impl Internal {
         self.metrics.len()
     }
 
-    fn push(&mut self, child: Box<Node>) {
+    fn push(&mut self, child: Arc<Node>) {
         let metric = child.metrics();
         self.children.push(child);
         self.metrics.push(metric);
     }
 
-    fn insert(&mut self, idx: usize, child: Box<Node>) {
+    fn insert(&mut self, idx: usize, child: Arc<Node>) {
         let metric = child.metrics();
         self.children.insert(idx, child);
         self.metrics.insert(idx, metric);
@@ -57,7 +58,7 @@ impl Internal {
         (last, acc)
     }
 
-    fn insert_node(&mut self, idx: usize, new_child: Box<Node>) -> Option<Box<Node>> {
+    fn insert_node(&mut self, idx: usize, new_child: Arc<Node>) -> Option<Arc<Node>> {
         // update the metrics for the current child
         self.metrics[idx] = self.children[idx].metrics();
         // shift idx to the right
@@ -81,7 +82,7 @@ impl Internal {
             } else {
                 right.insert(idx - middle, new_child);
             }
-            Some(Box::new(Node::Internal(right)))
+            Some(Arc::new(Node::Internal(right)))
         }
     }
 
@@ -94,8 +95,7 @@ impl Internal {
             return false;
         }
 
-        #[allow(clippy::borrowed_box)]
-        let free_nodes = |x: &Box<Node>| x.len().saturating_sub(MIN);
+        let free_nodes = |x: &Arc<Node>| x.len().saturating_sub(MIN);
 
         let left_free = if idx == 0 { 0 } else { free_nodes(&self.children[idx - 1]) };
         let right_free = self.children.get(idx + 1).map_or(0, free_nodes);
@@ -120,7 +120,8 @@ impl Internal {
         let right_idx = if idx == 0 { idx + 1 } else { idx };
         let left_idx = right_idx - 1;
         let (left, right) = self.children.split_at_mut(right_idx);
-        let underfull = left[left_idx].merge_sibling(&mut right[0]);
+        let underfull =
+            Arc::make_mut(&mut left[left_idx]).merge_sibling(Arc::make_mut(&mut right[0]));
         self.children.remove(right_idx);
         let right_metric = self.metrics.remove(right_idx);
         self.metrics[left_idx] += right_metric;
@@ -133,9 +134,9 @@ impl Internal {
         let Some(left_idx) = idx.checked_sub(1) else { return true };
 
         while self.children[idx].len() < MIN {
-            let left_node = self.children[left_idx].steal(false);
+            let left_node = Arc::make_mut(&mut self.children[left_idx]).steal(false);
             if let Some((node, node_metric)) = left_node {
-                self.children[idx].merge_node(node, node_metric, 0);
+                Arc::make_mut(&mut self.children[idx]).merge_node(node, node_metric, 0);
                 self.metrics[idx] += node_metric;
                 self.metrics[left_idx] -= node_metric;
             } else {
@@ -153,9 +154,9 @@ impl Internal {
         }
 
         while self.children[idx].len() < MIN {
-            let right_node = self.children[right_idx].steal(true);
+            let right_node = Arc::make_mut(&mut self.children[right_idx]).steal(true);
             if let Some((node, node_metric)) = right_node {
-                let underfull_child = &mut self.children[idx];
+                let underfull_child = Arc::make_mut(&mut self.children[idx]);
                 let len = underfull_child.len();
                 underfull_child.merge_node(node, node_metric, len);
                 self.metrics[idx] += node_metric;
@@ -168,7 +169,7 @@ impl Internal {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct Leaf {
     metrics: Metrics,
 }
@@ -178,7 +179,7 @@ impl Leaf {
         self.metrics.len()
     }
 
-    fn insert_at(&mut self, idx: usize, pos: Metric, data: Metric) -> Option<Box<Node>> {
+    fn insert_at(&mut self, idx: usize, pos: Metric, data: Metric) -> Option<Arc<Node>> {
         if (self.metrics[idx].bytes + data.bytes) < MAX_LEAF {
             self.metrics[idx] += data;
             return None;
@@ -212,11 +213,11 @@ impl Leaf {
                 right_metrics.insert(idx - middle, new);
             }
             let right = Node::Leaf(Leaf { metrics: right_metrics });
-            Some(Box::new(right))
+            Some(Arc::new(right))
         }
     }
 
-    fn push(&mut self, metric: Metric) -> Option<Box<Node>> {
+    fn push(&mut self, metric: Metric) -> Option<Arc<Node>> {
         if self.len() < MAX {
             // If there is room in this node then insert the
             // leaf before the current one, splitting the
@@ -227,19 +228,25 @@ impl Leaf {
             assert_eq!(self.len(), MAX);
             // split this node into two and return the left one
             let right = Node::Leaf(Leaf { metrics: smallvec![metric] });
-            Some(Box::new(right))
+            Some(Arc::new(right))
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub(crate) struct BufferMetrics {
     root: Node,
 }
 
 impl BufferMetrics {
-    pub(crate) fn search_char(&self, chars: usize) -> (Metric, usize) {
-        self.root.search_char(chars)
+    /// Find the leaf chunk that contains position `needle`, where positions
+    /// are measured in `unit`. Returns the metric at the start of that chunk,
+    /// the metric of the chunk itself, and the remaining distance from the
+    /// start of the chunk to `needle`. If the position could be resolved
+    /// without a chunk, the metric at `needle` is returned with an empty chunk
+    /// and no remaining distance.
+    pub(crate) fn search(&self, needle: usize, unit: Unit) -> (Metric, Metric, usize) {
+        self.root.search_impl(needle, unit)
     }
 
     pub(crate) fn len(&self) -> Metric {
@@ -264,12 +271,12 @@ impl BufferMetrics {
         for (idx, metric) in metrics.enumerate() {
             leaf.push(metric);
             if leaf.len() == MAX || idx == split_idx {
-                nodes.push(Box::new(Node::Leaf(leaf)));
+                nodes.push(Arc::new(Node::Leaf(leaf)));
                 leaf = Leaf::default();
             }
         }
         if leaf.len() > 0 {
-            nodes.push(Box::new(Node::Leaf(leaf)));
+            nodes.push(Arc::new(Node::Leaf(leaf)));
         }
         // build each layer of internal nodes from the bottom up
         let mut next_level = Vec::with_capacity((nodes.len() / MAX) + 1);
@@ -290,7 +297,7 @@ impl BufferMetrics {
                 int.metrics.push(node.metrics());
                 int.children.push(node);
                 if int.len() == MAX || idx == split_idx {
-                    next_level.push(Box::new(Node::Internal(int)));
+                    next_level.push(Arc::new(Node::Internal(int)));
                     int = Internal::default();
                 }
             }
@@ -298,10 +305,10 @@ impl BufferMetrics {
             mem::swap(&mut nodes, &mut next_level);
 
             if int.len() > 0 {
-                nodes.push(Box::new(Node::Internal(int)));
+                nodes.push(Arc::new(Node::Internal(int)));
             }
         }
-        let root = *nodes.pop().unwrap_or_default();
+        let root = nodes.pop().map_or_else(Node::default, Arc::unwrap_or_clone);
         let built = Self { root };
         built.assert_invariants();
         built
@@ -388,7 +395,7 @@ impl BufferMetrics {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Node {
     Leaf(Leaf),
     Internal(Internal),
@@ -450,7 +457,7 @@ impl Node {
             match self {
                 Node::Internal(int) => {
                     let child = int.children.pop().unwrap();
-                    let _ = mem::replace(self, *child);
+                    let _ = mem::replace(self, Arc::unwrap_or_clone(child));
                 }
                 Node::Leaf(_) => break,
             }
@@ -461,7 +468,7 @@ impl Node {
         let len = self.metrics();
         assert!(pos.bytes <= len.bytes);
         if self.len() == 0 {
-            assert!(pos.bytes == 0);
+            assert_eq!(pos.bytes, 0);
             let Node::Leaf(leaf) = self else { unreachable!() };
             leaf.metrics.push(data);
             return;
@@ -472,18 +479,18 @@ impl Node {
             let left = mem::replace(self, Node::Internal(Internal::default()));
             let Node::Internal(int) = self else { unreachable!() };
             int.metrics = smallvec![left.metrics(), right.metrics()];
-            int.children = smallvec![Box::new(left), right];
+            int.children = smallvec![Arc::new(left), right];
         }
     }
 
-    fn insert_impl(&mut self, pos: Metric, data: Metric) -> Option<Box<Node>> {
+    fn insert_impl(&mut self, pos: Metric, data: Metric) -> Option<Arc<Node>> {
         self.assert_node_integrity();
         let (idx, metric) = self.search_char_pos(pos.chars);
         let offset = pos - metric;
         match self {
             Node::Leaf(leaf) => leaf.insert_at(idx, offset, data),
             Node::Internal(int) => {
-                if let Some(new) = int.children[idx].insert_impl(offset, data) {
+                if let Some(new) = Arc::make_mut(&mut int.children[idx]).insert_impl(offset, data) {
                     int.insert_node(idx, new)
                 } else {
                     int.metrics[idx] += data;
@@ -504,7 +511,7 @@ impl Node {
                     // delete range is in a single child
                     let idx = start_idx;
                     let metrics = &mut int.metrics;
-                    let fix_seam = int.children[idx].delete_impl(start, end);
+                    let fix_seam = Arc::make_mut(&mut int.children[idx]).delete_impl(start, end);
                     metrics[idx] -= end - start;
                     if int.children[idx].is_underfull() {
                         let fix = int.balance_node(idx);
@@ -528,8 +535,8 @@ impl Node {
                     let mut merge_left = false;
                     // has a left child
                     if start_delete > start_idx {
-                        fix_seam |=
-                            int.children[start_idx].delete_impl(start, int.metrics[start_idx]);
+                        fix_seam |= Arc::make_mut(&mut int.children[start_idx])
+                            .delete_impl(start, int.metrics[start_idx]);
                         int.metrics[start_idx] = start;
                         if int.children[start_idx].is_underfull() {
                             merge_left = true;
@@ -546,7 +553,8 @@ impl Node {
                             );
                             start_idx + 1
                         };
-                        fix_seam |= int.children[end_idx].delete_impl(Metric::default(), end);
+                        fix_seam |= Arc::make_mut(&mut int.children[end_idx])
+                            .delete_impl(Metric::default(), end);
                         int.metrics[end_idx] -= end;
                         // merge right child first so that the index of left is not changed
                         if int.children[end_idx].is_underfull() {
@@ -604,7 +612,7 @@ impl Node {
         ((start_idx.unwrap(), start), (end_idx.unwrap(), end))
     }
 
-    fn merge_node(&mut self, node: Option<Box<Node>>, metric: Metric, idx: usize) {
+    fn merge_node(&mut self, node: Option<Arc<Node>>, metric: Metric, idx: usize) {
         match (self, node) {
             // TODO don't recalculate the metric
             (Node::Internal(int), Some(node)) => int.insert(idx, node),
@@ -629,7 +637,7 @@ impl Node {
         }
     }
 
-    fn steal(&mut self, first: bool) -> Option<(Option<Box<Node>>, Metric)> {
+    fn steal(&mut self, first: bool) -> Option<(Option<Arc<Node>>, Metric)> {
         let idx = if first { 0 } else { self.len() - 1 };
         match self {
             Node::Internal(int) if int.len() > MIN => {
@@ -668,11 +676,11 @@ impl Node {
                 let on_seam = metric.chars == char_pos && idx > 0;
                 if on_seam {
                     let new_pos = int.metrics[idx - 1].chars;
-                    retry |= int.children[idx - 1].fix_seam(new_pos);
+                    retry |= Arc::make_mut(&mut int.children[idx - 1]).fix_seam(new_pos);
                 }
 
                 let new_pos = char_pos - metric.chars;
-                retry |= int.children[idx].fix_seam(new_pos);
+                retry |= Arc::make_mut(&mut int.children[idx]).fix_seam(new_pos);
                 // If one of the children was underfull we need to retry the
                 // loop to merge it again
                 if !retry {
@@ -714,12 +722,12 @@ impl Node {
                         children: int.children.drain(idx..).collect(),
                     };
                 } else {
-                    let right_node = int.children[idx].split(offset);
+                    let right_node = Arc::make_mut(&mut int.children[idx]).split(offset);
                     let right_metric = int.metrics[idx] - offset;
                     int.metrics[idx] = offset;
                     right = Internal {
                         metrics: smallvec![right_metric],
-                        children: smallvec![Box::new(right_node)],
+                        children: smallvec![Arc::new(right_node)],
                     };
                     right.take(int, idx + 1..);
                 }
@@ -739,7 +747,7 @@ impl Node {
                 let left = mem::replace(self, Node::Internal(Internal::default()));
                 let Node::Internal(int) = self else { unreachable!() };
                 int.metrics = smallvec![left.metrics(), right.metrics()];
-                int.children = smallvec![Box::new(left), right];
+                int.children = smallvec![Arc::new(left), right];
             }
         } else {
             let left = mem::replace(self, other);
@@ -750,12 +758,12 @@ impl Node {
                 let right = mem::replace(self, Node::Internal(Internal::default()));
                 let Node::Internal(int) = self else { unreachable!() };
                 int.metrics = smallvec![left.metrics(), right.metrics()];
-                int.children = smallvec![left, Box::new(right)];
+                int.children = smallvec![left, Arc::new(right)];
             }
-        };
+        }
     }
 
-    fn append_at_depth(&mut self, other: Self, depth: usize) -> Option<Box<Node>> {
+    fn append_at_depth(&mut self, other: Self, depth: usize) -> Option<Arc<Node>> {
         if depth == 0 {
             match (self, other) {
                 (Node::Leaf(left), Node::Leaf(mut right)) => {
@@ -763,7 +771,7 @@ impl Node {
                         left.metrics.extend(right.metrics.drain(..));
                         None
                     } else {
-                        Some(Box::new(Node::Leaf(right)))
+                        Some(Arc::new(Node::Leaf(right)))
                     }
                 }
                 (Node::Internal(left), Node::Internal(mut right)) => {
@@ -771,18 +779,19 @@ impl Node {
                         left.take(&mut right, ..);
                         None
                     } else {
-                        Some(Box::new(Node::Internal(right)))
+                        Some(Arc::new(Node::Internal(right)))
                     }
                 }
                 _ => unreachable!("siblings have different types"),
             }
         } else if let Node::Internal(int) = self {
-            match int.children.last_mut().unwrap().append_at_depth(other, depth - 1) {
+            let last = Arc::make_mut(int.children.last_mut().unwrap());
+            match last.append_at_depth(other, depth - 1) {
                 Some(new) if int.len() < MAX => {
                     int.push(new);
                     None
                 }
-                Some(new) => Some(Box::new(Node::Internal(Internal {
+                Some(new) => Some(Arc::new(Node::Internal(Internal {
                     metrics: smallvec![new.metrics()],
                     children: smallvec![new],
                 }))),
@@ -797,7 +806,7 @@ impl Node {
         }
     }
 
-    fn prepend_at_depth(&mut self, other: Self, depth: usize) -> Option<Box<Node>> {
+    fn prepend_at_depth(&mut self, other: Self, depth: usize) -> Option<Arc<Node>> {
         if depth == 0 {
             match (other, self) {
                 (Node::Leaf(mut left), Node::Leaf(right)) => {
@@ -806,7 +815,7 @@ impl Node {
                         *right = left;
                         None
                     } else {
-                        Some(Box::new(Node::Leaf(left)))
+                        Some(Arc::new(Node::Leaf(left)))
                     }
                 }
                 (Node::Internal(mut left), Node::Internal(right)) => {
@@ -815,18 +824,19 @@ impl Node {
                         *right = left;
                         None
                     } else {
-                        Some(Box::new(Node::Internal(left)))
+                        Some(Arc::new(Node::Internal(left)))
                     }
                 }
                 _ => unreachable!("siblings have different types"),
             }
         } else if let Node::Internal(int) = self {
-            match int.children.first_mut().unwrap().prepend_at_depth(other, depth - 1) {
+            let first = Arc::make_mut(int.children.first_mut().unwrap());
+            match first.prepend_at_depth(other, depth - 1) {
                 Some(new) if int.len() < MAX => {
                     int.insert(0, new);
                     None
                 }
-                Some(new) => Some(Box::new(Node::Internal(Internal {
+                Some(new) => Some(Arc::new(Node::Internal(Internal {
                     metrics: smallvec![new.metrics()],
                     children: smallvec![new],
                 }))),
@@ -841,40 +851,37 @@ impl Node {
         }
     }
 
-    fn search_char(&self, chars: usize) -> (Metric, usize) {
-        self.search_impl(chars, |x| x.chars)
-    }
-
-    fn search_impl(&self, needle: usize, getter: impl Fn(&Metric) -> usize) -> (Metric, usize) {
+    fn search_impl(&self, needle: usize, unit: Unit) -> (Metric, Metric, usize) {
         self.assert_node_integrity();
         let mut needle = needle;
         let mut sum = Metric::default();
         for (idx, metric) in self.metric_slice().iter().enumerate() {
-            // fast path if we happen get the exact position in the node
-            if needle == 0 {
+            // fast path if we happen get the exact position in the node. A line
+            // search needs the chunk holding the newline, so it can't stop here
+            if needle == 0 && unit != Unit::Lines {
                 break;
             }
-            let pos = getter(metric);
+            let pos = unit.of(metric);
             if needle < pos {
-                // if it is ascii then we can just calculate the offset
-                if metric.is_ascii() {
-                    let offset = Metric { bytes: needle, chars: needle };
-                    return (sum + offset, 0);
+                // if it is ascii then we can just calculate the offset, as long
+                // as there are no newlines that would need counting
+                if metric.is_ascii() && metric.lines == 0 {
+                    let offset = Metric { bytes: needle, chars: needle, lines: 0, utf16: needle };
+                    return (sum + offset, Metric::default(), 0);
                 }
-                let child_sum = match &self {
+                return match &self {
                     Node::Internal(int) => {
-                        let (metric, offset) = int.children[idx].search_impl(needle, getter);
-                        (sum + metric, offset)
+                        let (start, chunk, offset) = int.children[idx].search_impl(needle, unit);
+                        (sum + start, chunk, offset)
                     }
-                    Node::Leaf(_) => (sum, needle),
+                    Node::Leaf(_) => (sum, *metric, needle),
                 };
-                return child_sum;
             }
             sum += *metric;
             needle -= pos;
         }
         // we are beyond total size of the tree
-        (sum, needle)
+        (sum, Metric::default(), needle)
     }
 
     fn assert_node_integrity(&self) {
@@ -891,7 +898,7 @@ impl Node {
                 Node::Leaf(leaf) => {
                     assert!(leaf.metrics.len() <= MAX);
                 }
-            };
+            }
         }
     }
 
@@ -991,6 +998,10 @@ impl fmt::Display for Node {
 pub(crate) struct Metric {
     pub(crate) bytes: usize,
     pub(crate) chars: usize,
+    /// The number of newlines
+    pub(crate) lines: usize,
+    /// The number of UTF-16 code units
+    pub(crate) utf16: usize,
 }
 
 impl PartialEq for Metric {
@@ -998,6 +1009,8 @@ impl PartialEq for Metric {
         let eq = self.bytes == other.bytes;
         if eq {
             debug_assert_eq!(self.chars, other.chars);
+            debug_assert_eq!(self.lines, other.lines);
+            debug_assert_eq!(self.utf16, other.utf16);
         } else {
             debug_assert_ne!(self.chars, other.chars);
         }
@@ -1006,23 +1019,40 @@ impl PartialEq for Metric {
 }
 
 impl Metric {
-    fn is_ascii(&self) -> bool {
+    pub(crate) fn is_ascii(&self) -> bool {
         self.bytes == self.chars
     }
 }
 
+/// A unit that positions in the buffer can be measured in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Unit {
+    Bytes,
+    Chars,
+    Lines,
+    Utf16,
+}
+
+impl Unit {
+    pub(crate) fn of(self, metric: &Metric) -> usize {
+        match self {
+            Unit::Bytes => metric.bytes,
+            Unit::Chars => metric.chars,
+            Unit::Lines => metric.lines,
+            Unit::Utf16 => metric.utf16,
+        }
+    }
+}
+
 impl fmt::Display for Metric {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "b:{}, c:{}", self.bytes, self.chars)
+        write!(f, "b:{}, c:{}, l:{}, u:{}", self.bytes, self.chars, self.lines, self.utf16)
     }
 }
 
 impl Sum for Metric {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
-        iter.fold(Self::default(), |a, b| Self {
-            bytes: a.bytes + b.bytes,
-            chars: a.chars + b.chars,
-        })
+        iter.fold(Self::default(), |a, b| a + b)
     }
 }
 
@@ -1030,7 +1060,12 @@ impl Add for Metric {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Self { bytes: self.bytes + rhs.bytes, chars: self.chars + rhs.chars }
+        Self {
+            bytes: self.bytes + rhs.bytes,
+            chars: self.chars + rhs.chars,
+            lines: self.lines + rhs.lines,
+            utf16: self.utf16 + rhs.utf16,
+        }
     }
 }
 
@@ -1038,7 +1073,12 @@ impl Sub for Metric {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Self { bytes: self.bytes - rhs.bytes, chars: self.chars - rhs.chars }
+        Self {
+            bytes: self.bytes - rhs.bytes,
+            chars: self.chars - rhs.chars,
+            lines: self.lines - rhs.lines,
+            utf16: self.utf16 - rhs.utf16,
+        }
     }
 }
 
@@ -1046,6 +1086,8 @@ impl AddAssign for Metric {
     fn add_assign(&mut self, rhs: Self) {
         self.bytes += rhs.bytes;
         self.chars += rhs.chars;
+        self.lines += rhs.lines;
+        self.utf16 += rhs.utf16;
     }
 }
 
@@ -1053,6 +1095,8 @@ impl SubAssign for Metric {
     fn sub_assign(&mut self, rhs: Self) {
         self.bytes -= rhs.bytes;
         self.chars -= rhs.chars;
+        self.lines -= rhs.lines;
+        self.utf16 -= rhs.utf16;
     }
 }
 
@@ -1061,12 +1105,12 @@ mod test {
     use super::*;
 
     fn metric(x: usize) -> Metric {
-        Metric { bytes: x * 2, chars: x }
+        Metric { bytes: x * 2, chars: x, lines: x, utf16: x }
     }
 
     fn mock_search_char(root: &Node, needle: usize) -> Metric {
-        let (metric, offset) = root.search_char(needle);
-        Metric { bytes: metric.bytes + offset * 2, chars: metric.chars + offset }
+        let (metric, _, offset) = root.search_impl(needle, Unit::Chars);
+        metric + self::metric(offset)
     }
 
     struct TreeBuilderBasic {
@@ -1117,6 +1161,21 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_shared() {
+        let mut buffer = BufferMetrics::build(&mut TreeBuilderBasic { count: 100, step: 1 });
+        let copy = buffer.clone();
+        buffer.insert(metric(0), &mut TreeBuilderBasic { count: 1, step: 1 });
+        assert_eq!(buffer.len(), metric(101));
+        assert_eq!(copy.len(), metric(100));
+        // only the path to the edited leaf is copied
+        let (Node::Internal(new), Node::Internal(old)) = (&buffer.root, &copy.root) else {
+            panic!("tree should have internal nodes");
+        };
+        assert!(!Arc::ptr_eq(&new.children[0], &old.children[0]));
+        assert!(Arc::ptr_eq(new.children.last().unwrap(), old.children.last().unwrap()));
+    }
+
     #[test]
     fn test_search() {
         let builder = &mut TreeBuilderBasic { count: 20, step: 1 };
@@ -1139,6 +1198,26 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_search_ascii() {
+        // ascii chunks without newlines are resolved without finding the chunk
+        let chunk = Metric { bytes: 4, chars: 4, lines: 0, utf16: 4 };
+        let root = BufferMetrics::build(std::iter::repeat_n(chunk, 20));
+        for i in 0..80 {
+            let (start, chunk, offset) = root.search(i, Unit::Chars);
+            assert_eq!(start.bytes, i);
+            assert_eq!((chunk.bytes, offset), (0, 0));
+        }
+        // newlines still have to be counted in the chunk
+        let chunk = Metric { bytes: 4, chars: 4, lines: 1, utf16: 4 };
+        let root = BufferMetrics::build(std::iter::repeat_n(chunk, 20));
+        for i in 0..80 {
+            let (start, chunk, offset) = root.search(i, Unit::Chars);
+            assert_eq!(start.bytes + offset, i);
+            assert!(offset == 0 || chunk.bytes == 4);
+        }
+    }
+
     #[test]
     fn test_delete_range_leaf() {
         // shouldn't need more then a single leaf node
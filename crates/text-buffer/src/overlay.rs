@@ -0,0 +1,529 @@
+#![allow(clippy::must_use_candidate)]
+//! Overlays are ranges of the buffer that are adjusted as text is inserted and
+//! deleted, like a pair of markers.
+//!
+//! Overlays are stored in a treap ordered by their start position. Each node
+//! also tracks the largest end position in its subtree so that queries can skip
+//! subtrees that end before the position of interest. Shifting the overlays
+//! after an edit is done lazily; a node holds an offset that still needs to be
+//! applied to its children. This means an edit only has to touch the overlays
+//! that contain the edit point, plus a logarithmic number of nodes.
+use crate::InsertionType;
+use std::ops::Range;
+
+/// A handle to an overlay in a [`Buffer`](crate::Buffer).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct OverlayId(usize);
+
+type Link = Option<usize>;
+
+#[derive(Debug)]
+struct Node {
+    start: usize,
+    end: usize,
+    /// The largest end in this subtree
+    max_end: usize,
+    /// Offset that has not been applied to the children yet
+    lazy: isize,
+    /// Heap priority of the treap
+    heap: u32,
+    /// The priority of the overlay
+    priority: i64,
+    /// What the start does when text is inserted at it
+    front: InsertionType,
+    /// What the end does when text is inserted at it
+    rear: InsertionType,
+    left: Link,
+    right: Link,
+    parent: Link,
+}
+
+#[derive(Debug)]
+pub(crate) struct Overlays {
+    /// Overlay ids index into this
+    nodes: Vec<Option<Node>>,
+    free: Vec<usize>,
+    root: Link,
+    /// State for the heap priority generator
+    seed: u32,
+}
+
+impl Default for Overlays {
+    fn default() -> Self {
+        Self { nodes: Vec::new(), free: Vec::new(), root: None, seed: 0x9E37_79B9 }
+    }
+}
+
+impl Overlays {
+    fn node(&self, idx: usize) -> &Node {
+        self.nodes[idx].as_ref().expect("overlay was removed")
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node {
+        self.nodes[idx].as_mut().expect("overlay was removed")
+    }
+
+    fn heap_priority(&mut self) -> u32 {
+        // xorshift
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        x
+    }
+
+    /// Shift the whole subtree at `idx` by `offset`.
+    fn apply(&mut self, idx: usize, offset: isize) {
+        let node = self.node_mut(idx);
+        node.start = node.start.wrapping_add_signed(offset);
+        node.end = node.end.wrapping_add_signed(offset);
+        node.max_end = node.max_end.wrapping_add_signed(offset);
+        node.lazy += offset;
+    }
+
+    /// Push the pending offset of `idx` down to its children.
+    fn push(&mut self, idx: usize) {
+        let node = self.node_mut(idx);
+        let lazy = std::mem::take(&mut node.lazy);
+        if lazy != 0 {
+            let (left, right) = (node.left, node.right);
+            for child in [left, right].into_iter().flatten() {
+                self.apply(child, lazy);
+            }
+        }
+    }
+
+    /// Recalculate `max_end` of `idx` from its children.
+    fn update(&mut self, idx: usize) {
+        let node = self.node(idx);
+        let max_end = [node.left, node.right]
+            .into_iter()
+            .flatten()
+            .map(|x| self.node(x).max_end)
+            .fold(node.end, usize::max);
+        self.node_mut(idx).max_end = max_end;
+    }
+
+    fn set_left(&mut self, idx: usize, child: Link) {
+        self.node_mut(idx).left = child;
+        if let Some(child) = child {
+            self.node_mut(child).parent = Some(idx);
+        }
+    }
+
+    fn set_right(&mut self, idx: usize, child: Link) {
+        self.node_mut(idx).right = child;
+        if let Some(child) = child {
+            self.node_mut(child).parent = Some(idx);
+        }
+    }
+
+    fn set_root(&mut self, root: Link) {
+        if let Some(root) = root {
+            self.node_mut(root).parent = None;
+        }
+        self.root = root;
+    }
+
+    /// Split the tree into nodes that start before `pos` and the rest. If
+    /// `inclusive` is true, nodes that start at `pos` go on the left.
+    fn split(&mut self, tree: Link, pos: usize, inclusive: bool) -> (Link, Link) {
+        let Some(idx) = tree else { return (None, None) };
+        self.push(idx);
+        let node = self.node(idx);
+        let go_left = if inclusive { node.start <= pos } else { node.start < pos };
+        if go_left {
+            let (left, right) = self.split(node.right, pos, inclusive);
+            self.set_right(idx, left);
+            self.update(idx);
+            (Some(idx), right)
+        } else {
+            let (left, right) = self.split(node.left, pos, inclusive);
+            self.set_left(idx, right);
+            self.update(idx);
+            (left, Some(idx))
+        }
+    }
+
+    /// Join two trees where every node in `left` starts before or at the same
+    /// position as every node in `right`.
+    fn merge(&mut self, left: Link, right: Link) -> Link {
+        match (left, right) {
+            (None, tree) | (tree, None) => tree,
+            (Some(left), Some(right)) => {
+                if self.node(left).heap > self.node(right).heap {
+                    self.push(left);
+                    let child = self.merge(self.node(left).right, Some(right));
+                    self.set_right(left, child);
+                    self.update(left);
+                    Some(left)
+                } else {
+                    self.push(right);
+                    let child = self.merge(Some(left), self.node(right).left);
+                    self.set_left(right, child);
+                    self.update(right);
+                    Some(right)
+                }
+            }
+        }
+    }
+
+    /// Take all nodes out of the tree in order, leaving them unlinked.
+    fn drain(&mut self, tree: Link, out: &mut Vec<usize>) {
+        if let Some(idx) = tree {
+            self.push(idx);
+            let node = self.node_mut(idx);
+            let (left, right) = (node.left.take(), node.right.take());
+            node.max_end = node.end;
+            self.drain(left, out);
+            out.push(idx);
+            self.drain(right, out);
+        }
+    }
+
+    /// Add unlinked nodes, sorted by start, between `left` and `right`.
+    fn rebuild(&mut self, left: Link, nodes: &[usize], right: Link) -> Link {
+        let mut tree = left;
+        for &idx in nodes {
+            tree = self.merge(tree, Some(idx));
+        }
+        self.merge(tree, right)
+    }
+
+    /// Put an unlinked node into the tree.
+    fn link(&mut self, idx: usize) {
+        let root = self.root.take();
+        let (left, right) = self.split(root, self.node(idx).start, true);
+        let root = self.rebuild(left, &[idx], right);
+        self.set_root(root);
+    }
+
+    /// Take a node out of the tree, applying any pending offsets to it.
+    fn unlink(&mut self, idx: usize) {
+        let start = self.range(OverlayId(idx)).start;
+        let root = self.root.take();
+        let (left, rest) = self.split(root, start, false);
+        let (middle, right) = self.split(rest, start, true);
+        let mut nodes = Vec::new();
+        self.drain(middle, &mut nodes);
+        nodes.retain(|x| *x != idx);
+        let root = self.rebuild(left, &nodes, right);
+        self.set_root(root);
+        self.node_mut(idx).parent = None;
+    }
+
+    pub(crate) fn create(
+        &mut self,
+        range: Range<usize>,
+        front: InsertionType,
+        rear: InsertionType,
+        len: usize,
+    ) -> OverlayId {
+        let (start, end) = clamp(range, len);
+        let node = Node {
+            start,
+            end,
+            max_end: end,
+            lazy: 0,
+            heap: self.heap_priority(),
+            priority: 0,
+            front,
+            rear,
+            left: None,
+            right: None,
+            parent: None,
+        };
+        let idx = if let Some(idx) = self.free.pop() {
+            self.nodes[idx] = Some(node);
+            idx
+        } else {
+            self.nodes.push(Some(node));
+            self.nodes.len() - 1
+        };
+        self.link(idx);
+        OverlayId(idx)
+    }
+
+    pub(crate) fn remove(&mut self, id: OverlayId) {
+        self.unlink(id.0);
+        self.nodes[id.0] = None;
+        self.free.push(id.0);
+    }
+
+    pub(crate) fn set_range(&mut self, id: OverlayId, range: Range<usize>, len: usize) {
+        self.unlink(id.0);
+        let (start, end) = clamp(range, len);
+        let node = self.node_mut(id.0);
+        node.start = start;
+        node.end = end;
+        node.max_end = end;
+        self.link(id.0);
+    }
+
+    /// Return the range of the overlay.
+    pub(crate) fn range(&self, id: OverlayId) -> Range<usize> {
+        let node = self.node(id.0);
+        let mut offset = 0;
+        let mut parent = node.parent;
+        while let Some(idx) = parent {
+            let node = self.node(idx);
+            offset += node.lazy;
+            parent = node.parent;
+        }
+        node.start.wrapping_add_signed(offset)..node.end.wrapping_add_signed(offset)
+    }
+
+    pub(crate) fn priority(&self, id: OverlayId) -> i64 {
+        self.node(id.0).priority
+    }
+
+    pub(crate) fn set_priority(&mut self, id: OverlayId, priority: i64) {
+        self.node_mut(id.0).priority = priority;
+    }
+
+    pub(crate) fn insertion_types(&self, id: OverlayId) -> (InsertionType, InsertionType) {
+        let node = self.node(id.0);
+        (node.front, node.rear)
+    }
+
+    /// Update the ends of overlays in `tree` that reach `pos` or beyond.
+    fn adjust_ends(&mut self, tree: Link, pos: usize, func: &impl Fn(&mut Node)) {
+        let Some(idx) = tree else { return };
+        if self.node(idx).max_end < pos {
+            return;
+        }
+        self.push(idx);
+        let node = self.node(idx);
+        let (left, right) = (node.left, node.right);
+        self.adjust_ends(left, pos, func);
+        self.adjust_ends(right, pos, func);
+        let node = self.node_mut(idx);
+        if node.end >= pos {
+            func(node);
+        }
+        self.update(idx);
+    }
+
+    /// Adjust the overlays for `size` chars inserted at `pos`.
+    pub(crate) fn insert(&mut self, pos: usize, size: usize) {
+        if size == 0 || self.root.is_none() {
+            return;
+        }
+        let root = self.root.take();
+        let (before, rest) = self.split(root, pos, false);
+        let (at, after) = self.split(rest, pos, true);
+        if let Some(after) = after {
+            self.apply(after, size.cast_signed());
+        }
+        // Overlays starting before the insertion only need their end updated
+        self.adjust_ends(before, pos, &|node| {
+            if node.end > pos || node.rear == InsertionType::Advance {
+                node.end += size;
+            }
+        });
+        let mut nodes = Vec::new();
+        self.drain(at, &mut nodes);
+        for &idx in &nodes {
+            let node = self.node_mut(idx);
+            if node.end > pos || node.rear == InsertionType::Advance {
+                node.end += size;
+            }
+            if node.front == InsertionType::Advance {
+                node.start = (pos + size).min(node.end);
+            }
+            node.max_end = node.end;
+        }
+        nodes.sort_by_key(|x| self.node(*x).start);
+        let root = self.rebuild(before, &nodes, after);
+        self.set_root(root);
+    }
+
+    /// Adjust the overlays for the chars in `beg..end` being deleted.
+    pub(crate) fn delete(&mut self, beg: usize, end: usize) {
+        if beg == end || self.root.is_none() {
+            return;
+        }
+        let size = end - beg;
+        let root = self.root.take();
+        let (before, rest) = self.split(root, beg, false);
+        let (inside, after) = self.split(rest, end, false);
+        if let Some(after) = after {
+            self.apply(after, -size.cast_signed());
+        }
+        let shift = |x: usize| if x >= end { x - size } else { x.min(beg) };
+        self.adjust_ends(before, beg + 1, &|node| node.end = shift(node.end));
+        let mut nodes = Vec::new();
+        self.drain(inside, &mut nodes);
+        for &idx in &nodes {
+            let node = self.node_mut(idx);
+            node.start = beg;
+            node.end = shift(node.end);
+            node.max_end = node.end;
+        }
+        let root = self.rebuild(before, &nodes, after);
+        self.set_root(root);
+    }
+
+    /// Call `func` on every overlay in `tree` that ends at or after `min_end`
+    /// and starts at or before `max_start`. `offset` is the pending offset from
+    /// the ancestors of `tree`.
+    fn visit(
+        &self,
+        tree: Link,
+        offset: isize,
+        min_end: usize,
+        max_start: usize,
+        func: &mut impl FnMut(usize, usize, usize),
+    ) {
+        let Some(idx) = tree else { return };
+        let node = self.node(idx);
+        if node.max_end.wrapping_add_signed(offset) < min_end {
+            return;
+        }
+        let start = node.start.wrapping_add_signed(offset);
+        let end = node.end.wrapping_add_signed(offset);
+        let child_offset = offset + node.lazy;
+        self.visit(node.left, child_offset, min_end, max_start, func);
+        if start <= max_start {
+            if end >= min_end {
+                func(idx, start, end);
+            }
+            self.visit(node.right, child_offset, min_end, max_start, func);
+        }
+    }
+
+    fn sort_by_priority(&self, ids: &mut [OverlayId]) {
+        ids.sort_by_key(|x| (std::cmp::Reverse(self.priority(*x)), x.0));
+    }
+
+    /// The overlays that contain the char at `pos`, sorted by decreasing
+    /// priority.
+    pub(crate) fn at(&self, pos: usize) -> Vec<OverlayId> {
+        let mut ids = Vec::new();
+        self.visit(self.root, 0, pos + 1, pos, &mut |idx, _, _| ids.push(OverlayId(idx)));
+        self.sort_by_priority(&mut ids);
+        ids
+    }
+
+    /// The overlays that overlap `range`, sorted by decreasing priority. Empty
+    /// overlays are included if they are inside the range or at its start. If
+    /// `at_end` is true, empty overlays at the end of the range are included as
+    /// well.
+    pub(crate) fn overlapping(&self, range: Range<usize>, at_end: bool) -> Vec<OverlayId> {
+        let Range { start: beg, end } = range;
+        let mut ids = Vec::new();
+        self.visit(self.root, 0, beg, end, &mut |idx, start, stop| {
+            let overlaps = start < end && stop > beg;
+            let empty = start == stop && (start < end || start == beg || (at_end && start == end));
+            if overlaps || empty {
+                ids.push(OverlayId(idx));
+            }
+        });
+        self.sort_by_priority(&mut ids);
+        ids
+    }
+
+    /// Return the first position after `pos` where an overlay starts or ends.
+    pub(crate) fn next_change(&self, pos: usize) -> Option<usize> {
+        // The closest start after pos
+        let mut next = None;
+        let mut tree = self.root;
+        let mut offset = 0;
+        while let Some(idx) = tree {
+            let node = self.node(idx);
+            let start = node.start.wrapping_add_signed(offset);
+            offset += node.lazy;
+            if start > pos {
+                next = Some(start);
+                tree = node.left;
+            } else {
+                tree = node.right;
+            }
+        }
+        // Any overlay that ends after pos and does not start after it contains
+        // pos.
+        self.visit(self.root, 0, pos + 1, pos, &mut |_, _, end| {
+            next = Some(next.map_or(end, |x: usize| x.min(end)));
+        });
+        next
+    }
+}
+
+fn clamp(range: Range<usize>, len: usize) -> (usize, usize) {
+    let (start, end) = (range.start.min(len), range.end.min(len));
+    if start > end {
+        (end, start)
+    } else {
+        (start, end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Buffer, InsertionType};
+
+    #[test]
+    fn test_insert() {
+        let mut buffer = Buffer::from("hello world");
+        let word = buffer.create_overlay(0..5, InsertionType::Stay, InsertionType::Stay);
+        let advance = buffer.create_overlay(0..5, InsertionType::Advance, InsertionType::Advance);
+        let empty = buffer.create_overlay(5..5, InsertionType::Stay, InsertionType::Advance);
+        let after = buffer.create_overlay(6..11, InsertionType::Stay, InsertionType::Stay);
+        buffer.set_cursor(5);
+        buffer.insert("!!");
+        assert_eq!(buffer.overlay_range(word), 0..5);
+        assert_eq!(buffer.overlay_range(advance), 0..7);
+        assert_eq!(buffer.overlay_range(empty), 5..7);
+        assert_eq!(buffer.overlay_range(after), 8..13);
+        buffer.set_cursor(0);
+        buffer.insert("Θ");
+        assert_eq!(buffer.overlay_range(word), 0..6);
+        assert_eq!(buffer.overlay_range(advance), 1..8);
+        assert_eq!(buffer.overlay_range(after), 9..14);
+        buffer.set_cursor(3);
+        buffer.insert("x");
+        assert_eq!(buffer.overlay_range(word), 0..7);
+        assert_eq!(buffer.overlay_range(empty), 7..9);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut buffer = Buffer::from("hello world");
+        let first = buffer.create_overlay(0..5, InsertionType::Stay, InsertionType::Stay);
+        let middle = buffer.create_overlay(4..7, InsertionType::Stay, InsertionType::Stay);
+        let last = buffer.create_overlay(6..11, InsertionType::Stay, InsertionType::Stay);
+        buffer.delete_range(3, 8);
+        assert_eq!(buffer, "helrld");
+        assert_eq!(buffer.overlay_range(first), 0..3);
+        assert_eq!(buffer.overlay_range(middle), 3..3);
+        assert_eq!(buffer.overlay_range(last), 3..6);
+        buffer.remove_overlay(middle);
+        buffer.delete_range(0, 6);
+        assert_eq!(buffer.overlay_range(first), 0..0);
+        assert_eq!(buffer.overlay_range(last), 0..0);
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut buffer = Buffer::from("hello world");
+        let outer = buffer.create_overlay(0..11, InsertionType::Stay, InsertionType::Stay);
+        let inner = buffer.create_overlay(2..4, InsertionType::Stay, InsertionType::Stay);
+        let empty = buffer.create_overlay(6..6, InsertionType::Stay, InsertionType::Stay);
+        let end = buffer.create_overlay(11..11, InsertionType::Stay, InsertionType::Stay);
+        buffer.set_overlay_priority(inner, 10);
+        assert_eq!(buffer.overlays_at(3), [inner, outer]);
+        assert_eq!(buffer.overlays_at(4), [outer]);
+        assert_eq!(buffer.overlays_in(4..8), [outer, empty]);
+        assert_eq!(buffer.overlays_in(6..6), [outer, empty]);
+        assert_eq!(buffer.overlays_in(8..11), [outer, end]);
+        assert_eq!(buffer.next_overlay_change(0), Some(2));
+        assert_eq!(buffer.next_overlay_change(2), Some(4));
+        assert_eq!(buffer.next_overlay_change(4), Some(6));
+        assert_eq!(buffer.next_overlay_change(6), Some(11));
+        assert_eq!(buffer.next_overlay_change(11), None);
+        buffer.move_overlay(inner, 7..9);
+        assert_eq!(buffer.overlays_at(3), [outer]);
+        assert_eq!(buffer.overlays_at(8), [inner, outer]);
+    }
+}
@@ -0,0 +1,326 @@
+#![allow(clippy::must_use_candidate)]
+#![allow(clippy::missing_panics_doc)]
+//! Searching the text of a buffer in place.
+//!
+//! The text is never copied out of the buffer. Literal searches run
+//! [`memmem`] over each side of the gap, plus a small window around the gap
+//! for matches that cross it. Regex searches drive a lazy DFA one byte at a
+//! time over both sides of the gap, so they work the same way no matter where
+//! the gap is.
+use crate::Snapshot;
+use memchr::memmem;
+use regex_automata::{
+    hybrid::{
+        dfa::{Cache, DFA},
+        CacheError, LazyStateID, StartError,
+    },
+    nfa::thompson,
+    util::{start, syntax},
+    Anchored, MatchKind,
+};
+use std::{ops::Range, sync::Mutex};
+
+pub use regex_automata::{hybrid::BuildError, MatchError};
+
+/// A compiled regular expression that can be used to search a buffer.
+#[derive(Debug)]
+pub struct Regex {
+    /// Finds the end of the leftmost match
+    forward: DFA,
+    /// Finds the start of a match, scanning backwards from its end
+    reverse: DFA,
+    /// Caches for the forward and reverse DFA
+    caches: Mutex<(Cache, Cache)>,
+}
+
+impl Regex {
+    /// Compile `pattern`. Like in Emacs, `^` and `$` match at the start and
+    /// end of lines.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regex.
+    pub fn new(pattern: &str) -> Result<Self, Box<BuildError>> {
+        let syntax = syntax::Config::new().multi_line(true);
+        let forward = DFA::builder()
+            .configure(
+                DFA::config().match_kind(MatchKind::LeftmostFirst).unicode_word_boundary(true),
+            )
+            .syntax(syntax)
+            .build(pattern)?;
+        let reverse = DFA::builder()
+            .configure(DFA::config().match_kind(MatchKind::All).unicode_word_boundary(true))
+            .syntax(syntax)
+            .thompson(thompson::Config::new().reverse(true))
+            .build(pattern)?;
+        let caches = Mutex::new((forward.create_cache(), reverse.create_cache()));
+        Ok(Self { forward, reverse, caches })
+    }
+}
+
+/// Get the start state of `dfa` for a search with the byte `before` preceding
+/// the search position. `pos` is used for error reporting.
+fn start_state(
+    dfa: &DFA,
+    cache: &mut Cache,
+    anchored: Anchored,
+    before: Option<u8>,
+    pos: usize,
+) -> Result<LazyStateID, MatchError> {
+    let config = start::Config::new().anchored(anchored).look_behind(before);
+    dfa.start_state(cache, &config).map_err(|err| match err {
+        StartError::Quit { byte } => MatchError::quit(byte, pos),
+        StartError::UnsupportedAnchored { mode } => MatchError::unsupported_anchored(mode),
+        _ => MatchError::gave_up(pos),
+    })
+}
+
+/// Run `dfa` over `bytes` and return how many bytes were consumed before the
+/// last match, or before the first match if `earliest` is true. `after` is the
+/// byte following the input, which is needed to resolve look-around
+/// assertions at the end. `pos` maps a number of consumed bytes to a buffer
+/// position for error reporting.
+fn scan(
+    dfa: &DFA,
+    cache: &mut Cache,
+    mut sid: LazyStateID,
+    bytes: impl Iterator<Item = u8>,
+    after: Option<u8>,
+    earliest: bool,
+    pos: impl Fn(usize) -> usize,
+) -> Result<Option<usize>, MatchError> {
+    let mut found = None;
+    let mut at = 0;
+    for byte in bytes {
+        sid = dfa.next_state(cache, sid, byte).map_err(|_| MatchError::gave_up(pos(at)))?;
+        if sid.is_tagged() {
+            // Matches are delayed by one byte, so this match ended before the
+            // current byte
+            if sid.is_match() {
+                found = Some(at);
+                if earliest {
+                    return Ok(found);
+                }
+            } else if sid.is_dead() {
+                return Ok(found);
+            } else if sid.is_quit() {
+                return Err(MatchError::quit(byte, pos(at)));
+            }
+        }
+        at += 1;
+    }
+    sid = match after {
+        Some(byte) => dfa.next_state(cache, sid, byte),
+        None => dfa.next_eoi_state(cache, sid),
+    }
+    .map_err(|_: CacheError| MatchError::gave_up(pos(at)))?;
+    if sid.is_match() {
+        found = Some(at);
+    }
+    Ok(found)
+}
+
+impl Snapshot {
+    /// Convert a char range into a byte range, clamped to the buffer.
+    fn search_bounds(&self, range: &Range<usize>) -> (usize, usize) {
+        let end = self.clamp(range.end);
+        let start = self.clamp(range.start).min(end);
+        (self.char_to_byte(start), self.char_to_byte(end))
+    }
+
+    fn to_char_range(&self, start: usize, end: usize) -> Range<usize> {
+        self.byte_to_char(start)..self.byte_to_char(end)
+    }
+
+    /// Find the first occurrence of `needle` in the char `range`. Returns the
+    /// char range of the match.
+    pub fn search_forward(&self, needle: &str, range: Range<usize>) -> Option<Range<usize>> {
+        let (beg, end) = self.search_bounds(&range);
+        let needle = needle.as_bytes();
+        let overlap = needle.len().saturating_sub(1);
+        // the bytes before the current piece that a match could start in
+        let mut tail = Vec::new();
+        let mut pos = beg;
+        for piece in self.pieces(beg..end) {
+            // check for a match that crosses into this piece
+            let head = &piece[..overlap.min(piece.len())];
+            let seam = [&tail, head].concat();
+            if let Some(idx) = memmem::find(&seam, needle).filter(|idx| *idx < tail.len()) {
+                let start = pos - tail.len() + idx;
+                return Some(self.to_char_range(start, start + needle.len()));
+            }
+            if let Some(idx) = memmem::find(piece, needle) {
+                return Some(self.to_char_range(pos + idx, pos + idx + needle.len()));
+            }
+            tail.extend_from_slice(&piece[piece.len().saturating_sub(overlap)..]);
+            tail.drain(..tail.len().saturating_sub(overlap));
+            pos += piece.len();
+        }
+        // an empty needle matches an empty range
+        needle.is_empty().then(|| self.to_char_range(beg, beg))
+    }
+
+    /// Find the last occurrence of `needle` in the char `range`. Returns the
+    /// char range of the match.
+    pub fn search_backward(&self, needle: &str, range: Range<usize>) -> Option<Range<usize>> {
+        let (beg, end) = self.search_bounds(&range);
+        let needle = needle.as_bytes();
+        let overlap = needle.len().saturating_sub(1);
+        // the bytes after the current piece that a match could end in
+        let mut head = Vec::new();
+        let mut pos = end;
+        for piece in self.pieces(beg..end).rev() {
+            pos -= piece.len();
+            // check for a match that crosses out of this piece
+            let tail = &piece[piece.len().saturating_sub(overlap)..];
+            let seam = [tail, &head].concat();
+            if let Some(idx) =
+                memmem::rfind(&seam, needle).filter(|idx| idx + needle.len() > tail.len())
+            {
+                let start = pos + piece.len() - tail.len() + idx;
+                return Some(self.to_char_range(start, start + needle.len()));
+            }
+            if let Some(idx) = memmem::rfind(piece, needle) {
+                return Some(self.to_char_range(pos + idx, pos + idx + needle.len()));
+            }
+            head.splice(0..0, piece.iter().take(overlap).copied());
+            head.truncate(overlap);
+        }
+        // an empty needle matches an empty range
+        needle.is_empty().then(|| self.to_char_range(end, end))
+    }
+
+    /// Find the leftmost match of `regex` in the char `range`. Returns the
+    /// char range of the match. Text outside of `range` is only used to
+    /// resolve look-around assertions like `\b` and `$`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search has to give up, which happens when
+    /// `regex` uses a Unicode word boundary and the text is not ASCII.
+    pub fn re_search_forward(
+        &self,
+        regex: &Regex,
+        range: Range<usize>,
+    ) -> Result<Option<Range<usize>>, MatchError> {
+        let (beg, end) = self.search_bounds(&range);
+        let mut caches = regex.caches.lock().unwrap();
+        let (fwd_cache, rev_cache) = &mut *caches;
+        let before = self.bytes_at(beg).prev();
+        // find the end of the match
+        let bytes = self.pieces(beg..end).flatten().copied();
+        let after = self.bytes_at(end).next();
+        let sid = start_state(&regex.forward, fwd_cache, Anchored::No, before, beg)?;
+        let Some(len) = scan(&regex.forward, fwd_cache, sid, bytes, after, false, |x| beg + x)?
+        else {
+            return Ok(None);
+        };
+        let match_end = beg + len;
+        // scan backwards from the end to find the start
+        let bytes = self.pieces(beg..match_end).flatten().copied().rev();
+        let after = self.bytes_at(match_end).next();
+        let sid = start_state(&regex.reverse, rev_cache, Anchored::Yes, after, match_end)?;
+        let pos = |x| match_end - x;
+        let len = scan(&regex.reverse, rev_cache, sid, bytes, before, false, pos)?
+            .expect("reverse search should find the start of the match");
+        Ok(Some(self.to_char_range(match_end - len, match_end)))
+    }
+
+    /// Find the match of `regex` in the char `range` that starts last. Returns
+    /// the char range of the match. Text outside of `range` is only used to
+    /// resolve look-around assertions like `\b` and `$`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search has to give up, which happens when
+    /// `regex` uses a Unicode word boundary and the text is not ASCII.
+    pub fn re_search_backward(
+        &self,
+        regex: &Regex,
+        range: Range<usize>,
+    ) -> Result<Option<Range<usize>>, MatchError> {
+        let (beg, end) = self.search_bounds(&range);
+        let mut caches = regex.caches.lock().unwrap();
+        let (fwd_cache, rev_cache) = &mut *caches;
+        let after = self.bytes_at(end).next();
+        // find the start of the last match
+        let bytes = self.pieces(beg..end).flatten().copied().rev();
+        let before = self.bytes_at(beg).prev();
+        let sid = start_state(&regex.reverse, rev_cache, Anchored::No, after, end)?;
+        let Some(len) = scan(&regex.reverse, rev_cache, sid, bytes, before, true, |x| end - x)?
+        else {
+            return Ok(None);
+        };
+        let match_start = end - len;
+        // scan forward from the start to find the end
+        let bytes = self.pieces(match_start..end).flatten().copied();
+        let before = self.bytes_at(match_start).prev();
+        let sid = start_state(&regex.forward, fwd_cache, Anchored::Yes, before, match_start)?;
+        let pos = |x| match_start + x;
+        let len = scan(&regex.forward, fwd_cache, sid, bytes, after, false, pos)?
+            .expect("forward search should find the end of the match");
+        Ok(Some(self.to_char_range(match_start, match_start + len)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Regex;
+    use crate::Buffer;
+
+    /// Create a buffer with the gap at char position `gap`.
+    fn buffer_with_gap(text: &str, gap: usize) -> Buffer {
+        let mut buffer = Buffer::from(text);
+        buffer.set_cursor(gap);
+        buffer.insert("Θ");
+        buffer.delete_backwards(1);
+        buffer
+    }
+
+    #[test]
+    fn test_literal() {
+        let text = "foo bar foo baz";
+        for gap in 0..=text.len() {
+            let buffer = buffer_with_gap(text, gap);
+            assert_eq!(buffer.search_forward("foo", 0..15), Some(0..3), "gap {gap}");
+            assert_eq!(buffer.search_forward("foo", 1..15), Some(8..11), "gap {gap}");
+            assert_eq!(buffer.search_forward("foo", 1..10), None, "gap {gap}");
+            assert_eq!(buffer.search_backward("foo", 0..15), Some(8..11), "gap {gap}");
+            assert_eq!(buffer.search_backward("foo", 0..10), Some(0..3), "gap {gap}");
+            assert_eq!(buffer.search_backward("ba", 0..15), Some(12..14), "gap {gap}");
+            assert_eq!(buffer.search_forward("", 4..15), Some(4..4), "gap {gap}");
+            assert_eq!(buffer.search_backward("", 4..15), Some(15..15), "gap {gap}");
+        }
+        let buffer = buffer_with_gap("aΘbΘc", 2);
+        assert_eq!(buffer.search_forward("Θc", 0..5), Some(3..5));
+        assert_eq!(buffer.search_backward("aΘ", 0..5), Some(0..2));
+    }
+
+    #[test]
+    fn test_regex() {
+        let text = "one two three\nfour";
+        let word = Regex::new(r"t\w+").unwrap();
+        let eol = Regex::new(r"\w+$").unwrap();
+        let alt = Regex::new(r"fo|four").unwrap();
+        for gap in 0..=text.len() {
+            let buffer = buffer_with_gap(text, gap);
+            let fwd = |re, range| buffer.re_search_forward(re, range).unwrap();
+            let bwd = |re, range| buffer.re_search_backward(re, range).unwrap();
+            assert_eq!(fwd(&word, 0..18), Some(4..7), "gap {gap}");
+            assert_eq!(fwd(&word, 5..18), Some(8..13), "gap {gap}");
+            assert_eq!(fwd(&word, 8..11), Some(8..11), "gap {gap}");
+            assert_eq!(bwd(&word, 0..18), Some(8..13), "gap {gap}");
+            assert_eq!(bwd(&word, 0..7), Some(4..7), "gap {gap}");
+            // `$` only matches at the real end of a line
+            assert_eq!(fwd(&eol, 0..11), None, "gap {gap}");
+            assert_eq!(fwd(&eol, 0..18), Some(8..13), "gap {gap}");
+            assert_eq!(bwd(&eol, 0..18), Some(17..18), "gap {gap}");
+            // leftmost first
+            assert_eq!(fwd(&alt, 0..18), Some(14..16), "gap {gap}");
+        }
+        let buffer = buffer_with_gap("αβγ δεζ", 5);
+        let greek = Regex::new(r"[α-ω]+").unwrap();
+        assert_eq!(buffer.re_search_forward(&greek, 2..7).unwrap(), Some(2..3));
+        assert_eq!(buffer.re_search_backward(&greek, 0..7).unwrap(), Some(6..7));
+    }
+}
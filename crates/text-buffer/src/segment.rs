@@ -0,0 +1,341 @@
+#![allow(clippy::must_use_candidate)]
+#![allow(clippy::missing_panics_doc)]
+//! Grapheme cluster and word boundaries.
+//!
+//! Newlines always end both a grapheme cluster and a word, so boundaries only
+//! depend on the line around a position. Chars that are not Unicode, like raw
+//! bytes, are treated as a cluster and a word of their own, which splits a
+//! line into runs of Unicode text that are segmented independently.
+//!
+//! Boundaries are found in a window of text around the position instead of
+//! the whole line. A space or tab followed by a printable ASCII char is
+//! always a boundary, and text on one side of it never affects the boundaries
+//! on the other, so the window is cut at the nearest such point on either
+//! side, and widened if there is none. Grapheme clusters are found by feeding
+//! the pieces of the window to a [`GraphemeCursor`] as it asks for them, so
+//! the text is never copied. Word segmentation needs the window as one
+//! string, so it is copied if it is split in storage.
+use crate::Snapshot;
+use std::ops::Range;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
+
+/// The number of chars on either side of a position that are searched for
+/// the edges of its window before the window is widened.
+#[cfg(test)]
+const WINDOW: usize = 3;
+#[cfg(not(test))]
+const WINDOW: usize = 128;
+
+impl Snapshot {
+    /// Return the char position of the next grapheme cluster boundary after
+    /// `pos`, or `None` if `pos` is at the end of the accessible region.
+    pub fn next_grapheme_boundary(&self, pos: usize) -> Option<usize> {
+        self.next_boundary(pos, |run, pos| self.grapheme_boundary(&run, pos, true))
+    }
+
+    /// Return the char position of the previous grapheme cluster boundary
+    /// before `pos`, or `None` if `pos` is at the start of the accessible
+    /// region.
+    pub fn prev_grapheme_boundary(&self, pos: usize) -> Option<usize> {
+        self.prev_boundary(pos, |run, pos| self.grapheme_boundary(&run, pos, false))
+    }
+
+    /// Return the char position of the next word boundary after `pos`, or
+    /// `None` if `pos` is at the end of the accessible region. Boundaries are
+    /// the ones defined by Unicode, so there is one at both ends of each word
+    /// and of each run of spaces or punctuation between them.
+    pub fn next_word_boundary(&self, pos: usize) -> Option<usize> {
+        self.next_boundary(pos, |run, pos| {
+            let text = self.read(run.clone()).expect("run should be Unicode");
+            let offset = pos - run.start;
+            let next = text.split_word_bound_indices().map(|(idx, _)| idx).find(|x| *x > offset);
+            next.map_or(run.end, |idx| run.start + idx)
+        })
+    }
+
+    /// Return the char position of the previous word boundary before `pos`,
+    /// or `None` if `pos` is at the start of the accessible region.
+    pub fn prev_word_boundary(&self, pos: usize) -> Option<usize> {
+        self.prev_boundary(pos, |run, pos| {
+            let text = self.read(run.clone()).expect("run should be Unicode");
+            let offset = pos - run.start;
+            let bounds = text.split_word_bound_indices().map(|(idx, _)| idx);
+            run.start + bounds.take_while(|x| *x < offset).last().unwrap_or(0)
+        })
+    }
+
+    /// Find the boundary after the char position `pos` with `find`, which is
+    /// given the window around the char after `pos` and the byte position of
+    /// `pos`.
+    fn next_boundary(
+        &self,
+        pos: usize,
+        find: impl FnOnce(Range<usize>, usize) -> usize,
+    ) -> Option<usize> {
+        let pos = self.clamp(pos);
+        if pos == self.zv() {
+            return None;
+        }
+        let byte = self.char_to_byte(pos);
+        match self.unicode_run(pos) {
+            Some(run) => Some(self.byte_to_char(find(run, byte))),
+            None => Some(pos + 1),
+        }
+    }
+
+    /// Find the boundary before the char position `pos` with `find`, which is
+    /// given the window around the char before `pos` and the byte position
+    /// of `pos`.
+    fn prev_boundary(
+        &self,
+        pos: usize,
+        find: impl FnOnce(Range<usize>, usize) -> usize,
+    ) -> Option<usize> {
+        let pos = self.clamp(pos);
+        if pos == self.begv() {
+            return None;
+        }
+        let byte = self.char_to_byte(pos);
+        match self.unicode_run(pos - 1) {
+            Some(run) => Some(self.byte_to_char(find(run, byte))),
+            None => Some(pos - 1),
+        }
+    }
+
+    /// Return the byte range of the window of Unicode chars around the char
+    /// at `pos` that can be segmented on its own. It is limited to the line,
+    /// the accessible region, and the run of chars that are Unicode. Returns
+    /// `None` if the char at `pos` is not Unicode.
+    fn unicode_run(&self, pos: usize) -> Option<Range<usize>> {
+        let (begv, zv) = self.byte_bounds();
+        let byte = self.char_to_byte(pos);
+        let first = self.pieces(byte..zv).next().and_then(|x| x.first().copied());
+        let mut window = WINDOW;
+        loop {
+            let lower = self.char_to_byte(pos.saturating_sub(window).max(self.begv()));
+            let upper = self.char_to_byte((pos + 1 + window).min(self.zv()));
+            // Find the first edge after the char at pos
+            let mut end = None;
+            let mut prev = None;
+            let mut idx = byte;
+            'outer: for chunk in self.pieces(byte..upper).flat_map(<[u8]>::utf8_chunks) {
+                for &b in chunk.valid().as_bytes() {
+                    if idx > byte && is_cut(prev, b) {
+                        end = Some(idx);
+                        break 'outer;
+                    }
+                    idx += 1;
+                    if b == b'\n' {
+                        end = Some(idx);
+                        break 'outer;
+                    }
+                    prev = Some(b);
+                }
+                if !chunk.invalid().is_empty() {
+                    end = Some(idx);
+                    break;
+                }
+            }
+            if end == Some(byte) {
+                return None;
+            }
+            let end = end.or_else(|| (upper == zv).then_some(zv));
+            // Find the last edge before the char at pos
+            let mut start = (lower == begv).then_some(lower);
+            let mut prev = None;
+            let mut idx = lower;
+            for chunk in self.pieces(lower..byte).flat_map(<[u8]>::utf8_chunks) {
+                for &b in chunk.valid().as_bytes() {
+                    if is_cut(prev, b) {
+                        start = Some(idx);
+                    }
+                    idx += 1;
+                    if b == b'\n' {
+                        start = Some(idx);
+                    }
+                    prev = Some(b);
+                }
+                if !chunk.invalid().is_empty() {
+                    idx += chunk.invalid().len();
+                    start = Some(idx);
+                    prev = None;
+                }
+            }
+            if first.is_some_and(|b| is_cut(prev, b)) {
+                start = Some(byte);
+            }
+            if let (Some(start), Some(end)) = (start, end) {
+                return Some(start..end);
+            }
+            window *= 2;
+        }
+    }
+
+    /// Return the piece of `run` that starts at the byte offset `offset` into
+    /// it, or that ends there if `forward` is false.
+    fn run_piece(&self, run: &Range<usize>, offset: usize, forward: bool) -> (usize, &str) {
+        let piece = if forward {
+            self.pieces(run.start + offset..run.end).next()
+        } else {
+            self.pieces(run.start..run.start + offset).next_back()
+        };
+        let piece = piece.expect("offset should be inside the run");
+        let start = if forward { offset } else { offset - piece.len() };
+        (start, std::str::from_utf8(piece).expect("run should be Unicode"))
+    }
+
+    /// Find the grapheme cluster boundary after or before the byte position
+    /// `pos` inside of `run`. The run is returned one piece at a time as the
+    /// cursor asks for it.
+    fn grapheme_boundary(&self, run: &Range<usize>, pos: usize, forward: bool) -> usize {
+        let offset = pos - run.start;
+        let (mut start, mut chunk) = self.run_piece(run, offset, forward);
+        let mut cursor = GraphemeCursor::new(offset, run.len(), true);
+        loop {
+            let result = if forward {
+                cursor.next_boundary(chunk, start)
+            } else {
+                cursor.prev_boundary(chunk, start)
+            };
+            match result {
+                Ok(Some(boundary)) => return run.start + boundary,
+                Ok(None) => return if forward { run.end } else { run.start },
+                Err(GraphemeIncomplete::NextChunk) => {
+                    (start, chunk) = self.run_piece(run, start + chunk.len(), true);
+                }
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    (start, chunk) = self.run_piece(run, start, false);
+                }
+                Err(GraphemeIncomplete::PreContext(end)) => {
+                    let (start, context) = self.run_piece(run, end, false);
+                    cursor.provide_context(context, start);
+                }
+                Err(GraphemeIncomplete::InvalidOffset) => unreachable!("offset is in the run"),
+            }
+        }
+    }
+}
+
+/// Whether there is always a boundary between the bytes `prev` and `next`
+/// that does not depend on the text around them.
+fn is_cut(prev: Option<u8>, next: u8) -> bool {
+    matches!(prev, Some(b' ' | b'\t')) && next.is_ascii_graphic()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Buffer;
+    use crate::Snapshot;
+    use proptest::prelude::*;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    fn boundaries(buffer: &Buffer, next: impl Fn(usize) -> Option<usize>) -> Vec<usize> {
+        std::iter::successors(Some(buffer.begv()), |pos| next(*pos)).skip(1).collect()
+    }
+
+    #[test]
+    fn test_graphemes() {
+        // flags, a ZWJ family, a combining mark and CRLF
+        let text = "🇩🇪🇫🇷a👨‍👩‍👧e\u{301}\r\nx";
+        for gap in 0..=text.chars().count() {
+            let mut buffer = Buffer::from(text);
+            buffer.set_cursor(gap);
+            buffer.insert("|");
+            buffer.delete_backwards(1);
+            let next = boundaries(&buffer, |pos| buffer.next_grapheme_boundary(pos));
+            assert_eq!(next, [2, 4, 5, 10, 12, 14, 15], "gap at {gap}");
+            let mut prev: Vec<_> = std::iter::successors(Some(buffer.len_chars()), |pos| {
+                buffer.prev_grapheme_boundary(*pos)
+            })
+            .collect();
+            prev.reverse();
+            assert_eq!(prev, [0, 2, 4, 5, 10, 12, 14, 15], "gap at {gap}");
+        }
+        let buffer = Buffer::from("e\u{301}");
+        assert_eq!(buffer.next_grapheme_boundary(1), Some(2));
+        assert_eq!(buffer.prev_grapheme_boundary(1), Some(0));
+        assert_eq!(buffer.next_grapheme_boundary(2), None);
+        assert_eq!(buffer.prev_grapheme_boundary(0), None);
+    }
+
+    #[test]
+    fn test_words() {
+        let text = "can't stop, 3.14 Straße\nnext";
+        let mut buffer = Buffer::from(text);
+        for gap in [0, 3, 9, 20, 24, 28] {
+            buffer.set_cursor(gap);
+            buffer.insert("|");
+            buffer.delete_backwards(1);
+            let next = boundaries(&buffer, |pos| buffer.next_word_boundary(pos));
+            assert_eq!(next, [5, 6, 10, 11, 12, 16, 17, 23, 24, 28]);
+            assert_eq!(buffer.prev_word_boundary(8), Some(6));
+            assert_eq!(buffer.prev_word_boundary(24), Some(23));
+            assert_eq!(buffer.prev_word_boundary(25), Some(24));
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        // a word and a sequence of regional indicators longer than the window
+        let text = format!("a {} {} b", "x".repeat(20), "\u{1F1E9}".repeat(9));
+        let buffer = Buffer::from(text.as_str());
+        assert_eq!(buffer.next_word_boundary(2), Some(22));
+        assert_eq!(buffer.prev_word_boundary(21), Some(2));
+        assert_eq!(buffer.next_grapheme_boundary(30), Some(31));
+        assert_eq!(buffer.prev_grapheme_boundary(32), Some(31));
+        assert_eq!(buffer.prev_grapheme_boundary(31), Some(29));
+        assert_eq!(buffer.next_word_boundary(30), Some(31));
+        assert_eq!(buffer.prev_word_boundary(32), Some(31));
+    }
+
+    #[test]
+    fn test_non_unicode() {
+        let mut buffer = Buffer::from(&b"ab\xFF\xCC\x81cd"[..]);
+        let next = boundaries(&buffer, |pos| buffer.next_grapheme_boundary(pos));
+        assert_eq!(next, [1, 2, 3, 4, 5, 6]);
+        let next = boundaries(&buffer, |pos| buffer.next_word_boundary(pos));
+        assert_eq!(next, [2, 3, 4, 6]);
+        assert_eq!(buffer.prev_word_boundary(6), Some(4));
+        assert_eq!(buffer.prev_word_boundary(3), Some(2));
+        buffer.narrow(1..5);
+        let next = boundaries(&buffer, |pos| buffer.next_word_boundary(pos));
+        assert_eq!(next, [2, 3, 4, 5]);
+        assert_eq!(buffer.prev_grapheme_boundary(1), None);
+    }
+
+    type Step = fn(&Snapshot, usize) -> Option<usize>;
+
+    fn check_steps(buffer: &Buffer, bounds: &[usize], next: Step, prev: Step) {
+        let found: Vec<_> = std::iter::successors(Some(0), |pos| next(buffer, *pos)).collect();
+        assert_eq!(found, bounds);
+        let end = buffer.len_chars();
+        let mut found: Vec<_> =
+            std::iter::successors(Some(end), |pos| prev(buffer, *pos)).collect();
+        found.reverse();
+        assert_eq!(found, bounds);
+    }
+
+    fn check_segments(text: &str, gap: usize) {
+        let mut buffer = Buffer::from(text);
+        buffer.set_cursor(gap % (buffer.len_chars() + 1));
+        buffer.insert("|");
+        buffer.delete_backwards(1);
+        let bounds = |indices: &mut dyn Iterator<Item = usize>| {
+            let mut bounds: Vec<_> = indices.map(|idx| text[..idx].chars().count()).collect();
+            bounds.push(buffer.len_chars());
+            bounds
+        };
+        let graphemes = bounds(&mut text.grapheme_indices(true).map(|(idx, _)| idx));
+        let (next, prev) = (Snapshot::next_grapheme_boundary, Snapshot::prev_grapheme_boundary);
+        check_steps(&buffer, &graphemes, next, prev);
+        let words = bounds(&mut text.split_word_bound_indices().map(|(idx, _)| idx));
+        check_steps(&buffer, &words, Snapshot::next_word_boundary, Snapshot::prev_word_boundary);
+    }
+
+    proptest! {
+        #[test]
+        fn pt_segments(ref text in "([a-z0-9 .,'\\n\\r]|\u{301}|\u{200D}|\u{1F468}|\u{1F1E9}|\u{1F1EA}|\u{0915}|\u{094D}|\u{0937})*", gap in any::<usize>()) {
+            check_segments(text, gap);
+        }
+    }
+}
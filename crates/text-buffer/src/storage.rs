@@ -0,0 +1,229 @@
+//! The bytes of a gap buffer, stored in fixed-size chunks that are shared
+//! between snapshots. Writing to the storage only copies the chunks that are
+//! written to, so editing a buffer that has a live snapshot does not copy all
+//! of its text.
+//!
+//! Each chunk also holds a copy of the first few bytes of the next chunk. A
+//! char that starts in one chunk always ends inside of that chunk's copy, so
+//! the text can be read as borrowed slices that never split a char.
+use crate::encoding::is_char_boundary;
+use std::{ops::Range, sync::Arc};
+
+#[cfg(not(test))]
+const CHUNK_SIZE: usize = 1024;
+#[cfg(test)]
+const CHUNK_SIZE: usize = 16;
+/// The number of bytes of the next chunk copied to the end of each chunk. This
+/// is enough to hold the rest of the widest char.
+const OVERLAP: usize = 4;
+
+type Chunk = [u8; CHUNK_SIZE + OVERLAP];
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Storage {
+    chunks: Vec<Arc<Chunk>>,
+    len: usize,
+}
+
+impl From<&[u8]> for Storage {
+    fn from(data: &[u8]) -> Self {
+        let chunks = (0..data.len().div_ceil(CHUNK_SIZE))
+            .map(|idx| {
+                let start = idx * CHUNK_SIZE;
+                let end = (start + CHUNK_SIZE + OVERLAP).min(data.len());
+                let mut chunk = [0; CHUNK_SIZE + OVERLAP];
+                chunk[..end - start].copy_from_slice(&data[start..end]);
+                Arc::new(chunk)
+            })
+            .collect();
+        Storage { chunks, len: data.len() }
+    }
+}
+
+impl Storage {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<u8> {
+        (idx < self.len).then(|| self.chunks[idx / CHUNK_SIZE][idx % CHUNK_SIZE])
+    }
+
+    /// Return the bytes in `range`, which has to start and end in the same
+    /// chunk or in the copy of the next chunk held by it.
+    fn slice(&self, range: Range<usize>) -> &[u8] {
+        if range.is_empty() {
+            return &[];
+        }
+        let (idx, start) = (range.start / CHUNK_SIZE, range.start % CHUNK_SIZE);
+        assert!(range.end <= self.len, "range end out of bounds");
+        &self.chunks[idx][start..start + range.len()]
+    }
+
+    /// Overwrite the bytes starting at `pos` with `src`.
+    pub(crate) fn write(&mut self, mut pos: usize, mut src: &[u8]) {
+        assert!(pos + src.len() <= self.len, "write out of bounds");
+        while !src.is_empty() {
+            let (idx, start) = (pos / CHUNK_SIZE, pos % CHUNK_SIZE);
+            let len = src.len().min(CHUNK_SIZE - start);
+            Arc::make_mut(&mut self.chunks[idx])[start..start + len].copy_from_slice(&src[..len]);
+            // keep the copy held by the previous chunk up to date
+            if idx > 0 && start < OVERLAP {
+                let end = (start + len).min(OVERLAP);
+                let prev = Arc::make_mut(&mut self.chunks[idx - 1]);
+                prev[CHUNK_SIZE + start..CHUNK_SIZE + end].copy_from_slice(&src[..end - start]);
+            }
+            pos += len;
+            src = &src[len..];
+        }
+    }
+
+    /// Copy the bytes in `src` to `dest`, like [`slice::copy_within`].
+    pub(crate) fn copy_within(&mut self, src: Range<usize>, dest: usize) {
+        assert!(src.end <= self.len && dest + src.len() <= self.len, "copy out of bounds");
+        let buf = &mut [0; CHUNK_SIZE];
+        let len = src.len();
+        let mut copied = 0;
+        while copied < len {
+            let size = (len - copied).min(CHUNK_SIZE);
+            // copy from the back if the ranges overlap with dest after src
+            let offset = if dest > src.start { len - copied - size } else { copied };
+            self.read_into(src.start + offset, &mut buf[..size]);
+            self.write(dest + offset, &buf[..size]);
+            copied += size;
+        }
+    }
+
+    fn read_into(&self, mut pos: usize, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            let len = buf.len().min(CHUNK_SIZE - pos % CHUNK_SIZE);
+            let (head, tail) = buf.split_at_mut(len);
+            head.copy_from_slice(self.slice(pos..pos + len));
+            pos += len;
+            buf = tail;
+        }
+    }
+
+    /// Return an iterator over the bytes in `range`, which must not contain
+    /// any of the gap. Slices are split at chunk boundaries, rounded up to the
+    /// next char boundary.
+    pub(crate) fn pieces(&self, range: Range<usize>) -> Pieces<'_> {
+        self.pieces_around_gap(range, 0..0)
+    }
+
+    /// Like [`Storage::pieces`], but the bytes in `front` come first and those
+    /// in `back` after them.
+    pub(crate) fn pieces_around_gap(&self, front: Range<usize>, back: Range<usize>) -> Pieces<'_> {
+        Pieces { storage: self, front, back }
+    }
+
+    /// Return the first char boundary at or after the start of chunk `idx`,
+    /// limited to `range`.
+    fn split_point(&self, idx: usize, range: &Range<usize>) -> usize {
+        let start = (idx * CHUNK_SIZE).clamp(range.start, range.end);
+        (start..range.end)
+            .find(|pos| is_char_boundary(self.chunks[idx][pos - idx * CHUNK_SIZE]))
+            .unwrap_or(range.end)
+    }
+}
+
+/// An iterator over the bytes of [`Storage`] as borrowed slices that end on
+/// char boundaries. Created by [`Storage::pieces`].
+#[derive(Debug, Clone)]
+pub(crate) struct Pieces<'a> {
+    storage: &'a Storage,
+    front: Range<usize>,
+    back: Range<usize>,
+}
+
+impl<'a> Pieces<'a> {
+    fn next_piece(storage: &'a Storage, range: &mut Range<usize>) -> &'a [u8] {
+        let idx = range.start / CHUNK_SIZE;
+        let end = if idx + 1 < storage.chunks.len() {
+            storage.split_point(idx + 1, range)
+        } else {
+            range.end
+        };
+        let piece = storage.slice(range.start..end);
+        range.start = end;
+        piece
+    }
+
+    fn next_back_piece(storage: &'a Storage, range: &mut Range<usize>) -> &'a [u8] {
+        let mut idx = (range.end - 1) / CHUNK_SIZE;
+        let mut start = storage.split_point(idx, range);
+        if start == range.end {
+            // the last char started in the previous chunk
+            idx -= 1;
+            start = storage.split_point(idx, range);
+        }
+        let piece = storage.slice(start..range.end);
+        range.end = start;
+        piece
+    }
+}
+
+impl<'a> Iterator for Pieces<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.front.is_empty() {
+            Some(Self::next_piece(self.storage, &mut self.front))
+        } else if !self.back.is_empty() {
+            Some(Self::next_piece(self.storage, &mut self.back))
+        } else {
+            None
+        }
+    }
+}
+
+impl DoubleEndedIterator for Pieces<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if !self.back.is_empty() {
+            Some(Self::next_back_piece(self.storage, &mut self.back))
+        } else if !self.front.is_empty() {
+            Some(Self::next_back_piece(self.storage, &mut self.front))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_vec(storage: &Storage) -> Vec<u8> {
+        storage.pieces(0..storage.len()).collect::<Vec<_>>().concat()
+    }
+
+    #[test]
+    fn test_copy_within() {
+        let data: Vec<u8> = (0..100).map(|x| b' ' + x % 64).collect();
+        let mut storage = Storage::from(data.as_slice());
+        let snapshot = storage.clone();
+        let mut expect = data.clone();
+        storage.copy_within(10..60, 30);
+        expect.copy_within(10..60, 30);
+        assert_eq!(to_vec(&storage), expect);
+        storage.copy_within(40..90, 5);
+        expect.copy_within(40..90, 5);
+        assert_eq!(to_vec(&storage), expect);
+        // the snapshot still shares the chunks that were not written to
+        assert_eq!(to_vec(&snapshot), data);
+        assert!(Arc::ptr_eq(&storage.chunks[6], &snapshot.chunks[6]));
+    }
+
+    #[test]
+    fn test_pieces() {
+        let text = "aaaaaaaaaaaaaaa😀bbbbbbbbbbbb😀c".as_bytes().to_vec();
+        let storage = Storage::from(text.as_slice());
+        let pieces: Vec<_> = storage.pieces(0..text.len()).collect();
+        assert_eq!(pieces, ["aaaaaaaaaaaaaaa😀".as_bytes(), "bbbbbbbbbbbb😀".as_bytes(), b"c"]);
+        let mut rev: Vec<_> = storage.pieces(0..text.len()).rev().collect();
+        rev.reverse();
+        assert_eq!(rev, pieces);
+        let pieces: Vec<_> = storage.pieces_around_gap(0..2, 20..text.len()).collect();
+        assert_eq!(pieces, [b"aa".as_slice(), "bbbbbbbbbbb😀".as_bytes(), b"c"]);
+    }
+}
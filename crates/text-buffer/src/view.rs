@@ -0,0 +1,160 @@
+//! Views let several editors share the text of one buffer, like indirect
+//! buffers in Emacs. Each view has its own cursor and restriction, but only
+//! one view is active at a time. The cursor and restriction of the other
+//! views are kept in markers, so edits made through the active view adjust
+//! them like any other marker.
+use crate::MarkerId;
+
+/// A handle to a view of a [`Buffer`](crate::Buffer).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ViewId(usize);
+
+/// The state of a view that is not active.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct SavedView {
+    pub(crate) cursor: MarkerId,
+    /// The markers for the bounds of the accessible region, if narrowed
+    pub(crate) restriction: Option<(MarkerId, MarkerId)>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Views {
+    /// The saved state of each view by id. The slots of the active view and
+    /// of removed views are empty. This is empty until a second view is
+    /// created.
+    slots: Vec<Option<SavedView>>,
+    free: Vec<usize>,
+    active: usize,
+}
+
+impl Views {
+    pub(crate) fn active(&self) -> ViewId {
+        ViewId(self.active)
+    }
+
+    pub(crate) fn insert(&mut self, saved: SavedView) -> ViewId {
+        if self.slots.is_empty() {
+            // The slot for the view the buffer was created with
+            self.slots.push(None);
+        }
+        if let Some(id) = self.free.pop() {
+            self.slots[id] = Some(saved);
+            ViewId(id)
+        } else {
+            self.slots.push(Some(saved));
+            ViewId(self.slots.len() - 1)
+        }
+    }
+
+    /// Make `view` the active view, saving `current` as the state of the
+    /// previously active one. Returns the saved state of `view`.
+    pub(crate) fn activate(&mut self, view: ViewId, current: SavedView) -> SavedView {
+        let saved = self.take(view);
+        self.slots[self.active] = Some(current);
+        self.active = view.0;
+        saved
+    }
+
+    pub(crate) fn remove(&mut self, view: ViewId) -> SavedView {
+        assert_ne!(view.0, self.active, "attempt to remove the active view");
+        let saved = self.take(view);
+        self.free.push(view.0);
+        saved
+    }
+
+    fn take(&mut self, view: ViewId) -> SavedView {
+        self.slots.get_mut(view.0).and_then(Option::take).expect("view was removed")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Buffer;
+    use proptest::prelude::*;
+
+    /// The cursor and restriction of each view, adjusted the way markers are.
+    type View = (usize, Option<(usize, usize)>);
+
+    fn check_views(text: &str, ops: &[(u8, usize, usize, String)]) {
+        let mut buffer = Buffer::from(text);
+        let mut chars: Vec<char> = text.chars().collect();
+        let mut ids = vec![buffer.view(), buffer.create_view(), buffer.create_view()];
+        let mut views: Vec<View> = vec![(0, None); 3];
+        let mut active = 0;
+        let adjust = |views: &mut [View], f: &dyn Fn(usize, bool) -> usize| {
+            for (cursor, restriction) in views {
+                *cursor = f(*cursor, false);
+                if let Some((begv, zv)) = restriction {
+                    *begv = f(*begv, false);
+                    *zv = f(*zv, true);
+                }
+            }
+        };
+        for (op, a, b, ins) in ops {
+            let len = chars.len();
+            let (begv, zv) = views[active].1.unwrap_or((0, len));
+            match op % 5 {
+                0 => {
+                    active = a % ids.len();
+                    buffer.set_view(ids[active]);
+                }
+                1 => {
+                    let pos = begv + a % (zv - begv + 1);
+                    buffer.set_cursor(pos);
+                    buffer.insert(ins);
+                    chars.splice(pos..pos, ins.chars());
+                    let n = ins.chars().count();
+                    adjust(&mut views, &|x, advance| {
+                        if x > pos || (advance && x == pos) {
+                            x + n
+                        } else {
+                            x
+                        }
+                    });
+                    views[active].0 = pos + n;
+                }
+                2 => {
+                    let beg = begv + a % (zv - begv + 1);
+                    let end = (beg + b % 10).min(zv);
+                    buffer.delete_range(beg, end);
+                    chars.drain(beg..end);
+                    adjust(&mut views, &|x, _| if x > end { x - (end - beg) } else { x.min(beg) });
+                }
+                3 => {
+                    let (beg, end) = (a % (len + 1), b % (len + 1));
+                    let (beg, end) = (beg.min(end), beg.max(end));
+                    buffer.widen();
+                    buffer.narrow(beg..end);
+                    let view = &mut views[active];
+                    view.0 = view.0.clamp(beg, end);
+                    view.1 = Some((beg, end));
+                }
+                _ => {
+                    buffer.widen();
+                    views[active].1 = None;
+                }
+            }
+            assert_eq!(buffer, chars.iter().collect::<String>());
+            let len = chars.len();
+            assert_eq!(buffer.cursor(), views[active].0);
+            assert_eq!((buffer.begv(), buffer.zv()), views[active].1.unwrap_or((0, len)));
+        }
+        let len = chars.len();
+        for (id, view) in ids.iter().zip(&views) {
+            buffer.set_view(*id);
+            assert_eq!(buffer.cursor(), view.0);
+            assert_eq!((buffer.begv(), buffer.zv()), view.1.unwrap_or((0, len)));
+        }
+        buffer.set_view(ids[0]);
+        for id in ids.drain(1..) {
+            buffer.remove_view(id);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pt_views(ref text in "\\PC{0,20}", ref ops in prop::collection::vec((any::<u8>(), any::<usize>(), any::<usize>(), "\\PC{0,5}"), 0..30)) {
+            check_views(text, ops);
+        }
+    }
+}
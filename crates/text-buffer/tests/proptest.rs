@@ -2,7 +2,7 @@
 
 use proptest::prelude::*;
 use str_indices::chars::to_byte_idx;
-use text_buffer::Buffer;
+use text_buffer::{Buffer, BufferError, InsertionType, MarkerId, OverlayId};
 
 fn string_insert(text: &mut String, char_idx: usize, text_ins: &str) {
     let byte_idx = to_byte_idx(text, char_idx);
@@ -39,6 +39,289 @@ fn delete(buffer: &mut Buffer, string: &mut String, beg: usize, end: usize) {
     assert_eq!(buffer, string);
 }
 
+/// An edit or query applied to both a [`Buffer`] and its [`Model`].
+#[derive(Debug, Clone)]
+enum Action {
+    Insert(usize, String),
+    /// Delete up to `len` chars at a position, passing the range to
+    /// `delete_range` reversed if the flag is set
+    Delete(usize, usize, bool),
+    TryDelete(usize, usize),
+    Narrow(usize, usize),
+    Widen,
+    Marker(usize, bool),
+    Overlay(usize, usize, bool, bool),
+    ApplyEdits(Vec<(usize, usize, String)>),
+    Search(String),
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    let text = "([ab\u{3b8}\n]|\\PC){0,5}";
+    let edit = (any::<usize>(), 0..5usize, text);
+    prop_oneof![
+        4 => (any::<usize>(), text).prop_map(|(pos, ins)| Action::Insert(pos, ins)),
+        3 => (any::<usize>(), 0..10usize, any::<bool>())
+            .prop_map(|(pos, len, rev)| Action::Delete(pos, len, rev)),
+        1 => (any::<usize>(), any::<usize>()).prop_map(|(beg, end)| Action::TryDelete(beg, end)),
+        1 => (any::<usize>(), any::<usize>()).prop_map(|(beg, end)| Action::Narrow(beg, end)),
+        1 => Just(Action::Widen),
+        1 => (any::<usize>(), any::<bool>()).prop_map(|(pos, adv)| Action::Marker(pos, adv)),
+        1 => (any::<usize>(), any::<usize>(), any::<bool>(), any::<bool>())
+            .prop_map(|(beg, end, front, rear)| Action::Overlay(beg, end, front, rear)),
+        1 => prop::collection::vec(edit, 0..5).prop_map(Action::ApplyEdits),
+        1 => "[ab\u{3b8}\n]{1,4}".prop_map(Action::Search),
+    ]
+}
+
+/// What a buffer is expected to hold: its text, and the positions of its
+/// markers, overlays and restriction.
+#[derive(Debug, Default)]
+struct Model {
+    text: String,
+    markers: Vec<(MarkerId, usize, bool)>,
+    overlays: Vec<(OverlayId, usize, usize, bool, bool)>,
+    restriction: Option<(usize, usize)>,
+}
+
+impl Model {
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn bounds(&self) -> (usize, usize) {
+        self.restriction.unwrap_or((0, self.len()))
+    }
+
+    fn accessible(&self) -> String {
+        let (begv, zv) = self.bounds();
+        self.text.chars().skip(begv).take(zv - begv).collect()
+    }
+
+    fn insert(&mut self, pos: usize, ins: &str) {
+        string_insert(&mut self.text, pos, ins);
+        let size = ins.chars().count();
+        for (_, marker, adv) in &mut self.markers {
+            if *marker > pos || (*marker == pos && *adv) {
+                *marker += size;
+            }
+        }
+        for (_, beg, end, front, rear) in &mut self.overlays {
+            if *end > pos || (*end == pos && *rear && size > 0) {
+                *end += size;
+            }
+            if *beg > pos || (*beg == pos && *front) {
+                *beg = (*beg + size).min(*end);
+            }
+        }
+        // the start of the restriction stays before inserted text and the end
+        // advances past it
+        if let Some((begv, zv)) = &mut self.restriction {
+            if *begv > pos {
+                *begv += size;
+            }
+            if *zv >= pos {
+                *zv += size;
+            }
+        }
+    }
+
+    fn delete(&mut self, beg: usize, end: usize) {
+        string_remove(&mut self.text, beg, end);
+        let shift = |x: usize| if x > end { x - (end - beg) } else { x.min(beg) };
+        for (_, marker, _) in &mut self.markers {
+            *marker = shift(*marker);
+        }
+        for (_, start, stop, _, _) in &mut self.overlays {
+            (*start, *stop) = (shift(*start), shift(*stop));
+        }
+        if let Some((begv, zv)) = &mut self.restriction {
+            (*begv, *zv) = (shift(*begv), shift(*zv));
+        }
+    }
+}
+
+fn kind(advance: bool) -> InsertionType {
+    if advance {
+        InsertionType::Advance
+    } else {
+        InsertionType::Stay
+    }
+}
+
+fn apply(buffer: &mut Buffer, model: &mut Model, action: &Action) {
+    let len = model.len();
+    let (begv, zv) = model.bounds();
+    match action {
+        Action::Insert(pos, ins) => {
+            let pos = begv + pos % (zv - begv + 1);
+            buffer.set_cursor(pos);
+            buffer.insert(ins);
+            model.insert(pos, ins);
+            assert_eq!(buffer.cursor(), pos + ins.chars().count());
+        }
+        Action::Delete(pos, size, reverse) => {
+            let beg = pos % (len + 1);
+            let end = (beg + size).min(len);
+            if *reverse {
+                buffer.delete_range(end, beg);
+            } else {
+                buffer.delete_range(beg, end);
+            }
+            model.delete(beg.clamp(begv, zv), end.clamp(begv, zv));
+        }
+        Action::TryDelete(beg, end) => {
+            let (beg, end) = (beg % (len + 2), end % (len + 2));
+            let valid = beg <= end && begv <= beg && end <= zv;
+            match buffer.try_delete_range(beg, end) {
+                Ok(()) => {
+                    assert!(valid);
+                    model.delete(beg, end);
+                }
+                Err(BufferError::Inverted { .. }) => assert!(beg > end),
+                Err(BufferError::OutOfRange { pos, start, end: stop }) => {
+                    assert!(!valid);
+                    assert_eq!((start, stop), (begv, zv));
+                    assert!(pos < begv || pos > zv);
+                }
+                Err(err) => panic!("unexpected error {err}"),
+            }
+            let (begv, zv) = model.bounds();
+            assert_eq!(buffer.try_set_cursor(beg).is_ok(), begv <= beg && beg <= zv);
+            let byte = buffer.char_to_byte(beg.min(zv));
+            assert_eq!(buffer.try_read(byte..byte).is_ok(), begv <= beg);
+        }
+        Action::Narrow(beg, end) => {
+            let (beg, end) = (beg % (len + 1), end % (len + 1));
+            buffer.widen();
+            buffer.narrow(beg..end);
+            model.restriction = Some((beg.min(end), beg.max(end)));
+        }
+        Action::Widen => {
+            buffer.widen();
+            model.restriction = None;
+        }
+        Action::Marker(pos, adv) => {
+            let pos = pos % (len + 1);
+            let id = buffer.create_marker(pos, kind(*adv));
+            model.markers.push((id, pos, *adv));
+        }
+        Action::Overlay(beg, end, front, rear) => {
+            let (beg, end) = (beg % (len + 1), end % (len + 1));
+            let (beg, end) = (beg.min(end), beg.max(end));
+            let id = buffer.create_overlay(beg..end, kind(*front), kind(*rear));
+            model.overlays.push((id, beg, end, *front, *rear));
+        }
+        Action::ApplyEdits(edits) => {
+            let edits: Vec<_> = edits
+                .iter()
+                .map(|(pos, del, ins)| {
+                    let start = pos % (len + 1);
+                    (start..(start + del).min(len), ins.as_str())
+                })
+                .collect();
+            let mut sorted = edits.clone();
+            sorted.sort_by_key(|(range, _)| (range.start, range.end));
+            let valid = sorted.windows(2).all(|pair| pair[0].0.end <= pair[1].0.start)
+                && sorted.iter().all(|(range, _)| begv <= range.start && range.end <= zv);
+            match buffer.apply_edits(&edits) {
+                Ok(map) => {
+                    assert!(valid);
+                    for (range, ins) in sorted.iter().rev() {
+                        model.delete(range.start, range.end);
+                        model.insert(range.start, ins);
+                    }
+                    for (range, (_, ins)) in map.inserted().zip(&sorted) {
+                        assert_eq!(buffer.read_chars(range).unwrap(), *ins);
+                    }
+                }
+                Err(BufferError::Overlap { .. } | BufferError::OutOfRange { .. }) => {
+                    assert!(!valid);
+                }
+                Err(err) => panic!("unexpected error {err}"),
+            }
+        }
+        Action::Search(needle) => {
+            let accessible = model.accessible();
+            let to_chars = |idx: usize| begv + accessible[..idx].chars().count();
+            let range = |idx: usize| to_chars(idx)..to_chars(idx + needle.len());
+            assert_eq!(
+                buffer.search_forward(needle, 0..len),
+                accessible.find(&**needle).map(range)
+            );
+            assert_eq!(
+                buffer.search_backward(needle, 0..len),
+                accessible.rfind(&**needle).map(range)
+            );
+        }
+    }
+}
+
+/// Compare everything that can be read from `buffer` with `model`.
+fn check(buffer: &Buffer, model: &Model) {
+    let string = &model.text;
+    assert_eq!(buffer, string);
+    let (begv, zv) = model.bounds();
+    assert_eq!((buffer.begv(), buffer.zv()), (begv, zv));
+    let accessible = model.accessible();
+    assert_eq!(buffer.read_chars(0..buffer.len_chars()).unwrap(), accessible);
+    let to_char = |x| char::from_u32(x).unwrap();
+    assert!(buffer.chars_at(0).map(to_char).eq(accessible.chars()));
+    assert!(buffer.chars_at(0).rev().map(to_char).eq(accessible.chars().rev()));
+    assert!(buffer.bytes_at(0).rev().eq(accessible.bytes().rev()));
+    check_lines(buffer, string);
+    check_utf16(buffer, string);
+    for (id, pos, _) in &model.markers {
+        assert_eq!(buffer.marker_position(*id), *pos);
+    }
+    for (id, beg, end, _, _) in &model.overlays {
+        assert_eq!(buffer.overlay_range(*id), *beg..*end);
+    }
+    for pos in 0..=model.len() {
+        let at: Vec<_> = model
+            .overlays
+            .iter()
+            .filter(|(_, beg, end, _, _)| *beg <= pos && pos < *end)
+            .map(|(id, ..)| *id)
+            .collect();
+        let actual = buffer.overlays_at(pos);
+        assert_eq!(actual.len(), at.len());
+        assert!(at.iter().all(|x| actual.contains(x)));
+        let bounds = model.overlays.iter().flat_map(|(_, beg, end, _, _)| [*beg, *end]);
+        assert_eq!(buffer.next_overlay_change(pos), bounds.filter(|x| *x > pos).min());
+    }
+}
+
+fn check_lines(buffer: &Buffer, string: &str) {
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(string.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1))
+        .collect();
+    assert_eq!(buffer.len_lines(), starts.len());
+    for (line, start) in starts.iter().enumerate() {
+        assert_eq!(buffer.line_to_char(line), *start);
+        assert_eq!(buffer.char_to_line(*start), line);
+    }
+    for (pos, _) in string.chars().enumerate() {
+        let line = starts.partition_point(|x| *x <= pos) - 1;
+        assert_eq!(buffer.char_to_line(pos), line);
+    }
+}
+
+fn check_utf16(buffer: &Buffer, string: &str) {
+    assert_eq!(buffer.len_utf16(), string.encode_utf16().count());
+    let mut line = 0;
+    let mut column = 0;
+    for (pos, chr) in string.chars().enumerate() {
+        assert_eq!(buffer.char_to_line_utf16(pos), (line, column));
+        assert_eq!(buffer.line_utf16_to_char(line, column), pos);
+        if chr == '\n' {
+            line += 1;
+            column = 0;
+        } else {
+            column += chr.len_utf16();
+        }
+    }
+}
+
 proptest! {
     #![proptest_config(ProptestConfig::with_cases(1000))]
 
@@ -71,4 +354,15 @@ proptest! {
             insert(buffer, text, char_idx, ins_text);
         }
     }
+
+    #[test]
+    fn pt_model(ref text in "([ab\u{3b8}\n]|\\PC)*", ref actions in prop::collection::vec(action(), 0..30)) {
+        let mut buffer = Buffer::from(&**text);
+        let mut model = Model { text: text.clone(), ..Model::default() };
+        check(&buffer, &model);
+        for action in actions {
+            apply(&mut buffer, &mut model, action);
+            check(&buffer, &model);
+        }
+    }
 }
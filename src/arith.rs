@@ -1,4 +1,5 @@
-use crate::core::object::{Gc, IntoObject, Number, Object};
+use crate::core::object::{Gc, IntOrMarker, IntoObject, Number, Object};
+use anyhow::{bail, Result};
 use float_cmp::ApproxEq;
 use fn_macros::defun;
 use std::cmp::{PartialEq, PartialOrd};
@@ -19,6 +20,19 @@ impl<'ob> Gc<Number<'ob>> {
     }
 }
 
+impl<'ob> Gc<IntOrMarker<'ob>> {
+    /// The integer, or the position of the marker.
+    pub(crate) fn position(self) -> Result<i64> {
+        match self.untag() {
+            IntOrMarker::Int(x) => Ok(x),
+            IntOrMarker::Marker(marker) => match marker.position() {
+                Some(pos) => Ok(pos as i64),
+                None => bail!("Marker does not point anywhere"),
+            },
+        }
+    }
+}
+
 impl IntoObject for NumberValue {
     type Out<'ob> = Object<'ob>;
 
@@ -219,13 +233,13 @@ pub(crate) fn greater_than_or_eq(number: Gc<Number>, numbers: &[Gc<Number>]) ->
 }
 
 #[defun]
-pub(crate) fn logior(ints_or_markers: &[Gc<i64>]) -> i64 {
-    ints_or_markers.iter().fold(0, |acc, x| acc | x.untag())
+pub(crate) fn logior(ints_or_markers: &[Gc<IntOrMarker>]) -> Result<i64> {
+    ints_or_markers.iter().try_fold(0, |acc, x| Ok(acc | x.position()?))
 }
 
 #[defun]
-fn logand(int_or_markers: &[Gc<i64>]) -> i64 {
-    int_or_markers.iter().fold(-1, |accum, x| accum & x.untag())
+fn logand(int_or_markers: &[Gc<IntOrMarker>]) -> Result<i64> {
+    int_or_markers.iter().try_fold(-1, |accum, x| Ok(accum & x.position()?))
 }
 
 #[defun(name = "mod")]
@@ -338,6 +352,6 @@ mod test {
     fn test_other() {
         let roots = &RootSet::default();
         let cx = &Context::new(roots);
-        assert_eq!(logand(&[258.into_obj(cx), 255.into_obj(cx)]), 2);
+        assert_eq!(logand(&[258.into_obj(cx).into(), 255.into_obj(cx).into()]).unwrap(), 2);
     }
 }
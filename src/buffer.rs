@@ -1,21 +1,72 @@
 use crate::{
     core::{
-        env::{Env, INTERNED_SYMBOLS},
+        env::{sym, Env, Symbol},
         error::{Type, TypeError},
         gc::{Context, Rt},
-        object::{GcObj, LispBuffer, Object},
+        object::{nil, Function, Gc, GcObj, LispBuffer, Object},
     },
-    hashmap::HashMap,
+    eval::run_hooks,
+    fns::slice_into_list,
+    root, rooted_iter,
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use fn_macros::defun;
-use lazy_static::lazy_static;
-use std::sync::Mutex;
+use streaming_iterator::StreamingIterator;
 
-// static hashmap containing all the buffers
-lazy_static! {
-    static ref BUFFERS: Mutex<HashMap<String, &'static LispBuffer>> =
-        Mutex::new(HashMap::default());
+/// The buffer an operation applies to: `buffer` if it is non-nil and the
+/// current buffer otherwise.
+pub(crate) fn target_buffer(
+    buffer: Option<GcObj>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<&'static LispBuffer> {
+    match buffer.map(Gc::untag) {
+        None => match env.current_lisp_buffer(cx) {
+            Some(current) => Ok(current),
+            None => bail!("No current buffer"),
+        },
+        // SAFETY: This can be 'static because a buffer is only collected once
+        // it is killed and nothing refers to it
+        Some(Object::Buffer(b)) => Ok(unsafe { b.as_static() }),
+        Some(x) => bail!(TypeError::new(Type::Buffer, x)),
+    }
+}
+
+/// The buffer `buffer_or_name` refers to, or `None` if there is no live
+/// buffer by that name.
+fn named_buffer(
+    buffer_or_name: GcObj,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Option<&'static LispBuffer>> {
+    match buffer_or_name.untag() {
+        // SAFETY: This can be 'static because a buffer is only collected once
+        // it is killed and nothing refers to it
+        Object::Buffer(b) => Ok(Some(unsafe { b.as_static() })),
+        Object::String(s) => {
+            let name: &str = s.try_into()?;
+            Ok(env.get_buffer(name, cx))
+        }
+        x => bail!(TypeError::new(Type::String, x)),
+    }
+}
+
+/// `name` if no buffer has it, and otherwise `name<N>` for the smallest N
+/// starting from 2 that no buffer has. `ignore` is used even if it is taken.
+fn unique_name(name: &str, ignore: Option<&str>, env: &Rt<Env>, cx: &Context) -> String {
+    let is_free = |name: &str| ignore == Some(name) || env.get_buffer(name, cx).is_none();
+    if is_free(name) {
+        return name.to_owned();
+    }
+    // There are fewer buffers than suffixes, so one of them is free
+    let mut n = 2;
+    loop {
+        let candidate = format!("{name}<{n}>");
+        if is_free(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
 }
 
 #[defun]
@@ -24,32 +75,105 @@ pub(crate) fn set_buffer<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<GcObj<'ob>> {
-    let buffer: &LispBuffer = match buffer_or_name.untag() {
-        Object::Buffer(b) => b,
-        Object::String(s) => {
-            let name: &str = s.try_into()?;
-            let buffer_list = BUFFERS.lock().unwrap();
-            let Some(buffer) = buffer_list.get(name) else {
-                bail!("No buffer named {}", name);
-            };
-            cx.bind(*buffer)
-        }
-        x => bail!(TypeError::new(Type::String, x)),
+    let Some(buffer) = named_buffer(buffer_or_name, env, cx)? else {
+        bail!("No buffer named {buffer_or_name}");
     };
     env.set_buffer(buffer, cx)?;
     Ok(cx.add(buffer))
 }
 
+/// Make `buffer_or_name` current, creating it if there is no buffer by that
+/// name. Unless `norecord` is non-nil it is also moved to the front of
+/// `buffer-list`. There are no windows, so `force-same-window` is ignored.
 #[defun]
-fn set_buffer_modified_p(flag: GcObj) -> GcObj {
-    // TODO: implement
-    flag
+fn switch_to_buffer<'ob>(
+    buffer_or_name: GcObj<'ob>,
+    norecord: Option<()>,
+    _force_same_window: Option<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let buffer = get_buffer_create(buffer_or_name, None, env, cx)?;
+    set_buffer(buffer, env, cx)?;
+    if norecord.is_none() {
+        let buffer = target_buffer(Some(buffer), env, cx)?;
+        env.record_buffer(buffer, cx);
+    }
+    Ok(buffer)
 }
 
 #[defun]
-fn buffer_live_p(buffer: GcObj, env: &mut Rt<Env>) -> bool {
+fn get_buffer<'ob>(
+    buffer_or_name: GcObj<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    Ok(match named_buffer(buffer_or_name, env, cx)? {
+        Some(buffer) => cx.add(buffer),
+        None => nil(),
+    })
+}
+
+#[defun]
+fn buffer_name<'ob>(buffer: Option<GcObj>, env: &Rt<Env>, cx: &'ob Context) -> Result<GcObj<'ob>> {
+    Ok(match target_buffer(buffer, env, cx)?.name() {
+        Some(name) => cx.add(name),
+        None => nil(),
+    })
+}
+
+#[defun]
+fn rename_buffer<'ob>(
+    newname: &str,
+    unique: Option<()>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    ensure!(!newname.is_empty(), "Empty string is invalid as a buffer name");
+    let buffer = target_buffer(None, env, cx)?;
+    let name = match env.get_buffer(newname, cx) {
+        Some(other) if other == buffer => return Ok(cx.add(newname)),
+        Some(_) if unique.is_some() => unique_name(newname, None, env, cx),
+        Some(_) => bail!("Buffer name `{newname}' is in use"),
+        None => newname.to_owned(),
+    };
+    buffer.rename(name.clone());
+    Ok(cx.add(name))
+}
+
+#[defun]
+fn generate_new_buffer_name(
+    name: &str,
+    ignore: Option<&str>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    unique_name(name, ignore, env, cx)
+}
+
+#[defun]
+fn buffer_list<'ob>(_frame: Option<GcObj>, env: &Rt<Env>, cx: &'ob Context) -> GcObj<'ob> {
+    let buffers: Vec<GcObj> = env.buffer_list.bind_ref(cx).iter().map(|b| cx.add(*b)).collect();
+    slice_into_list(&buffers, None, cx)
+}
+
+#[defun]
+fn buffer_modified_p(buffer: Option<GcObj>, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let buffer = target_buffer(buffer, env, cx)?;
+    Ok(env.with_buffer(buffer, |b| b.is_some_and(|b| b.is_modified())))
+}
+
+#[defun]
+fn set_buffer_modified_p<'ob>(flag: GcObj<'ob>, env: &mut Rt<Env>) -> Result<GcObj<'ob>> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    buffer.set_modified(!flag.nil());
+    Ok(flag)
+}
+
+#[defun]
+fn buffer_live_p(buffer: GcObj) -> bool {
     match buffer.untag() {
-        Object::Buffer(b) => env.with_buffer(b, |b| b.is_some()),
+        Object::Buffer(b) => b.is_live(),
         _ => false,
     }
 }
@@ -57,26 +181,20 @@ fn buffer_live_p(buffer: GcObj, env: &mut Rt<Env>) -> bool {
 #[defun]
 pub(crate) fn get_buffer_create<'ob>(
     buffer_or_name: GcObj<'ob>,
-    _inhibit_buffer_hooks: GcObj,
+    _inhibit_buffer_hooks: Option<GcObj>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<GcObj<'ob>> {
     match buffer_or_name.untag() {
         Object::String(x) => {
-            let name = x.try_into()?;
-            let mut buffer_list = BUFFERS.lock().unwrap();
-            match buffer_list.get(name) {
-                Some(b) => Ok(cx.add(*b)),
+            let name: &str = x.try_into()?;
+            match env.get_buffer(name, cx) {
+                Some(b) => Ok(cx.add(b)),
                 None => {
-                    // If not already in the global buffer list, create a new
-                    // buffer and add it
-                    let buffer: &'static _ = {
-                        let global = INTERNED_SYMBOLS.lock().unwrap();
-                        let buffer = global.create_buffer(name);
-                        // SAFETY: This can be 'static because it is stored in the
-                        // global block. Eventually it will be garbage collected
-                        unsafe { &*(buffer as *const LispBuffer) }
-                    };
-                    buffer_list.insert(name.to_string(), buffer);
+                    // If not already in the buffer list, create a new buffer
+                    // and add it
+                    let buffer = LispBuffer::create(name.to_owned(), cx);
+                    env.buffer_list.bind_mut(cx).push_back(buffer);
                     Ok(cx.add(buffer))
                 }
             }
@@ -86,18 +204,290 @@ pub(crate) fn get_buffer_create<'ob>(
     }
 }
 
+#[defun]
+fn make_indirect_buffer<'ob>(
+    base_buffer: GcObj,
+    name: &str,
+    _clone: Option<GcObj>,
+    _inhibit_buffer_hooks: Option<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    // TODO: implement CLONE once buffers have modes and local variables
+    let Some(base) = named_buffer(base_buffer, env, cx)? else {
+        bail!("No such buffer: `{base_buffer}'");
+    };
+    if env.get_buffer(name, cx).is_some() {
+        bail!("Buffer name `{name}' is in use");
+    }
+    let Some(text) = env.with_buffer(base, |b| b.map(|b| b.indirect_text())) else {
+        bail!("Base buffer has been killed");
+    };
+    // An indirect buffer of an indirect buffer shares the text of the
+    // original buffer
+    let base = base.base().unwrap_or(base);
+    let buffer = LispBuffer::create_indirect(name.to_owned(), base, text, cx);
+    env.buffer_list.bind_mut(cx).push_back(buffer);
+    Ok(cx.add(buffer))
+}
+
+#[defun]
+fn buffer_base_buffer<'ob>(
+    buffer: Option<GcObj>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    Ok(match target_buffer(buffer, env, cx)?.base() {
+        Some(base) => cx.add(base),
+        None => nil(),
+    })
+}
+
+#[defun]
+pub(crate) fn kill_buffer(
+    buffer_or_name: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let buffer = match buffer_or_name {
+        Some(x) => match named_buffer(x.bind(cx), env, cx)? {
+            Some(buffer) => buffer,
+            None => bail!("No such buffer: {}", x.bind(cx)),
+        },
+        None => target_buffer(None, env, cx)?,
+    };
+    kill(buffer, env, cx)
+}
+
+/// Kill `buffer` and any indirect buffers that share its text, unless one of
+/// `kill-buffer-query-functions` returns nil. Returns whether it was killed.
+fn kill(buffer: &'static LispBuffer, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    if !buffer.is_live() {
+        return Ok(false);
+    }
+    // Keep the buffer from being collected if the hooks kill it
+    root!(_buffer, move(buffer), cx);
+    // The hooks are run with the buffer current
+    let depth = env.binding_depth();
+    if env.current_buffer.is_some() {
+        env.save_current_buffer(cx)?;
+    }
+    let query = env.set_buffer(buffer, cx).and_then(|()| run_kill_hooks(env, cx));
+    env.unbind_to(depth, cx);
+    if !query? {
+        return Ok(false);
+    }
+    // The hooks may have killed the buffer themselves
+    if !buffer.is_live() {
+        return Ok(true);
+    }
+    // Look for the indirect buffers again after each kill, because the hooks
+    // can kill buffers as well
+    while let Some(other) = indirect_buffer(buffer, env, cx) {
+        if !kill(other, env, cx)? {
+            return Ok(false);
+        }
+    }
+    if env.current_lisp_buffer(cx) == Some(buffer) {
+        let other = env.buffer_list.bind_ref(cx).iter().copied().find(|x| *x != buffer);
+        let other = match other {
+            Some(other) => cx.add(other),
+            None => get_buffer_create(cx.add("*scratch*"), None, env, cx)?,
+        };
+        set_buffer(other, env, cx)?;
+    }
+    // The buffer can't be found by name once it is killed
+    env.buffer_list.bind_mut(cx).retain(|x| *x != buffer);
+    buffer.kill();
+    Ok(true)
+}
+
+/// A live indirect buffer whose base is `base`.
+fn indirect_buffer(base: &LispBuffer, env: &Rt<Env>, cx: &Context) -> Option<&'static LispBuffer> {
+    let buffers = env.buffer_list.bind_ref(cx);
+    let buffer = *buffers.iter().find(|x| x.base().is_some_and(|x| x == base))?;
+    // SAFETY: This can be 'static because the buffer list is rooted
+    Some(unsafe { buffer.as_static() })
+}
+
+/// Run `kill-buffer-query-functions` and then `kill-buffer-hook` in the current
+/// buffer. Returns false without running the hook if a query function
+/// returned nil.
+fn run_kill_hooks(env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    if !run_query_functions(sym::KILL_BUFFER_QUERY_FUNCTIONS, env, cx)? {
+        return Ok(false);
+    }
+    let hook: GcObj = sym::KILL_BUFFER_HOOK.into();
+    root!(hooks, move(vec![hook]), cx);
+    run_hooks(hooks, env, cx)?;
+    Ok(true)
+}
+
+/// Call the functions in `hook` with no arguments until one returns nil.
+/// Returns false if one did.
+fn run_query_functions(hook: Symbol, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let Some(val) = env.var(hook, cx) else { return Ok(true) };
+    match val.untag() {
+        Object::Cons(hook_list) => {
+            rooted_iter!(funcs, hook_list, cx);
+            while let Some(func) = funcs.next() {
+                let func: &Rt<Gc<Function>> = func.try_into()?;
+                root!(args, Vec::new(), cx);
+                if func.call(args, env, cx, None)?.nil() {
+                    return Ok(false);
+                }
+            }
+        }
+        Object::NIL => {}
+        _ => {
+            let func: Gc<Function> = val.try_into()?;
+            root!(func, cx);
+            root!(args, Vec::new(), cx);
+            if func.call(args, env, cx, None)?.nil() {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+defvar!(TAB_WIDTH, 8);
+defvar!(KILL_BUFFER_HOOK);
+defvar!(KILL_BUFFER_QUERY_FUNCTIONS);
+
 #[cfg(test)]
 mod test {
-    use crate::core::env::sym;
     use crate::core::gc::RootSet;
 
     use super::*;
+    use crate::{
+        editfns::{insert, narrow_to_region, widen},
+        root,
+    };
 
     #[test]
     fn test_create_buffer() {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
-        let buffer = get_buffer_create(cx.add("test_create_buffer"), sym::NIL.into(), cx).unwrap();
+        root!(env, Env::default(), cx);
+        let buffer = get_buffer_create(cx.add("test_create_buffer"), None, env, cx).unwrap();
         assert!(matches!(buffer.untag(), Object::Buffer(_)));
     }
+
+    fn buffer_names(env: &Rt<Env>, cx: &Context) -> Vec<String> {
+        env.buffer_list.bind_ref(cx).iter().filter_map(|b| b.name()).collect()
+    }
+
+    #[test]
+    fn test_set_buffer_keeps_order() {
+        use crate::data::{make_local_variable, set, symbol_value};
+        use crate::lread::intern;
+
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let a = get_buffer_create(cx.add("test_order_a"), None, env, cx).unwrap();
+        set_buffer(a, env, cx).unwrap();
+        let var = intern("buffer-order-test", cx);
+        set(var, cx.add("default"), env, cx).unwrap();
+        make_local_variable(var, env, cx).unwrap();
+        set(var, cx.add("local"), env, cx).unwrap();
+        let b = get_buffer_create(cx.add("test_order_b"), None, env, cx).unwrap();
+        set_buffer(b, env, cx).unwrap();
+        assert_eq!(buffer_names(env, cx), ["test_order_a", "test_order_b"]);
+        assert_eq!(symbol_value(var, env, cx).unwrap(), "default");
+
+        // Saving and restoring the current buffer does not reorder them either
+        let depth = env.binding_depth();
+        env.save_current_buffer(cx).unwrap();
+        set_buffer(cx.add("test_order_a"), env, cx).unwrap();
+        assert_eq!(symbol_value(var, env, cx).unwrap(), "local");
+        env.unbind_to(depth, cx);
+        assert_eq!(buffer_names(env, cx), ["test_order_a", "test_order_b"]);
+        assert_eq!(env.current_lisp_buffer(cx).unwrap().name().unwrap(), "test_order_b");
+        assert_eq!(symbol_value(var, env, cx).unwrap(), "default");
+    }
+
+    #[test]
+    fn test_switch_to_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        get_buffer_create(cx.add("test_switch_a"), None, env, cx).unwrap();
+        get_buffer_create(cx.add("test_switch_b"), None, env, cx).unwrap();
+        assert_eq!(buffer_names(env, cx), ["test_switch_a", "test_switch_b"]);
+
+        // Selecting a buffer moves it to the front
+        switch_to_buffer(cx.add("test_switch_b"), None, None, env, cx).unwrap();
+        assert_eq!(buffer_names(env, cx), ["test_switch_b", "test_switch_a"]);
+        switch_to_buffer(cx.add("test_switch_a"), Some(()), None, env, cx).unwrap();
+        assert_eq!(buffer_names(env, cx), ["test_switch_b", "test_switch_a"]);
+        assert_eq!(env.current_lisp_buffer(cx).unwrap().name().unwrap(), "test_switch_a");
+
+        // A buffer is created if there is none by that name
+        switch_to_buffer(cx.add("test_switch_c"), None, None, env, cx).unwrap();
+        let names = ["test_switch_c", "test_switch_b", "test_switch_a"];
+        assert_eq!(buffer_names(env, cx), names);
+    }
+
+    #[test]
+    fn test_collect_killed_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let other = get_buffer_create(cx.add("test_collect_other"), None, env, cx).unwrap();
+        set_buffer(other, env, cx).unwrap();
+        cx.garbage_collect(true);
+        let count = cx.object_count();
+        let name = "test_collect_killed_buffer";
+        let buffer = get_buffer_create(cx.add(name), None, env, cx).unwrap();
+        let Object::Buffer(buffer) = buffer.untag() else { unreachable!() };
+        let buffer = unsafe { buffer.as_static() };
+        // Live buffers are kept even if nothing refers to them
+        cx.garbage_collect(true);
+        assert_eq!(cx.object_count(), count + 1);
+        assert!(buffer.is_live());
+        assert!(kill(buffer, env, cx).unwrap());
+        assert!(env.get_buffer(name, cx).is_none());
+        cx.garbage_collect(true);
+        assert_eq!(cx.object_count(), count);
+    }
+
+    #[test]
+    fn test_indirect_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let base = get_buffer_create(cx.add("test_indirect_base"), None, env, cx).unwrap();
+        set_buffer(base, env, cx).unwrap();
+        cx.garbage_collect(true);
+        // Fetch the buffer again because the collection invalidates `base`
+        let base = get_buffer_create(cx.add("test_indirect_base"), None, env, cx).unwrap();
+        insert(&[cx.add("hello world")], env).unwrap();
+        narrow_to_region(0_i64.into(), 5_i64.into(), env, cx).unwrap();
+        let name = "test_indirect";
+        let indirect = make_indirect_buffer(base, name, None, None, env, cx).unwrap();
+        assert!(make_indirect_buffer(base, name, None, None, env, cx).is_err());
+        let Object::Buffer(base) = base.untag() else { unreachable!() };
+        let base = unsafe { base.as_static() };
+        set_buffer(indirect, env, cx).unwrap();
+        let result = buffer_base_buffer(None, env, cx).unwrap();
+        assert!(matches!(result.untag(), Object::Buffer(b) if *b == *base));
+        // Point and the restriction are copied from the base buffer but are
+        // independent
+        widen(env).unwrap();
+        insert(&[cx.add("!")], env).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello! world");
+        // The base buffer stays narrowed, and the end of its restriction
+        // advances over the insertion
+        let result = env.with_buffer(base, |b| b.unwrap().restriction());
+        assert_eq!(result, Some((0, 6)));
+        // An indirect buffer of an indirect buffer shares the original base
+        let nested = make_indirect_buffer(indirect, "test_indirect_nested", None, None, env, cx);
+        let result = buffer_base_buffer(Some(nested.unwrap()), env, cx).unwrap();
+        assert!(matches!(result.untag(), Object::Buffer(b) if *b == *base));
+        set_buffer(cx.add(base), env, cx).unwrap();
+        widen(env).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hello! world");
+    }
 }
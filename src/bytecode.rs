@@ -202,6 +202,8 @@ struct Handler<'ob> {
     jump_code: u16,
     #[no_trace]
     stack_size: usize,
+    #[no_trace]
+    binding_depth: usize,
     condition: GcObj<'ob>,
 }
 
@@ -210,6 +212,7 @@ impl<'ob> IntoRoot<Handler<'static>> for Handler<'ob> {
         Handler {
             jump_code: self.jump_code,
             stack_size: self.stack_size,
+            binding_depth: self.binding_depth,
             condition: self.condition.into_root(),
         }
     }
@@ -238,8 +241,8 @@ impl<'brw, 'ob> Routine<'brw> {
     fn varref(&mut self, idx: u16, env: &Rt<Env>, cx: &'ob Context) -> Result<()> {
         let symbol = self.frame.get_const(idx as usize, cx);
         if let Object::Symbol(sym) = symbol.untag() {
-            let Some(var) = env.vars.get(sym) else { bail!("Void Variable: {sym}") };
-            self.stack.push(var.bind(cx));
+            let Some(var) = env.var(sym, cx) else { bail!("Void Variable: {sym}") };
+            self.stack.push(var);
             Ok(())
         } else {
             unreachable!("Varref was not a symbol: {:?}", symbol);
@@ -250,7 +253,7 @@ impl<'brw, 'ob> Routine<'brw> {
         let obj = self.frame.get_const(idx, cx);
         let symbol: Symbol = obj.try_into()?;
         let value = self.stack.pop(cx);
-        crate::data::set(symbol, value, env)?;
+        crate::data::set(symbol, value, env, cx)?;
         Ok(())
     }
 
@@ -326,6 +329,7 @@ impl<'brw, 'ob> Routine<'brw> {
     }
 
     fn run(&mut self, env: &mut Rt<Env>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        let binding_depth = env.binding_depth();
         'main: loop {
             let err = match self.execute_bytecode(env, cx) {
                 Ok(x) => return Ok(rebind!(x, cx)),
@@ -359,11 +363,15 @@ impl<'brw, 'ob> Routine<'brw> {
                     // full errors are implemented
                     cons!(sym::ERROR, format!("{err}"); cx)
                 };
+                env.unbind_to(handler.binding_depth, cx);
                 self.stack.truncate(handler.stack_size);
                 self.stack.push(error);
                 self.frame.pc.goto(handler.jump_code);
                 continue 'main;
             }
+            // Restore any bindings and saved state that the unwound code
+            // would have undone
+            env.unbind_to(binding_depth, cx);
             return Err(err);
         }
     }
@@ -371,7 +379,7 @@ impl<'brw, 'ob> Routine<'brw> {
     #[allow(clippy::too_many_lines)]
     /// The main bytecode execution loop.
     fn execute_bytecode(&mut self, env: &mut Rt<Env>, cx: &'ob mut Context) -> EvalResult<'ob> {
-        use crate::{alloc, arith, data, fns};
+        use crate::{alloc, arith, cmds, data, editfns, fns, indent, marker};
         use opcode::OpCode as op;
         loop {
             let op = match self.frame.pc.next().try_into() {
@@ -490,6 +498,7 @@ impl<'brw, 'ob> Routine<'brw> {
                     let handler = Handler {
                         jump_code: self.frame.pc.arg2(),
                         stack_size: self.stack.len(),
+                        binding_depth: env.binding_depth(),
                         condition,
                     };
                     self.handlers.push(handler);
@@ -591,7 +600,7 @@ impl<'brw, 'ob> Routine<'brw> {
                 op::Set => {
                     let newlet = self.stack.pop(cx);
                     let top = self.stack.top();
-                    top.set(data::set(top.bind_as(cx)?, newlet, env)?);
+                    top.set(data::set(top.bind_as(cx)?, newlet, env, cx)?);
                 }
                 op::Fset => {
                     let def = self.stack.pop(cx);
@@ -669,34 +678,101 @@ impl<'brw, 'ob> Routine<'brw> {
                     let args = &[top.bind_as(cx)?, arg1.try_into()?];
                     top.set(cx.add(arith::mul(args)));
                 }
-                op::Point => todo!("Point bytecode"),
-                op::GotoChar => todo!("GotoChar bytecode"),
+                op::Point => {
+                    let point = editfns::point(env)?;
+                    self.stack.push(cx.add(point));
+                }
+                op::GotoChar => {
+                    let top = self.stack.top();
+                    top.set::<GcObj>(editfns::goto_char(top.bind_as(cx)?, env)?.into());
+                }
                 op::Insert => todo!("Insert bytecode"),
-                op::PointMax => todo!("PointMax bytecode"),
-                op::PointMin => todo!("PointMin bytecode"),
-                op::CharAfter => todo!("CharAfter bytecode"),
-                op::FollowingChar => todo!("FollowingChar bytecode"),
-                op::PrecedingChar => todo!("PrecedingChar bytecode"),
-                op::CurrentColumn => todo!("CurrentColumn bytecode"),
-                op::IndentTo => todo!("IndentTo bytecode"),
-                op::EndOfLineP => todo!("EndOfLineP bytecode"),
-                op::EndOfBufferP => todo!("EndOfBufferP bytecode"),
-                op::BeginningOfLineP => todo!("BeginningOfLineP bytecode"),
-                op::BeginningOfBufferP => todo!("BeginningOfBufferP bytecode"),
+                op::PointMax => {
+                    let point_max = editfns::point_max(env)?;
+                    self.stack.push(cx.add(point_max));
+                }
+                op::PointMin => {
+                    let point_min = editfns::point_min(env)?;
+                    self.stack.push(cx.add(point_min));
+                }
+                op::CharAfter => {
+                    let top = self.stack.top();
+                    let pos = GcObj::try_from_option(top.bind(cx))?;
+                    top.set(editfns::char_after(pos, env)?);
+                }
+                op::FollowingChar => {
+                    let chr = editfns::following_char(env)?;
+                    self.stack.push(cx.add(chr));
+                }
+                op::PrecedingChar => {
+                    let chr = editfns::preceding_char(env)?;
+                    self.stack.push(cx.add(chr));
+                }
+                op::CurrentColumn => {
+                    let column = indent::current_column(env, cx)?;
+                    self.stack.push(cx.add(column));
+                }
+                op::IndentTo => {
+                    let top = self.stack.top();
+                    let column = indent::indent_to(top.bind_as(cx)?, None, env, cx)?;
+                    top.set(cx.add(column));
+                }
+                op::EndOfLineP => {
+                    let eolp = editfns::eolp(env)?;
+                    self.stack.push(cx.add(eolp));
+                }
+                op::EndOfBufferP => {
+                    let eobp = editfns::eobp(env)?;
+                    self.stack.push(cx.add(eobp));
+                }
+                op::BeginningOfLineP => {
+                    let bolp = editfns::bolp(env)?;
+                    self.stack.push(cx.add(bolp));
+                }
+                op::BeginningOfBufferP => {
+                    let bobp = editfns::bobp(env)?;
+                    self.stack.push(cx.add(bobp));
+                }
                 op::CurrentBuffer => todo!("CurrentBuffer bytecode"),
                 op::SetBuffer => todo!("SetBuffer bytecode"),
-                op::SaveCurrentBuffer1 => todo!("SaveCurrentBuffer1 bytecode"),
-                op::ForwardChar => todo!("ForwardChar bytecode"),
+                op::SaveCurrentBuffer1 => env.save_current_buffer(cx)?,
+                op::ForwardChar => {
+                    let top = self.stack.top();
+                    cmds::forward_char(top.bind_as(cx)?, env)?;
+                    top.set(nil());
+                }
                 op::ForwardWord => todo!("ForwardWord bytecode"),
                 op::SkipCharsForward => todo!("SkipCharsForward bytecode"),
                 op::SkipCharsBackward => todo!("SkipCharsBackward bytecode"),
-                op::ForwardLine => todo!("ForwardLine bytecode"),
+                op::ForwardLine => {
+                    let top = self.stack.top();
+                    let shortage = cmds::forward_line(top.bind_as(cx)?, env)?;
+                    top.set(cx.add(shortage));
+                }
                 op::CharSyntax => todo!("CharSyntax bytecode"),
-                op::BufferSubstring => todo!("BufferSubstring bytecode"),
+                op::BufferSubstring => {
+                    let end = self.stack.pop(cx);
+                    let top = self.stack.top();
+                    let string =
+                        editfns::buffer_substring(top.bind_as(cx)?, end.try_into()?, env, cx)?;
+                    top.set(cx.add(string));
+                }
                 op::DeleteRegion => todo!("DeleteRegion bytecode"),
-                op::NarrowToRegion => todo!("NarrowToRegion bytecode"),
-                op::Widen => todo!("Widen bytecode"),
-                op::EndOfLine => todo!("EndOfLine bytecode"),
+                op::NarrowToRegion => {
+                    let end = self.stack.pop(cx);
+                    let top = self.stack.top();
+                    editfns::narrow_to_region(top.bind_as(cx)?, end.try_into()?, env, cx)?;
+                    top.set(nil());
+                }
+                op::Widen => {
+                    editfns::widen(env)?;
+                    self.stack.push(nil());
+                }
+                op::EndOfLine => {
+                    let top = self.stack.top();
+                    cmds::end_of_line(top.bind_as(cx)?, env)?;
+                    top.set(nil());
+                }
                 op::ConstantN2 => {
                     let idx = self.frame.pc.arg2();
                     self.stack.push(self.frame.get_const(idx.into(), cx));
@@ -765,10 +841,17 @@ impl<'brw, 'ob> Routine<'brw> {
                     let top = self.stack[0].bind(cx);
                     self.stack.push(top);
                 }
-                op::SaveExcursion => todo!("SaveExcursion bytecode"),
-                op::SaveRestriction => todo!("SaveRestriction bytecode"),
+                op::SaveExcursion => env.save_excursion(cx)?,
+                op::SaveRestriction => env.save_restriction(cx)?,
                 op::UnwindProtect => todo!("UnwindProtect bytecode"),
-                op::SetMarker => todo!("SetMarker bytecode"),
+                op::SetMarker => {
+                    let buffer = self.stack.pop(cx);
+                    let position = self.stack.pop(cx);
+                    let top = self.stack.top();
+                    let position = GcObj::try_from_option(position)?;
+                    let buffer = GcObj::try_from_option(buffer)?;
+                    top.set(marker::set_marker(top.bind_as(cx)?, position, buffer, env, cx)?);
+                }
                 op::MatchBeginning => todo!("MatchBeginning bytecode"),
                 op::MatchEnd => todo!("MatchEnd bytecode"),
                 op::Upcase => todo!("Upcase bytecode"),
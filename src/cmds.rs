@@ -0,0 +1,89 @@
+use crate::core::{
+    env::{sym, Env},
+    error::EvalError,
+    gc::Rt,
+    object::nil,
+};
+use anyhow::{bail, Result};
+use fn_macros::defun;
+
+#[allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss,
+    clippy::cast_possible_truncation
+)]
+#[defun]
+pub(crate) fn forward_char(n: Option<i64>, env: &mut Rt<Env>) -> Result<()> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    let (min, max) = (buffer.point_min() as i64, buffer.point_max() as i64);
+    let target = (buffer.point() as i64).saturating_add(n.unwrap_or(1));
+    let pos = target.clamp(min, max);
+    buffer.goto_char(pos as usize);
+    if pos == target {
+        return Ok(());
+    }
+    // Point is left at the edge of the accessible region
+    let error = if pos == min { sym::BEGINNING_OF_BUFFER } else { sym::END_OF_BUFFER };
+    Err(EvalError::signal(error.into(), nil(), env).into())
+}
+
+#[defun]
+pub(crate) fn forward_line(n: Option<i64>, env: &mut Rt<Env>) -> Result<i64> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    Ok(buffer.forward_line(n.unwrap_or(1)))
+}
+
+#[defun]
+pub(crate) fn end_of_line(n: Option<i64>, env: &mut Rt<Env>) -> Result<()> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    let n = n.unwrap_or(1);
+    if n != 1 {
+        buffer.forward_line(n.saturating_sub(1));
+    }
+    let end = buffer.line_end(buffer.point());
+    buffer.goto_char(end);
+    Ok(())
+}
+
+defsym!(BEGINNING_OF_BUFFER);
+defsym!(END_OF_BUFFER);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::{get_buffer_create, set_buffer},
+        core::gc::{Context, RootSet},
+        editfns::{goto_char, insert, point},
+        root,
+    };
+
+    #[test]
+    fn test_motion() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let buffer = get_buffer_create(cx.add("test_motion"), None, env, cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        cx.garbage_collect(true);
+        insert(&[cx.add("one\ntwo\nthree")], env).unwrap();
+
+        goto_char(0_i64.into(), env).unwrap();
+        forward_char(Some(2), env).unwrap();
+        assert_eq!(point(env).unwrap(), 2);
+        assert!(forward_char(Some(-5), env).is_err());
+        assert_eq!(point(env).unwrap(), 0);
+
+        assert_eq!(forward_line(None, env).unwrap(), 0);
+        assert_eq!(point(env).unwrap(), 4);
+        end_of_line(None, env).unwrap();
+        assert_eq!(point(env).unwrap(), 7);
+        // The partial last line counts as a line moved over
+        assert_eq!(forward_line(Some(3), env).unwrap(), 1);
+        assert_eq!(point(env).unwrap(), 13);
+        assert_eq!(forward_line(Some(-5), env).unwrap(), -3);
+        assert_eq!(point(env).unwrap(), 0);
+        assert!(forward_char(Some(20), env).is_err());
+        assert_eq!(point(env).unwrap(), 13);
+    }
+}
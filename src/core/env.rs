@@ -1,15 +1,27 @@
 #![allow(unstable_name_collisions)]
 use super::gc::{Block, Context, Rt};
-use super::object::{Buffer, CloneIn, Function, Gc, GcObj, LispBuffer, WithLifetime};
+use super::object::{Buffer, CloneIn, Function, Gc, GcObj, LispBuffer, TextMarker, WithLifetime};
 use crate::hashmap::HashMap;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use fn_macros::Trace;
 use std::collections::VecDeque;
 use std::sync::Mutex;
+use text_buffer::InsertionType;
 
 mod symbol;
 pub(crate) use symbol::*;
 
+/// State saved by `save-excursion`, `save-restriction` and
+/// `save-current-buffer`. It is restored when it is unbound.
+#[derive(Debug)]
+enum SavedState {
+    /// The current buffer and its point
+    Excursion(&'static LispBuffer, TextMarker),
+    /// A buffer and the bounds of its restriction, if it was narrowed
+    Restriction(&'static LispBuffer, Option<(TextMarker, TextMarker)>),
+    CurrentBuffer(&'static LispBuffer),
+}
+
 #[derive(Debug, Default, Trace)]
 pub(crate) struct Env {
     pub(crate) vars: HashMap<Symbol<'static>, GcObj<'static>>,
@@ -19,14 +31,58 @@ pub(crate) struct Env {
     #[no_trace]
     exception_id: u32,
     binding_stack: Vec<(Symbol<'static>, Option<GcObj<'static>>)>,
+    /// The entries of `binding_stack` that bind a buffer-local value, by
+    /// index, along with the buffer they are local to.
+    #[no_trace]
+    local_bindings: Vec<(usize, &'static LispBuffer)>,
+    /// State saved by the `save-*` forms, along with the length of
+    /// `binding_stack` when it was saved. Together they act as one stack.
+    #[no_trace]
+    saved_states: Vec<(usize, SavedState)>,
+    /// The buffers in `local_bindings` and `saved_states`, which are kept
+    /// alive until they are unbound even if they are killed.
+    saved_buffers: Vec<&'static LispBuffer>,
     pub(crate) match_data: GcObj<'static>,
+    /// The live buffers, in creation order except that selected buffers are
+    /// moved to the front. Buffers are found by name through this list, which
+    /// also keeps them from being collected until they are killed.
     pub(crate) buffer_list: VecDeque<&'static LispBuffer>,
+    /// The buffer object of `current_buffer`. Making a buffer current does
+    /// not change its place in `buffer_list`.
+    current: Option<&'static LispBuffer>,
     #[no_trace]
     pub(crate) current_buffer: Option<Buffer<'static>>,
 }
 
 impl Rt<Env> {
-    pub(crate) fn set_var(&mut self, sym: Symbol, value: GcObj) -> Result<()> {
+    /// The value of `sym` in the current buffer.
+    pub(crate) fn var<'ob>(&self, sym: Symbol, cx: &'ob Context) -> Option<GcObj<'ob>> {
+        let local = self.current_lisp_buffer(cx).and_then(|b| b.local_value(sym, cx));
+        local.or_else(|| self.vars.get(sym).map(|x| x.bind(cx)))
+    }
+
+    /// Set the value of `sym` in the current buffer. This sets the local value
+    /// if there is one, and the default value otherwise.
+    pub(crate) fn set_var(&mut self, sym: Symbol, value: GcObj, cx: &Context) -> Result<()> {
+        if sym.is_const() {
+            bail!("Attempt to set a constant symbol: {sym}");
+        }
+        if let Some(buffer) = self.current_lisp_buffer(cx) {
+            // Setting a variable that is bound by `let` does not make it
+            // local, so that the binding can be undone
+            let make_local = sym.is_local_if_set() && !self.let_bound_default(sym);
+            if make_local || buffer.has_local(sym) {
+                buffer.set_local(sym, value);
+                return Ok(());
+            }
+        }
+        self.vars.insert(sym, value);
+        Ok(())
+    }
+
+    /// Set the default value of `sym`, which is seen in buffers that don't
+    /// have a local value.
+    pub(crate) fn set_default(&mut self, sym: Symbol, value: GcObj) -> Result<()> {
         if sym.is_const() {
             Err(anyhow!("Attempt to set a constant symbol: {sym}"))
         } else {
@@ -35,6 +91,13 @@ impl Rt<Env> {
         }
     }
 
+    /// Whether the default value of `sym` is bound by `let`.
+    fn let_bound_default(&self, sym: Symbol) -> bool {
+        let is_local = |idx| self.local_bindings.iter().any(|(local, _)| *local == idx);
+        let mut bindings = self.binding_stack.iter().enumerate();
+        bindings.any(|(idx, binding)| binding.0 == sym && !is_local(idx))
+    }
+
     pub(crate) fn set_prop(&mut self, symbol: Symbol, propname: Symbol, value: GcObj) {
         match self.props.get_mut(symbol) {
             Some(plist) => match plist.iter_mut().find(|x| x.0 == propname) {
@@ -62,6 +125,18 @@ impl Rt<Env> {
     }
 
     pub(crate) fn varbind(&mut self, var: Symbol, value: GcObj, cx: &Context) {
+        // A variable that is local to the current buffer is only bound there,
+        // even if the current buffer changes before it is unbound
+        if let Some(buffer) = self.current_lisp_buffer(cx) {
+            if let Some(prev_value) = buffer.local_value(var, cx) {
+                let depth = self.binding_stack.len();
+                self.local_bindings.push((depth, buffer));
+                self.saved_buffers.push(buffer);
+                self.binding_stack.push((var, Some(prev_value)));
+                buffer.set_local(var, value);
+                return;
+            }
+        }
         let prev_value = self.vars.get(var).map(|x| x.bind(cx));
         self.binding_stack.push((var, prev_value));
         self.vars.insert(var, value);
@@ -69,18 +144,117 @@ impl Rt<Env> {
 
     pub(crate) fn unbind(&mut self, count: u16, cx: &Context) {
         for _ in 0..count {
-            match self.binding_stack.bind_mut(cx).pop() {
-                Some((sym, val)) => match val {
-                    Some(val) => self.vars.insert(sym, val),
-                    None => self.vars.remove(sym),
-                },
-                None => panic!("Binding stack was empty"),
+            self.pop_binding(cx);
+        }
+    }
+
+    /// The depth of the binding stack, counting both variable bindings and
+    /// saved state.
+    pub(crate) fn binding_depth(&self) -> usize {
+        self.binding_stack.len() + self.saved_states.len()
+    }
+
+    /// Unbind variables and restore saved state until the binding stack is
+    /// `depth` deep. This is used to clean up after a non-local exit.
+    pub(crate) fn unbind_to(&mut self, depth: usize, cx: &Context) {
+        while self.binding_depth() > depth {
+            self.pop_binding(cx);
+        }
+    }
+
+    fn pop_binding(&mut self, cx: &Context) {
+        let len = self.binding_stack.len();
+        if self.saved_states.last().is_some_and(|(saved_len, _)| *saved_len == len) {
+            let (_, state) = self.saved_states.pop().unwrap();
+            self.saved_buffers.pop();
+            self.restore(state, cx);
+            return;
+        }
+        let local = match self.local_bindings.last() {
+            Some((idx, buffer)) if idx + 1 == len => Some(*buffer),
+            _ => None,
+        };
+        let Some((sym, val)) = self.binding_stack.bind_mut(cx).pop() else {
+            panic!("Binding stack was empty")
+        };
+        if let Some(buffer) = local {
+            self.local_bindings.pop();
+            self.saved_buffers.pop();
+            // Nothing is restored if the local value was killed since
+            if let Some(val) = val.filter(|_| buffer.has_local(sym)) {
+                buffer.set_local(sym, val);
+            }
+            return;
+        }
+        match val {
+            Some(val) => self.vars.insert(sym, val),
+            None => self.vars.remove(sym),
+        }
+    }
+
+    /// Save the current buffer and point, for `save-excursion`.
+    pub(crate) fn save_excursion(&mut self, cx: &Context) -> Result<()> {
+        let Some(buffer) = self.current_lisp_buffer(cx) else { bail!("No current buffer") };
+        let current = self.current_buffer.as_ref().unwrap();
+        let point = current.create_marker(current.point(), InsertionType::Stay);
+        self.push_saved(buffer, SavedState::Excursion(buffer, point));
+        Ok(())
+    }
+
+    /// Save the restriction of the current buffer, for `save-restriction`.
+    pub(crate) fn save_restriction(&mut self, cx: &Context) -> Result<()> {
+        let Some(buffer) = self.current_lisp_buffer(cx) else { bail!("No current buffer") };
+        let current = self.current_buffer.as_ref().unwrap();
+        // Text inserted at either bound stays inside the restriction
+        let bounds = current.restriction().map(|(beg, end)| {
+            let beg = current.create_marker(beg, InsertionType::Stay);
+            (beg, current.create_marker(end, InsertionType::Advance))
+        });
+        self.push_saved(buffer, SavedState::Restriction(buffer, bounds));
+        Ok(())
+    }
+
+    /// Save the current buffer, for `save-current-buffer`.
+    pub(crate) fn save_current_buffer(&mut self, cx: &Context) -> Result<()> {
+        let Some(buffer) = self.current_lisp_buffer(cx) else { bail!("No current buffer") };
+        self.push_saved(buffer, SavedState::CurrentBuffer(buffer));
+        Ok(())
+    }
+
+    fn push_saved(&mut self, buffer: &'static LispBuffer, state: SavedState) {
+        let len = self.binding_stack.len();
+        self.saved_states.push((len, state));
+        self.saved_buffers.push(buffer);
+    }
+
+    /// Restore saved state. Nothing is restored in a buffer that has been
+    /// killed since.
+    fn restore(&mut self, state: SavedState, cx: &Context) {
+        match state {
+            SavedState::Excursion(buffer, point) => {
+                if self.set_buffer(buffer, cx).is_ok() {
+                    let current = self.current_buffer.as_mut().unwrap();
+                    current.goto_char(point.position());
+                }
+            }
+            SavedState::Restriction(buffer, bounds) => {
+                self.with_buffer(buffer, |buffer| match (buffer, &bounds) {
+                    // Markers never point outside of their buffer
+                    (Some(buffer), Some((beg, end))) => {
+                        let _ = buffer.narrow(beg.position(), end.position());
+                    }
+                    (Some(buffer), None) => buffer.widen(),
+                    (None, _) => {}
+                });
+            }
+            SavedState::CurrentBuffer(buffer) => {
+                let _ = self.set_buffer(buffer, cx);
             }
         }
     }
 
     pub(crate) fn defvar(&mut self, var: Symbol, value: GcObj) -> Result<()> {
-        self.set_var(var, value)?;
+        self.set_default(var, value)?;
         var.make_special();
         // If this variable was unbound previously in the binding stack,
         // we will bind it to the new value
@@ -93,21 +267,47 @@ impl Rt<Env> {
     }
 
     pub(crate) fn set_buffer(&mut self, buffer: &LispBuffer, cx: &Context) -> Result<()> {
-        let buffer_list = self.buffer_list.bind_mut(cx);
-        buffer_list.push_front(buffer);
+        // The buffer is already locked if it is current
+        if self.current_lisp_buffer(cx).is_some_and(|current| current == buffer) {
+            return Ok(());
+        }
         // SAFETY: We are not dropping the buffer until we have can trace it
         // with the garbage collector
         let lock = unsafe { buffer.lock()?.with_lifetime() };
         self.current_buffer = Some(lock);
+        *self.current.bind_mut(cx) = Some(buffer);
         Ok(())
     }
 
+    /// Move `buffer` to the front of the buffer list, for when it is
+    /// explicitly selected.
+    pub(crate) fn record_buffer(&mut self, buffer: &LispBuffer, cx: &Context) {
+        let buffer_list = self.buffer_list.bind_mut(cx);
+        buffer_list.retain(|x| *x != buffer);
+        buffer_list.push_front(buffer);
+    }
+
+    /// The buffer object of the current buffer.
+    pub(crate) fn current_lisp_buffer(&self, cx: &Context) -> Option<&'static LispBuffer> {
+        self.current_buffer.as_ref()?;
+        let buffer = self.current.bind(cx)?;
+        // SAFETY: This can be 'static because the current buffer is rooted
+        Some(unsafe { buffer.as_static() })
+    }
+
+    /// The live buffer named `name`.
+    pub(crate) fn get_buffer(&self, name: &str, cx: &Context) -> Option<&'static LispBuffer> {
+        let buffer = *self.buffer_list.bind_ref(cx).iter().find(|x| x.is_named(name))?;
+        // SAFETY: This can be 'static because the buffer list is rooted
+        Some(unsafe { buffer.as_static() })
+    }
+
     pub(crate) fn with_buffer<T>(
         &mut self,
         buffer: &LispBuffer,
         func: impl Fn(Option<&mut Buffer>) -> T,
     ) -> T {
-        if let Some(current) = unsafe { self.buffer_list.bind_mut_unchecked().front() } {
+        if let Some(current) = unsafe { self.current.bind_mut_unchecked() } {
             if *current == buffer && self.current_buffer.is_some() {
                 return func(Some(self.current_buffer.as_mut().unwrap()));
             }
@@ -224,10 +424,6 @@ impl ObjectMap {
         unsafe { symbol.set_func(new_func) }
     }
 
-    pub(crate) fn create_buffer(&self, name: &str) -> &LispBuffer {
-        LispBuffer::create(name.to_owned(), &self.block)
-    }
-
     pub(crate) fn get(&self, name: &str) -> Option<Symbol> {
         self.map.get(name)
     }
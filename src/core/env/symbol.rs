@@ -30,6 +30,8 @@ pub(crate) struct SymbolCell {
     // https://github.com/crossbeam-rs/crossbeam/issues/748
     func: Option<AtomicPtr<u8>>,
     special: AtomicBool,
+    // Whether setting this variable makes it local to the current buffer
+    local_if_set: AtomicBool,
 }
 
 #[derive(Debug)]
@@ -100,6 +102,15 @@ impl<'a> Symbol<'a> {
     pub(crate) fn is_special(self) -> bool {
         self.special.load(Ordering::Acquire)
     }
+
+    /// Make this variable become buffer-local whenever it is set.
+    pub(crate) fn make_local_if_set(self) {
+        self.local_if_set.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_local_if_set(self) -> bool {
+        self.local_if_set.load(Ordering::Acquire)
+    }
 }
 
 unsafe impl Send for Symbol<'_> {}
@@ -200,6 +211,7 @@ impl SymbolCell {
                 func: Some(Self::EMTPTY),
                 marked: AtomicBool::new(true),
                 special: AtomicBool::new(false),
+                local_if_set: AtomicBool::new(false),
             }
         }
     }
@@ -210,6 +222,7 @@ impl SymbolCell {
             func: Some(Self::EMTPTY),
             marked: AtomicBool::new(true),
             special: AtomicBool::new(true),
+            local_if_set: AtomicBool::new(false),
         }
     }
 
@@ -219,6 +232,7 @@ impl SymbolCell {
             func: None,
             marked: AtomicBool::new(true),
             special: AtomicBool::new(true),
+            local_if_set: AtomicBool::new(false),
         }
     }
 
@@ -228,6 +242,7 @@ impl SymbolCell {
             func: Some(Self::EMTPTY),
             marked: AtomicBool::new(false),
             special: AtomicBool::new(false),
+            local_if_set: AtomicBool::new(false),
         }
     }
 
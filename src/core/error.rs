@@ -155,6 +155,8 @@ pub(crate) enum Type {
     Number,
     List,
     Buffer,
+    Marker,
+    IntOrMarker,
 }
 
 /// Error provided if object was the wrong type
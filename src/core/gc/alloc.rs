@@ -1,7 +1,9 @@
 use super::Block;
 use crate::core::cons::Cons;
 use crate::core::env::SymbolCell;
-use crate::core::object::{ByteFn, LispBuffer, LispFloat, LispHashTable, LispString, LispVec};
+use crate::core::object::{
+    ByteFn, LispBuffer, LispFloat, LispHashTable, LispMarker, LispString, LispVec,
+};
 use std::fmt::Debug;
 
 /// The owner of an object allocation. No references to
@@ -16,6 +18,7 @@ pub(super) enum OwnedObject {
     Symbol(Box<SymbolCell>),
     ByteFn(Box<ByteFn>),
     Buffer(Box<LispBuffer>),
+    Marker(Box<LispMarker>),
 }
 
 pub(in crate::core) trait AllocObject
@@ -113,10 +116,21 @@ impl AllocObject for LispBuffer {
     type Output = Self;
 
     fn alloc_obj<const CONST: bool>(self, block: &Block<CONST>) -> *const Self::Output {
-        assert!(CONST, "Buffers must only be created in the shared block");
+        assert!(!CONST, "Buffers must be created in the block of the context that owns them");
         let mut objects = block.objects.borrow_mut();
         Block::<CONST>::register(&mut objects, OwnedObject::Buffer(Box::new(self)));
         let Some(OwnedObject::Buffer(x)) = objects.last() else { unreachable!() };
         x.as_ref()
     }
 }
+
+impl AllocObject for LispMarker {
+    type Output = Self;
+
+    fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
+        let mut objects = block.objects.borrow_mut();
+        Block::<C>::register(&mut objects, OwnedObject::Marker(Box::new(self)));
+        let Some(OwnedObject::Marker(x)) = objects.last() else { unreachable!() };
+        x.as_ref()
+    }
+}
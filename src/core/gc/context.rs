@@ -150,6 +150,12 @@ impl<'ob, 'rt> Context<'rt> {
         self.root_set
     }
 
+    /// The number of objects allocated in this context.
+    #[cfg(test)]
+    pub(crate) fn object_count(&self) -> usize {
+        self.block.objects.borrow().len()
+    }
+
     pub(crate) fn garbage_collect(&mut self, force: bool) {
         let mut objects = self.block.objects.borrow_mut();
         if cfg!(not(test))
@@ -197,7 +203,8 @@ impl OwnedObject {
             OwnedObject::String(x) => x.unmark(),
             OwnedObject::Symbol(x) => x.unmark(),
             OwnedObject::ByteFn(x) => x.unmark(),
-            OwnedObject::Buffer(_) => todo!("unmark buffer"),
+            OwnedObject::Buffer(x) => x.unmark(),
+            OwnedObject::Marker(x) => x.unmark(),
         }
     }
 
@@ -210,7 +217,8 @@ impl OwnedObject {
             OwnedObject::String(x) => x.is_marked(),
             OwnedObject::Symbol(x) => x.is_marked(),
             OwnedObject::ByteFn(x) => x.is_marked(),
-            OwnedObject::Buffer(_) => todo!("is_marked buffer"),
+            OwnedObject::Buffer(x) => x.is_marked(),
+            OwnedObject::Marker(x) => x.is_marked(),
         }
     }
 }
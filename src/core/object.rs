@@ -14,6 +14,7 @@ mod convert;
 mod float;
 mod func;
 mod hashtable;
+mod marker;
 mod string;
 mod tagged;
 mod vector;
@@ -24,6 +25,7 @@ pub(crate) use convert::*;
 pub(crate) use float::*;
 pub(crate) use func::*;
 pub(crate) use hashtable::*;
+pub(crate) use marker::*;
 pub(crate) use string::*;
 pub(crate) use tagged::*;
 pub(crate) use vector::*;
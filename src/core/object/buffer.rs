@@ -1,14 +1,19 @@
-use super::{Gc, GcObj, Object, RawObj, TagType, WithLifetime};
+use super::{Gc, GcObj, MarkerLink, Object, RawObj, TagType, WithLifetime};
 use crate::core::{
+    env::Symbol,
     error::{Type, TypeError},
-    gc::{AllocObject, Block, GcManaged, GcMark, Trace},
+    gc::{AllocObject, Block, Context, GcManaged, GcMark, Trace},
 };
+use crate::hashmap::HashMap;
 use anyhow::{bail, Result};
 use std::{
     fmt::Display,
-    sync::{Mutex, MutexGuard},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
 };
-use text_buffer::Buffer as TextBuffer;
+use text_buffer::{Buffer as TextBuffer, BufferError, InsertionType, MarkerId, ViewId};
 
 #[derive(Debug)]
 pub(crate) struct Buffer<'a> {
@@ -29,27 +34,179 @@ impl<'a> Buffer<'a> {
         self.data.as_ref().unwrap()
     }
 
-    fn get_mut(&mut self) -> &mut BufferData {
-        // buffer can never be none because we check it as part of `new`. Could
-        // make this unchecked at some point.
-        self.data.as_mut().unwrap()
+    /// Lock the text of the buffer and make this buffer's view of it active.
+    /// The text is shared with any indirect buffers, so it is only locked for
+    /// the duration of one operation.
+    fn text(&self) -> MutexGuard<'_, TextBuffer> {
+        let data = self.get();
+        let mut text = data.text.lock().unwrap();
+        text.set_view(data.view);
+        text
+    }
+
+    /// Create a new view of this buffer's text for an indirect buffer. It
+    /// starts with the same point and restriction as this buffer.
+    pub(crate) fn indirect_text(&self) -> IndirectText {
+        let view = self.text().create_view();
+        IndirectText { text: self.get().text.clone(), view }
+    }
+
+    pub(crate) fn point(&self) -> usize {
+        self.text().cursor()
+    }
+
+    /// Create a marker at `pos` in the text of this buffer.
+    pub(crate) fn create_marker(&self, pos: usize, kind: InsertionType) -> TextMarker {
+        let id = self.text().create_marker(pos, kind);
+        TextMarker { text: self.get().text.clone(), id }
     }
 
     pub(crate) fn insert(&mut self, arg: GcObj) -> Result<()> {
         match arg.untag() {
             Object::Int(i) => {
-                let Ok(u_32) = i.try_into() else { bail!("{i} is an invalid char") };
-                let Some(chr) = char::from_u32(u_32) else { bail!("{i} is an Invalid char") };
-                self.get_mut().text.insert_char(chr);
+                let Ok(code) = u32::try_from(i) else { bail!("{i} is an invalid char") };
+                if code > text_buffer::MAX_CHAR {
+                    bail!("{i} is an invalid char");
+                }
+                self.text().insert_char_code(code);
             }
-            Object::String(s) => self.get_mut().text.insert(s.try_into()?),
+            Object::String(s) => self.text().insert(s.try_into()?),
             x => bail!(TypeError::new(Type::String, x)),
         }
         Ok(())
     }
 
-    pub(crate) fn delete(&mut self, beg: usize, end: usize) {
-        self.get_mut().text.delete_range(beg, end);
+    pub(crate) fn insert_str(&mut self, text: &str) {
+        self.text().insert(text);
+    }
+
+    pub(crate) fn delete(&mut self, beg: usize, end: usize) -> Result<(), BufferError> {
+        let (beg, end) = (beg.min(end), beg.max(end));
+        self.text().try_delete_range(beg, end)
+    }
+
+    /// Restrict the accessible region to `beg..end`. Both positions must be
+    /// inside the whole buffer, not just the current restriction.
+    pub(crate) fn narrow(&mut self, beg: usize, end: usize) -> Result<(), BufferError> {
+        let mut text = self.text();
+        let len = text.len_chars();
+        if let Some(pos) = [beg, end].into_iter().find(|pos| *pos > len) {
+            return Err(BufferError::OutOfRange { pos, start: 0, end: len });
+        }
+        text.narrow(beg..end);
+        Ok(())
+    }
+
+    pub(crate) fn widen(&mut self) {
+        self.text().widen();
+    }
+
+    /// The bounds of the accessible region, if the buffer is narrowed.
+    pub(crate) fn restriction(&self) -> Option<(usize, usize)> {
+        let text = self.text();
+        text.is_narrowed().then(|| (text.begv(), text.zv()))
+    }
+
+    /// The start of the accessible region.
+    pub(crate) fn point_min(&self) -> usize {
+        self.text().begv()
+    }
+
+    /// The end of the accessible region.
+    pub(crate) fn point_max(&self) -> usize {
+        self.text().zv()
+    }
+
+    /// Move point to `pos`, clamped to the accessible region.
+    pub(crate) fn goto_char(&mut self, pos: usize) {
+        self.text().set_cursor(pos);
+    }
+
+    /// The char after `pos`, or `None` if `pos` is at or past the end of the
+    /// accessible region.
+    pub(crate) fn char_after(&self, pos: usize) -> Option<u32> {
+        let text = self.text();
+        if pos < text.begv() || pos >= text.zv() {
+            return None;
+        }
+        text.chars_at(pos).next()
+    }
+
+    /// The char before `pos`, or `None` if `pos` is at or before the start of
+    /// the accessible region.
+    pub(crate) fn char_before(&self, pos: usize) -> Option<u32> {
+        let text = self.text();
+        if pos <= text.begv() || pos > text.zv() {
+            return None;
+        }
+        text.chars_at(pos).prev()
+    }
+
+    /// The end of the line containing `pos`, limited to the accessible region.
+    pub(crate) fn line_end(&self, pos: usize) -> usize {
+        let text = self.text();
+        text.line_end(pos).min(text.zv())
+    }
+
+    /// Move point to the start of the line `count` lines forward (backward if
+    /// negative) and return how many lines short of that it stopped. Like
+    /// `forward-line`, a partial line at the end of the accessible region
+    /// counts as a line moved over.
+    #[allow(
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation
+    )]
+    pub(crate) fn forward_line(&mut self, count: i64) -> i64 {
+        let mut text = self.text();
+        let (begv, zv, point) = (text.begv(), text.zv(), text.cursor());
+        let line = text.char_to_line(point) as i64;
+        let target = line.saturating_add(count);
+        if count <= 0 {
+            let first = text.char_to_line(begv) as i64;
+            let pos = text.line_to_char(target.max(first) as usize).max(begv);
+            text.set_cursor(pos);
+            target.saturating_sub(first).min(0)
+        } else {
+            let last = text.char_to_line(zv) as i64;
+            if target <= last {
+                let pos = text.line_to_char(target as usize);
+                text.set_cursor(pos);
+                return 0;
+            }
+            text.set_cursor(zv);
+            let partial_line = zv != point && text.line_start(zv) != zv;
+            target - last - i64::from(partial_line)
+        }
+    }
+
+    /// The text between `beg` and `end`, in either order.
+    pub(crate) fn substring(&self, beg: usize, end: usize) -> Result<String, BufferError> {
+        let (beg, end) = (beg.min(end), beg.max(end));
+        Ok(self.text().try_read_chars(beg..end)?.into_owned())
+    }
+
+    pub(crate) fn current_column(&self, tab_width: usize) -> usize {
+        let text = self.text();
+        text.char_to_column(text.cursor(), tab_width)
+    }
+
+    pub(crate) fn is_modified(&self) -> bool {
+        self.text().is_modified()
+    }
+
+    pub(crate) fn set_modified(&mut self, modified: bool) {
+        self.text().set_modified(modified);
+    }
+
+    /// Move the cursor to `column` on the current line and return the column
+    /// it ended up at.
+    pub(crate) fn move_to_column(&mut self, column: usize, tab_width: usize) -> usize {
+        let mut text = self.text();
+        let line = text.char_to_line(text.cursor());
+        let pos = text.line_column_to_char(line, column, tab_width);
+        text.set_cursor(pos);
+        text.char_to_column(pos, tab_width)
     }
 }
 
@@ -63,34 +220,224 @@ impl<'old, 'new> WithLifetime<'new> for Buffer<'old> {
 
 impl PartialEq<str> for Buffer<'_> {
     fn eq(&self, other: &str) -> bool {
-        self.get().text == other
+        *self.text() == other
+    }
+}
+
+/// A marker in the text of a buffer. It only needs to lock the text, so it can
+/// be used while the buffer is current. The marker is removed from the text
+/// when this is dropped.
+#[derive(Debug)]
+pub(crate) struct TextMarker {
+    text: Arc<Mutex<TextBuffer>>,
+    id: MarkerId,
+}
+
+impl TextMarker {
+    pub(crate) fn position(&self) -> usize {
+        self.text.lock().unwrap().marker_position(self.id)
+    }
+
+    pub(crate) fn set_position(&self, pos: usize) {
+        self.text.lock().unwrap().set_marker(self.id, pos);
+    }
+
+    pub(crate) fn set_insertion_type(&self, kind: InsertionType) {
+        self.text.lock().unwrap().set_marker_insertion_type(self.id, kind);
     }
+
+    /// Create a new marker at the same position with the same insertion type.
+    pub(crate) fn copy(&self) -> Self {
+        let mut text = self.text.lock().unwrap();
+        let (pos, kind) = (text.marker_position(self.id), text.marker_insertion_type(self.id));
+        let id = text.create_marker(pos, kind);
+        Self { text: self.text.clone(), id }
+    }
+}
+
+impl Drop for TextMarker {
+    fn drop(&mut self) {
+        self.text.lock().unwrap().remove_marker(self.id);
+    }
+}
+
+/// A view of the text of a buffer, used to create an indirect buffer.
+#[derive(Debug)]
+pub(crate) struct IndirectText {
+    text: Arc<Mutex<TextBuffer>>,
+    view: ViewId,
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 struct BufferData {
-    name: String,
-    text: TextBuffer,
+    text: Arc<Mutex<TextBuffer>>,
+    /// The view of `text` that holds this buffer's point and restriction
+    view: ViewId,
 }
 
 #[derive(Debug)]
 pub(crate) struct LispBuffer {
+    /// The mark bit. It is atomic rather than a [`GcMark`] so that buffers
+    /// are `Sync`, which markers and indirect buffers that refer to them need.
+    marked: AtomicBool,
+    /// The name of the buffer, or `None` once it has been killed
+    name: Mutex<Option<String>>,
     text_buffer: Mutex<Option<BufferData>>,
+    /// The buffer that owns the text, if this is an indirect buffer
+    base: Option<&'static LispBuffer>,
+    /// The buffer-local values of variables. These are kept apart from the
+    /// text so that they can be reached while the buffer is current.
+    locals: Mutex<HashMap<Symbol<'static>, GcObj<'static>>>,
+    /// Links to the markers that point into this buffer
+    markers: Mutex<Vec<MarkerLink>>,
 }
 
 impl LispBuffer {
-    pub(crate) fn create(name: String, block: &Block<true>) -> &LispBuffer {
-        let new =
-            Self { text_buffer: Mutex::new(Some(BufferData { name, text: TextBuffer::new() })) };
+    pub(crate) fn create<const C: bool>(name: String, block: &Block<C>) -> &LispBuffer {
+        let text = TextBuffer::new();
+        let view = text.view();
+        let text = Arc::new(Mutex::new(text));
+        Self::alloc(name, BufferData { text, view }, None, block)
+    }
+
+    /// Create a buffer that shares the text of `base`.
+    pub(crate) fn create_indirect<'ob, const C: bool>(
+        name: String,
+        base: &'static LispBuffer,
+        text: IndirectText,
+        block: &'ob Block<C>,
+    ) -> &'ob LispBuffer {
+        let IndirectText { text, view } = text;
+        Self::alloc(name, BufferData { text, view }, Some(base), block)
+    }
+
+    fn alloc<'ob, const C: bool>(
+        name: String,
+        data: BufferData,
+        base: Option<&'static LispBuffer>,
+        block: &'ob Block<C>,
+    ) -> &'ob LispBuffer {
+        let new = Self {
+            marked: AtomicBool::new(false),
+            name: Mutex::new(Some(name)),
+            text_buffer: Mutex::new(Some(data)),
+            base,
+            locals: Mutex::new(HashMap::default()),
+            markers: Mutex::new(Vec::new()),
+        };
         let ptr = new.alloc_obj(block);
         unsafe { &*ptr }
     }
 
+    /// The name of the buffer, or `None` if it has been killed.
+    pub(crate) fn name(&self) -> Option<String> {
+        self.name.lock().unwrap().clone()
+    }
+
+    /// Extend the lifetime of a reference to this buffer to `'static`.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must not be collected while the returned reference is in
+    /// use. A live buffer is rooted by the buffer list of `Env`, but a killed
+    /// buffer has to be rooted some other way.
+    pub(crate) unsafe fn as_static(&self) -> &'static LispBuffer {
+        self.with_lifetime()
+    }
+
+    pub(crate) fn is_live(&self) -> bool {
+        self.name.lock().unwrap().is_some()
+    }
+
+    pub(crate) fn is_named(&self, name: &str) -> bool {
+        self.name.lock().unwrap().as_deref() == Some(name)
+    }
+
+    /// The buffer this buffer shares its text with, if it is a live indirect
+    /// buffer.
+    pub(crate) fn base(&self) -> Option<&'static LispBuffer> {
+        self.base.filter(|_| self.is_live())
+    }
+
+    /// Change the name of a live buffer.
+    pub(crate) fn rename(&self, name: String) {
+        if let Some(old) = self.name.lock().unwrap().as_mut() {
+            *old = name;
+        }
+    }
+
+    /// Keep a link to a marker that was set to point into this buffer.
+    pub(crate) fn add_marker(&self, link: MarkerLink) {
+        let mut markers = self.markers.lock().unwrap();
+        // Drop the links to markers that were collected or moved elsewhere
+        // before growing, so the list stays proportional to the live markers
+        if markers.len() == markers.capacity() {
+            markers.retain(|x| x.points_into(self));
+        }
+        markers.push(link);
+    }
+
+    /// Release the text and local variables of the buffer and make every
+    /// marker in it point nowhere. It can't be made current again. This must
+    /// not be called while the buffer is current.
+    pub(crate) fn kill(&self) {
+        *self.name.lock().unwrap() = None;
+        self.locals.lock().unwrap().clear();
+        let markers = std::mem::take(&mut *self.markers.lock().unwrap());
+        for marker in markers {
+            marker.detach(self);
+        }
+        let data = self.text_buffer.lock().unwrap().take();
+        // The text may still be used by other buffers
+        if let Some(data) = data.filter(|_| self.base.is_some()) {
+            let mut text = data.text.lock().unwrap();
+            if text.view() != data.view {
+                text.remove_view(data.view);
+            }
+        }
+    }
+
     pub(in crate::core) fn lock(&self) -> Result<Buffer<'_>> {
         let buffer = self.text_buffer.lock().unwrap();
         Buffer::new(buffer)
     }
+
+    /// The value of `var` local to this buffer, if it has one.
+    pub(crate) fn local_value<'ob>(&self, var: Symbol, cx: &'ob Context) -> Option<GcObj<'ob>> {
+        let locals = self.locals.lock().unwrap();
+        locals.get(&local_key(var)).map(|x| cx.bind(*x))
+    }
+
+    pub(crate) fn has_local(&self, var: Symbol) -> bool {
+        let locals = self.locals.lock().unwrap();
+        locals.contains_key(&local_key(var))
+    }
+
+    /// Set the value of `var` local to this buffer, making it local if it was
+    /// not already.
+    pub(crate) fn set_local(&self, var: Symbol, value: GcObj) {
+        // SAFETY: The value is traced through the buffer
+        let value = unsafe { value.with_lifetime() };
+        self.locals.lock().unwrap().insert(local_key(var), value);
+    }
+
+    /// Remove the local value of `var`, returning whether it had one.
+    pub(crate) fn kill_local(&self, var: Symbol) -> bool {
+        let mut locals = self.locals.lock().unwrap();
+        locals.remove(&local_key(var)).is_some()
+    }
+
+    /// All the variables local to this buffer, with their values.
+    pub(crate) fn local_variables<'ob>(&self, cx: &'ob Context) -> Vec<(Symbol<'ob>, GcObj<'ob>)> {
+        let locals = self.locals.lock().unwrap();
+        locals.iter().map(|(var, value)| (cx.bind(*var), cx.bind(*value))).collect()
+    }
+}
+
+/// The key for `var` in the table of local values. The key is traced along
+/// with the values.
+fn local_key(var: Symbol) -> Symbol<'static> {
+    unsafe { var.with_lifetime() }
 }
 
 impl PartialEq for LispBuffer {
@@ -103,18 +450,23 @@ impl Eq for LispBuffer {}
 
 impl Display for LispBuffer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let data = self.text_buffer.lock().unwrap();
-        let name = match data.as_ref() {
-            Some(buf) => &buf.name,
-            None => "deleted buffer",
-        };
-        write!(f, "#<{name}>")
+        match self.name.lock().unwrap().as_ref() {
+            Some(name) => write!(f, "#<{name}>"),
+            None => write!(f, "#<deleted buffer>"),
+        }
     }
 }
 
 impl Trace for LispBuffer {
-    fn trace(&self, _v: &mut Vec<RawObj>) {
-        // Implement once we hold gc data in the buffer
+    fn trace(&self, stack: &mut Vec<RawObj>) {
+        self.mark();
+        let locals = self.locals.lock().unwrap();
+        for (var, value) in locals.iter() {
+            var.trace(stack);
+            if value.is_markable() {
+                stack.push(value.into_raw());
+            }
+        }
     }
 }
 
@@ -122,6 +474,18 @@ impl GcManaged for LispBuffer {
     fn get_mark(&self) -> &GcMark {
         panic!("Buffer does not use GcMark")
     }
+
+    fn mark(&self) {
+        self.marked.store(true, Ordering::Relaxed);
+    }
+
+    fn unmark(&self) {
+        self.marked.store(false, Ordering::Relaxed);
+    }
+
+    fn is_marked(&self) -> bool {
+        self.marked.load(Ordering::Relaxed)
+    }
 }
 
 impl<'old, 'new> LispBuffer {
@@ -0,0 +1,163 @@
+use super::{CloneIn, Gc, GcObj, IntoObject, LispBuffer, RawObj, TextMarker};
+use crate::core::gc::{Block, GcManaged, GcMark, Trace};
+use std::fmt::{Debug, Display};
+use std::sync::{Arc, Mutex, Weak};
+use text_buffer::InsertionType;
+
+/// A lisp marker. It either points nowhere or to a position in a buffer that
+/// moves as text is inserted and deleted around it. The buffer keeps a link to
+/// the marker so that it points nowhere once the buffer is killed.
+pub(crate) struct LispMarker {
+    gc: GcMark,
+    inner: Arc<Mutex<MarkerInner>>,
+}
+
+#[derive(Debug)]
+struct MarkerInner {
+    target: Option<(&'static LispBuffer, TextMarker)>,
+    /// Kept while the marker points nowhere, so that it is used again once it
+    /// is set
+    kind: InsertionType,
+}
+
+/// The link from a buffer to a marker that points into it. It does not keep
+/// the marker alive.
+#[derive(Debug)]
+pub(crate) struct MarkerLink(Weak<Mutex<MarkerInner>>);
+
+impl MarkerLink {
+    /// Whether the marker is still alive and points into `buffer`.
+    pub(crate) fn points_into(&self, buffer: &LispBuffer) -> bool {
+        let Some(inner) = self.0.upgrade() else { return false };
+        let inner = inner.lock().unwrap();
+        inner.target.as_ref().is_some_and(|(target, _)| *target == buffer)
+    }
+
+    /// Make the marker point nowhere if it still points into `buffer`.
+    pub(crate) fn detach(&self, buffer: &LispBuffer) {
+        let Some(inner) = self.0.upgrade() else { return };
+        let mut inner = inner.lock().unwrap();
+        if inner.target.as_ref().is_some_and(|(target, _)| *target == buffer) {
+            // Drop the text marker after releasing the lock
+            let _old = inner.target.take();
+            drop(inner);
+        }
+    }
+}
+
+impl LispMarker {
+    /// Create a marker that points nowhere.
+    pub(crate) fn new(kind: InsertionType) -> Self {
+        let inner = MarkerInner { target: None, kind };
+        Self { gc: GcMark::default(), inner: Arc::new(Mutex::new(inner)) }
+    }
+
+    /// The buffer the marker points into.
+    pub(crate) fn buffer(&self) -> Option<&'static LispBuffer> {
+        self.target(|buffer, _| buffer)
+    }
+
+    pub(crate) fn position(&self) -> Option<usize> {
+        self.target(|_, marker| marker.position())
+    }
+
+    fn target<T>(&self, func: impl Fn(&'static LispBuffer, &TextMarker) -> T) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let (buffer, marker) = inner.target.as_ref()?;
+        Some(func(buffer, marker))
+    }
+
+    pub(crate) fn insertion_type(&self) -> InsertionType {
+        self.inner.lock().unwrap().kind
+    }
+
+    pub(crate) fn set_insertion_type(&self, kind: InsertionType) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.kind = kind;
+        if let Some((_, marker)) = &inner.target {
+            marker.set_insertion_type(kind);
+        }
+    }
+
+    /// Point the marker at `marker`, which was created in the text of
+    /// `buffer` with this marker's insertion type.
+    pub(crate) fn set(&self, buffer: &'static LispBuffer, marker: TextMarker) {
+        // Drop the old text marker after releasing the lock
+        let old = self.inner.lock().unwrap().target.replace((buffer, marker));
+        if old.is_none_or(|(old, _)| old != buffer) {
+            buffer.add_marker(MarkerLink(Arc::downgrade(&self.inner)));
+        }
+    }
+
+    /// Move the marker to `pos` if it already points into `buffer`. Returns
+    /// false if it doesn't.
+    pub(crate) fn set_position_in(&self, buffer: &LispBuffer, pos: usize) -> bool {
+        match &self.inner.lock().unwrap().target {
+            Some((current, marker)) if *current == buffer => {
+                marker.set_position(pos);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Make the marker point nowhere.
+    pub(crate) fn detach(&self) {
+        let _old = self.inner.lock().unwrap().target.take();
+    }
+}
+
+impl PartialEq for LispMarker {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for LispMarker {}
+
+impl<'new> CloneIn<'new, &'new Self> for LispMarker {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        let inner = self.inner.lock().unwrap();
+        let new = Self::new(inner.kind);
+        if let Some((buffer, marker)) = &inner.target {
+            new.set(buffer, marker.copy());
+        }
+        new.into_obj(bk)
+    }
+}
+
+impl GcManaged for LispMarker {
+    fn get_mark(&self) -> &GcMark {
+        &self.gc
+    }
+}
+
+impl Trace for LispMarker {
+    fn trace(&self, stack: &mut Vec<RawObj>) {
+        self.mark();
+        // Markers are detached when their buffer is killed, so this never
+        // keeps a killed buffer alive
+        if let Some((buffer, _)) = &self.inner.lock().unwrap().target {
+            let buffer: GcObj = (*buffer).into();
+            if buffer.is_markable() {
+                stack.push(buffer.into_raw());
+            }
+        }
+    }
+}
+
+impl Display for LispMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The buffer name is not shown because the buffer may be locked
+        match self.position() {
+            Some(pos) => write!(f, "#<marker at {pos}>"),
+            None => write!(f, "#<marker in no buffer>"),
+        }
+    }
+}
+
+impl Debug for LispMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
@@ -6,7 +6,7 @@ use super::{
         error::{Type, TypeError},
         gc::{AllocObject, Block},
     },
-    LispBuffer,
+    LispBuffer, LispMarker,
 };
 use super::{
     ByteFn, HashTable, LispFloat, LispHashTable, LispString, LispVec, Record, RecordBuilder, SubrFn,
@@ -327,6 +327,15 @@ impl<'a> IntoObject for HashTable<'a> {
     }
 }
 
+impl IntoObject for LispMarker {
+    type Out<'ob> = &'ob LispMarker;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = self.alloc_obj(block);
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
 mod private {
     use super::{Gc, WithLifetime};
 
@@ -343,6 +352,7 @@ mod private {
         SubrFn,
         ByteFn,
         Buffer,
+        Marker,
     }
 
     pub(crate) trait TaggedPtr: Copy + for<'a> WithLifetime<'a> {
@@ -393,6 +403,7 @@ impl<'a> TaggedPtr for Object<'a> {
                 Tag::Record => Object::Record(<&Record>::from_obj_ptr(ptr)),
                 Tag::HashTable => Object::HashTable(<&LispHashTable>::from_obj_ptr(ptr)),
                 Tag::Buffer => Object::Buffer(<&LispBuffer>::from_obj_ptr(ptr)),
+                Tag::Marker => Object::Marker(<&LispMarker>::from_obj_ptr(ptr)),
             }
         }
     }
@@ -410,6 +421,7 @@ impl<'a> TaggedPtr for Object<'a> {
             Object::ByteFn(x) => TaggedPtr::tag(x).into(),
             Object::SubrFn(x) => TaggedPtr::tag(x).into(),
             Object::Buffer(x) => TaggedPtr::tag(x).into(),
+            Object::Marker(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
@@ -498,6 +510,34 @@ impl<'a> TaggedPtr for Number<'a> {
     }
 }
 
+impl<'a> TaggedPtr for IntOrMarker<'a> {
+    type Ptr = IntOrMarker<'a>;
+    const TAG: Tag = Tag::Int;
+
+    unsafe fn tag_ptr(ptr: *const Self::Ptr) -> Gc<Self> {
+        // The tag depends on the variant, so tag the value itself
+        TaggedPtr::tag(*ptr)
+    }
+
+    fn untag(val: Gc<Self>) -> Self {
+        let (ptr, tag) = val.untag_ptr();
+        unsafe {
+            match tag {
+                Tag::Int => IntOrMarker::Int(i64::from_obj_ptr(ptr)),
+                Tag::Marker => IntOrMarker::Marker(<&LispMarker>::from_obj_ptr(ptr)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn tag(self) -> Gc<Self> {
+        match self {
+            IntOrMarker::Int(x) => TaggedPtr::tag(x).into(),
+            IntOrMarker::Marker(x) => TaggedPtr::tag(x).into(),
+        }
+    }
+}
+
 impl TaggedPtr for i64 {
     type Ptr = i64;
     const TAG: Tag = Tag::Int;
@@ -636,6 +676,18 @@ impl TaggedPtr for &LispBuffer {
     }
 }
 
+impl TaggedPtr for &LispMarker {
+    type Ptr = LispMarker;
+    const TAG: Tag = Tag::Marker;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
 macro_rules! cast_gc {
     ($supertype:ty => $($subtype:ty),+ $(,)?) => {
         $(
@@ -675,6 +727,23 @@ impl<'old, 'new> WithLifetime<'new> for Number<'old> {
     }
 }
 
+// IntOrMarker
+#[derive(Copy, Clone)]
+#[repr(u8)]
+pub(crate) enum IntOrMarker<'ob> {
+    Int(i64) = Tag::Int as u8,
+    Marker(&'ob LispMarker) = Tag::Marker as u8,
+}
+cast_gc!(IntOrMarker<'ob> => i64, &'ob LispMarker);
+
+impl<'old, 'new> WithLifetime<'new> for IntOrMarker<'old> {
+    type Out = IntOrMarker<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute::<IntOrMarker<'old>, IntOrMarker<'new>>(self)
+    }
+}
+
 // List
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
@@ -768,8 +837,9 @@ pub(crate) enum Object<'ob> {
     ByteFn(&'ob ByteFn) = Tag::ByteFn as u8,
     SubrFn(&'static SubrFn) = Tag::SubrFn as u8,
     Buffer(&'static LispBuffer) = Tag::Buffer as u8,
+    Marker(&'ob LispMarker) = Tag::Marker as u8,
 }
-cast_gc!(Object<'ob> => Number<'ob>, List<'ob>, Function<'ob>, i64, Symbol<'_>, &LispFloat, &'ob Cons, &'ob LispVec, &'ob Record, &'ob LispHashTable, &'ob LispString, &'ob ByteFn, &'ob SubrFn, &'ob LispBuffer);
+cast_gc!(Object<'ob> => Number<'ob>, IntOrMarker<'ob>, List<'ob>, Function<'ob>, i64, Symbol<'_>, &LispFloat, &'ob Cons, &'ob LispVec, &'ob Record, &'ob LispHashTable, &'ob LispString, &'ob ByteFn, &'ob SubrFn, &'ob LispBuffer, &'ob LispMarker);
 
 impl Object<'_> {
     pub(crate) const NIL: Object<'static> = Object::Symbol(sym::NIL);
@@ -787,6 +857,7 @@ impl Object<'_> {
             Object::String(_) => Type::String,
             Object::ByteFn(_) | Object::SubrFn(_) => Type::Func,
             Object::Buffer(_) => Type::Buffer,
+            Object::Marker(_) => Type::Marker,
         }
     }
 }
@@ -864,6 +935,17 @@ impl<'ob> TryFrom<Gc<Object<'ob>>> for Option<Gc<Number<'ob>>> {
     }
 }
 
+impl<'ob> TryFrom<Gc<Object<'ob>>> for Gc<IntOrMarker<'ob>> {
+    type Error = TypeError;
+
+    fn try_from(value: Gc<Object<'ob>>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::Int | Tag::Marker => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::IntOrMarker, value)),
+        }
+    }
+}
+
 impl<'ob> TryFrom<Gc<Object<'ob>>> for Gc<List<'ob>> {
     type Error = TypeError;
 
@@ -1019,6 +1101,7 @@ where
             Object::Record(x) => x.clone_in(bk).into(),
             Object::HashTable(x) => x.clone_in(bk).into(),
             Object::Buffer(x) => x.clone_in(bk).into(),
+            Object::Marker(x) => x.clone_in(bk).into(),
         };
         let Ok(x) = Gc::<U>::try_from(obj) else { unreachable!() };
         x
@@ -1124,6 +1207,7 @@ impl fmt::Display for Object<'_> {
             Object::SubrFn(x) => D::fmt(x, f),
             Object::Float(x) => D::fmt(x, f),
             Object::Buffer(x) => D::fmt(x, f),
+            Object::Marker(x) => D::fmt(x, f),
         }
     }
 }
@@ -1145,6 +1229,7 @@ impl<'ob> Gc<Object<'ob>> {
             Object::ByteFn(x) => x.is_marked(),
             Object::Symbol(x) => x.is_marked(),
             Object::Buffer(x) => x.is_marked(),
+            Object::Marker(x) => x.is_marked(),
         }
     }
 
@@ -1160,6 +1245,7 @@ impl<'ob> Gc<Object<'ob>> {
             Object::Symbol(x) => x.trace(stack),
             Object::ByteFn(x) => x.trace(stack),
             Object::Buffer(x) => x.trace(stack),
+            Object::Marker(x) => x.trace(stack),
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::buffer::target_buffer;
 use crate::core::{
     cons::Cons,
     env::{sym, Env, Symbol, INTERNED_SYMBOLS},
@@ -5,8 +6,9 @@ use crate::core::{
     gc::{Context, IntoRoot, Rt},
     object::{nil, Gc, GcObj, List, Number, Object, SubrFn},
 };
+use crate::fns::slice_into_list;
 use crate::hashmap::HashSet;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use fn_macros::defun;
 use lazy_static::lazy_static;
 use std::sync::Mutex;
@@ -38,8 +40,13 @@ pub(crate) fn defalias<'ob>(
 }
 
 #[defun]
-pub(crate) fn set<'ob>(place: Symbol, newlet: GcObj<'ob>, env: &mut Rt<Env>) -> Result<GcObj<'ob>> {
-    env.set_var(place, newlet)?;
+pub(crate) fn set<'ob>(
+    place: Symbol,
+    newlet: GcObj<'ob>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<GcObj<'ob>> {
+    env.set_var(place, newlet, cx)?;
     Ok(newlet)
 }
 
@@ -84,7 +91,7 @@ pub(crate) fn symbol_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Option<GcObj<'ob>> {
-    env.vars.get(symbol).map(|x| x.bind(cx))
+    env.var(symbol, cx)
 }
 
 #[defun]
@@ -109,8 +116,8 @@ pub(crate) fn fmakunbound(symbol: Symbol) -> Symbol {
 }
 
 #[defun]
-pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> bool {
+    env.var(symbol, cx).is_some()
 }
 
 #[defun]
@@ -124,6 +131,18 @@ pub(crate) fn default_boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
     env.vars.get(symbol).is_some()
 }
 
+#[defun]
+pub(crate) fn default_value<'ob>(
+    symbol: Symbol,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    match env.vars.get(symbol) {
+        Some(value) => Ok(value.bind(cx)),
+        None => bail!("Void variable: {symbol}"),
+    }
+}
+
 #[defun]
 pub(crate) fn listp(object: GcObj) -> bool {
     matches!(object.untag(), Object::NIL | Object::Cons(_))
@@ -165,9 +184,8 @@ pub(crate) fn numberp(object: GcObj) -> bool {
 }
 
 #[defun]
-pub(crate) fn markerp(_: GcObj) -> bool {
-    // TODO: implement
-    false
+pub(crate) fn markerp(object: GcObj) -> bool {
+    matches!(object.untag(), Object::Marker(_))
 }
 
 #[defun]
@@ -241,15 +259,79 @@ pub(crate) fn defvar<'ob>(
     env: &mut Rt<Env>,
 ) -> Result<GcObj<'ob>> {
     let value = initvalue.unwrap_or_default();
-    set(symbol, value, env)
+    env.set_default(symbol, value)?;
+    Ok(value)
 }
 
 #[defun]
 pub(crate) fn make_variable_buffer_local(variable: Symbol) -> Symbol {
-    // TODO: Implement
+    variable.make_local_if_set();
     variable
 }
 
+#[defun]
+pub(crate) fn make_local_variable<'ob>(
+    variable: Symbol<'ob>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Symbol<'ob>> {
+    if variable.is_const() {
+        bail!("Attempt to set a constant symbol: {variable}");
+    }
+    let buffer = target_buffer(None, env, cx)?;
+    if !buffer.has_local(variable) {
+        // The local value starts out as the default value
+        let value = env.vars.get(variable).map_or_else(nil, |x| x.bind(cx));
+        buffer.set_local(variable, value);
+    }
+    Ok(variable)
+}
+
+#[defun]
+pub(crate) fn kill_local_variable<'ob>(
+    variable: Symbol<'ob>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Symbol<'ob>> {
+    target_buffer(None, env, cx)?.kill_local(variable);
+    Ok(variable)
+}
+
+#[defun]
+pub(crate) fn buffer_local_value<'ob>(
+    variable: Symbol,
+    buffer: GcObj,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let buffer = target_buffer(Some(buffer), env, cx)?;
+    match buffer.local_value(variable, cx) {
+        Some(value) => Ok(value),
+        None => default_value(variable, env, cx),
+    }
+}
+
+#[defun]
+pub(crate) fn local_variable_p(
+    variable: Symbol,
+    buffer: Option<GcObj>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    Ok(target_buffer(buffer, env, cx)?.has_local(variable))
+}
+
+#[defun]
+pub(crate) fn buffer_local_variables<'ob>(
+    buffer: Option<GcObj>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let locals = target_buffer(buffer, env, cx)?.local_variables(cx);
+    let alist: Vec<_> = locals.into_iter().map(|(var, value)| cons!(var, value; cx)).collect();
+    Ok(slice_into_list(&alist, None, cx))
+}
+
 #[defun]
 fn subr_arity<'ob>(subr: &SubrFn, cx: &'ob Context) -> GcObj<'ob> {
     let min = subr.args.required as usize;
@@ -347,6 +429,7 @@ fn type_of(object: GcObj) -> GcObj {
         Object::String(_) => sym::STRING.into(),
         Object::SubrFn(_) => sym::SUBR.into(),
         Object::Buffer(_) => sym::BUFFER.into(),
+        Object::Marker(_) => sym::MARKER.into(),
     }
 }
 
@@ -431,6 +514,31 @@ mod test {
         assert_eq!(ash(256, -8), 1);
         assert_eq!(ash(-8, 1), -16);
     }
+
+    #[test]
+    fn test_buffer_local_collection() {
+        use crate::buffer::{get_buffer_create, set_buffer};
+        use crate::core::gc::RootSet;
+        use crate::lread::intern;
+        use crate::root;
+
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let a = get_buffer_create(cx.add("test_local_collection_a"), None, env, cx).unwrap();
+        set_buffer(a, env, cx).unwrap();
+        let var = intern("local-collection-test", cx);
+        make_local_variable(var, env, cx).unwrap();
+        set(var, cx.add("local value"), env, cx).unwrap();
+        let b = get_buffer_create(cx.add("test_local_collection_b"), None, env, cx).unwrap();
+        set_buffer(b, env, cx).unwrap();
+        // The local value is only reachable through buffer a
+        cx.garbage_collect(true);
+        let a = get_buffer_create(cx.add("test_local_collection_a"), None, env, cx).unwrap();
+        set_buffer(a, env, cx).unwrap();
+        let var = intern("local-collection-test", cx);
+        assert_eq!(symbol_value(var, env, cx).unwrap(), "local value");
+    }
 }
 
 defsym!(MANY);
@@ -439,5 +547,6 @@ defsym!(SYMBOL);
 defsym!(COMPILED_FUNCTION);
 defsym!(HASH_TABLE);
 defsym!(BUFFER);
+defsym!(MARKER);
 defsym!(STRING);
 defsym!(SUBR);
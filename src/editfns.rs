@@ -1,11 +1,13 @@
 use crate::core::{
-    env::Env,
-    gc::Rt,
-    object::{GcObj, Object},
+    env::{sym, Env},
+    error::EvalError,
+    gc::{Context, Rt},
+    object::{nil, Gc, GcObj, IntOrMarker, Object},
 };
 use anyhow::{bail, ensure, Result};
 use fn_macros::defun;
 use std::{fmt::Write as _, io::Write};
+use text_buffer::BufferError;
 
 #[defun]
 fn message(format_string: &str, args: &[GcObj]) -> Result<String> {
@@ -82,12 +84,152 @@ pub(crate) fn insert(args: &[GcObj], env: &mut Rt<Env>) -> Result<()> {
 }
 
 #[defun]
-fn delete_region(start: usize, end: usize, env: &mut Rt<Env>) -> Result<()> {
+fn delete_region(
+    start: Gc<IntOrMarker>,
+    end: Gc<IntOrMarker>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
     let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
-    buffer.delete(start, end);
+    let (start, end) = (start.position()?, end.position()?);
+    match char_range(start, end).map(|(beg, end)| buffer.delete(beg, end)) {
+        Some(Ok(())) => Ok(()),
+        _ => Err(args_out_of_range(start, end, env, cx)),
+    }
+}
+
+#[defun]
+pub(crate) fn narrow_to_region(
+    start: Gc<IntOrMarker>,
+    end: Gc<IntOrMarker>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    let (start, end) = (start.position()?, end.position()?);
+    match char_range(start, end).map(|(beg, end)| buffer.narrow(beg, end)) {
+        Some(Ok(())) => Ok(()),
+        _ => Err(args_out_of_range(start, end, env, cx)),
+    }
+}
+
+/// Convert lisp positions to char positions. Negative positions are out of
+/// range of any buffer.
+fn char_range(start: i64, end: i64) -> Option<(usize, usize)> {
+    usize::try_from(start).ok().zip(usize::try_from(end).ok())
+}
+
+fn args_out_of_range(start: i64, end: i64, env: &mut Rt<Env>, cx: &Context) -> anyhow::Error {
+    let data = list![start, end; cx];
+    EvalError::signal(sym::ARGS_OUT_OF_RANGE.into(), data, env).into()
+}
+
+#[defun]
+pub(crate) fn widen(env: &mut Rt<Env>) -> Result<()> {
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    buffer.widen();
     Ok(())
 }
 
+#[defun]
+pub(crate) fn point(env: &Rt<Env>) -> Result<usize> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.point())
+}
+
+#[defun]
+pub(crate) fn point_min(env: &Rt<Env>) -> Result<usize> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.point_min())
+}
+
+#[defun]
+pub(crate) fn point_max(env: &Rt<Env>) -> Result<usize> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.point_max())
+}
+
+#[defun]
+pub(crate) fn goto_char<'ob>(
+    position: Gc<IntOrMarker<'ob>>,
+    env: &mut Rt<Env>,
+) -> Result<Gc<IntOrMarker<'ob>>> {
+    let pos = position.position()?;
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    // Positions outside of the accessible region are clamped to it
+    buffer.goto_char(usize::try_from(pos).unwrap_or(0));
+    Ok(position)
+}
+
+#[defun]
+pub(crate) fn char_after<'ob>(pos: Option<Gc<IntOrMarker>>, env: &Rt<Env>) -> Result<GcObj<'ob>> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    let pos = match pos {
+        Some(pos) => match usize::try_from(pos.position()?) {
+            Ok(pos) => pos,
+            Err(_) => return Ok(nil()),
+        },
+        None => buffer.point(),
+    };
+    Ok(buffer.char_after(pos).map(i64::from).into())
+}
+
+#[defun]
+pub(crate) fn following_char(env: &Rt<Env>) -> Result<i64> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.char_after(buffer.point()).map_or(0, i64::from))
+}
+
+#[defun]
+pub(crate) fn preceding_char(env: &Rt<Env>) -> Result<i64> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.char_before(buffer.point()).map_or(0, i64::from))
+}
+
+#[defun]
+pub(crate) fn bobp(env: &Rt<Env>) -> Result<bool> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.point() == buffer.point_min())
+}
+
+#[defun]
+pub(crate) fn eobp(env: &Rt<Env>) -> Result<bool> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.point() == buffer.point_max())
+}
+
+#[defun]
+pub(crate) fn bolp(env: &Rt<Env>) -> Result<bool> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(matches!(buffer.char_before(buffer.point()), None | Some(NEWLINE)))
+}
+
+#[defun]
+pub(crate) fn eolp(env: &Rt<Env>) -> Result<bool> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(matches!(buffer.char_after(buffer.point()), None | Some(NEWLINE)))
+}
+
+const NEWLINE: u32 = '\n' as u32;
+
+#[defun]
+pub(crate) fn buffer_substring(
+    start: Gc<IntOrMarker>,
+    end: Gc<IntOrMarker>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    let (start, end) = (start.position()?, end.position()?);
+    match char_range(start, end).map(|(beg, end)| buffer.substring(beg, end)) {
+        Some(Ok(string)) => Ok(string),
+        Some(Err(BufferError::Decode(e))) => Err(e.into()),
+        _ => Err(args_out_of_range(start, end, env, cx)),
+    }
+}
+
+defsym!(ARGS_OUT_OF_RANGE);
+
 #[cfg(test)]
 mod test {
     use crate::core::env::sym;
@@ -120,7 +262,7 @@ mod test {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
         root!(env, Env::default(), cx);
-        let buffer = get_buffer_create(cx.add("test_insert"), sym::NIL.into(), cx).unwrap();
+        let buffer = get_buffer_create(cx.add("test_insert"), None, env, cx).unwrap();
         set_buffer(buffer, env, cx).unwrap();
         cx.garbage_collect(true);
         insert(&[104.into(), 101.into(), 108.into(), 108.into(), 111.into()], env).unwrap();
@@ -132,13 +274,68 @@ mod test {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
         root!(env, Env::default(), cx);
-        let buffer = get_buffer_create(cx.add("test_delete_region"), sym::NIL.into(), cx).unwrap();
+        let buffer = get_buffer_create(cx.add("test_delete_region"), None, env, cx).unwrap();
         set_buffer(buffer, env, cx).unwrap();
         cx.garbage_collect(true);
         insert(&[cx.add("hello"), cx.add(" world")], env).unwrap();
 
         assert_eq!(env.current_buffer.as_ref().unwrap(), "hello world");
-        delete_region(1, 3, env).unwrap();
+        delete_region(1_i64.into(), 3_i64.into(), env, cx).unwrap();
         assert_eq!(env.current_buffer.as_ref().unwrap(), "hlo world");
+        assert!(delete_region(5_i64.into(), 100_i64.into(), env, cx).is_err());
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "hlo world");
+    }
+
+    #[test]
+    fn test_narrow_to_region() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let buffer = get_buffer_create(cx.add("test_narrow_to_region"), None, env, cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        cx.garbage_collect(true);
+        insert(&[cx.add("hello world")], env).unwrap();
+
+        // Positions outside of the buffer are not clamped
+        assert!(narrow_to_region(2_i64.into(), 20_i64.into(), env, cx).is_err());
+        assert!(narrow_to_region((-1_i64).into(), 8_i64.into(), env, cx).is_err());
+        assert_eq!(env.current_buffer.as_ref().unwrap().restriction(), None);
+        narrow_to_region(2_i64.into(), 8_i64.into(), env, cx).unwrap();
+        assert!(delete_region(0_i64.into(), 4_i64.into(), env, cx).is_err());
+        delete_region(2_i64.into(), 4_i64.into(), env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "heo world");
+        widen(env).unwrap();
+        delete_region(0_i64.into(), 2_i64.into(), env, cx).unwrap();
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "o world");
+    }
+
+    #[test]
+    fn test_point() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let buffer = get_buffer_create(cx.add("test_point"), None, env, cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        cx.garbage_collect(true);
+        insert(&[cx.add("ab\ncd")], env).unwrap();
+
+        assert_eq!(point(env).unwrap(), 5);
+        assert!(eobp(env).unwrap() && eolp(env).unwrap());
+        assert_eq!(following_char(env).unwrap(), 0);
+        assert_eq!(preceding_char(env).unwrap(), i64::from(b'd'));
+
+        goto_char(2_i64.into(), env).unwrap();
+        assert!(eolp(env).unwrap() && !bolp(env).unwrap());
+        assert_eq!(char_after(None, env).unwrap(), i64::from(b'\n'));
+        assert_eq!(char_after(Some(10_i64.into()), env).unwrap(), sym::NIL);
+        goto_char(3_i64.into(), env).unwrap();
+        assert!(bolp(env).unwrap());
+
+        narrow_to_region(1_i64.into(), 4_i64.into(), env, cx).unwrap();
+        assert_eq!((point_min(env).unwrap(), point_max(env).unwrap()), (1, 4));
+        goto_char(0_i64.into(), env).unwrap();
+        assert!(bobp(env).unwrap() && bolp(env).unwrap());
+        assert_eq!(buffer_substring(4_i64.into(), 1_i64.into(), env, cx).unwrap(), "b\nc");
+        assert!(buffer_substring(0_i64.into(), 2_i64.into(), env, cx).is_err());
     }
 }
@@ -49,7 +49,7 @@ pub(crate) fn funcall<'ob>(
 }
 
 #[defun]
-fn run_hooks<'ob>(
+pub(crate) fn run_hooks<'ob>(
     hooks: &[Rt<GcObj>],
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
@@ -57,8 +57,7 @@ fn run_hooks<'ob>(
     for hook in hooks {
         match hook.get(cx) {
             Object::Symbol(sym) => {
-                if let Some(val) = env.vars.get(sym) {
-                    let val = val.bind(cx);
+                if let Some(val) = env.var(sym, cx) {
                     match val.untag() {
                         Object::Cons(hook_list) => {
                             rooted_iter!(hooks, hook_list, cx);
@@ -210,7 +209,7 @@ fn set_default_toplevel_value<'ob>(
     value: GcObj,
     env: &'ob mut Rt<Env>,
 ) -> Result<GcObj<'ob>> {
-    env.set_var(symbol, value)?;
+    env.set_default(symbol, value)?;
     Ok(nil())
 }
 
@@ -220,8 +219,7 @@ fn set_default<'ob>(
     value: GcObj<'ob>,
     env: &'ob mut Rt<Env>,
 ) -> Result<GcObj<'ob>> {
-    // TODO: implement buffer local variables
-    env.set_var(symbol, value)?;
+    env.set_default(symbol, value)?;
     Ok(value)
 }
 
@@ -237,6 +235,9 @@ defsym!(LAMBDA);
 defsym!(CLOSURE);
 defsym!(CONDITION_CASE);
 defsym!(UNWIND_PROTECT);
+defsym!(SAVE_EXCURSION);
+defsym!(SAVE_RESTRICTION);
+defsym!(SAVE_CURRENT_BUFFER);
 defsym!(WHILE);
 defsym!(INLINE);
 defsym!(PROGN);
@@ -0,0 +1,89 @@
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Gc, GcObj, Object},
+};
+use anyhow::{bail, Result};
+use fn_macros::defun;
+
+defvar_bool!(INDENT_TABS_MODE, true);
+
+/// The value of `tab-width`, or 8 if it is not a sensible width.
+fn tab_width(env: &Rt<Env>, cx: &Context) -> usize {
+    match env.var(sym::TAB_WIDTH, cx).map(Gc::untag) {
+        Some(Object::Int(width)) if (1..=1000).contains(&width) => width as usize,
+        _ => 8,
+    }
+}
+
+#[defun]
+pub(crate) fn current_column(env: &Rt<Env>, cx: &Context) -> Result<usize> {
+    let tab_width = tab_width(env, cx);
+    let Some(buffer) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    Ok(buffer.current_column(tab_width))
+}
+
+#[defun]
+fn move_to_column(
+    column: usize,
+    _force: Option<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    // TODO: implement FORCE, which converts a tab to spaces to reach the
+    // column exactly.
+    let tab_width = tab_width(env, cx);
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    Ok(buffer.move_to_column(column, tab_width))
+}
+
+#[defun]
+pub(crate) fn indent_to(
+    column: usize,
+    minimum: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    let tab_width = tab_width(env, cx);
+    let use_tabs = !env.var(sym::INDENT_TABS_MODE, cx).is_some_and(Gc::nil);
+    let Some(buffer) = env.current_buffer.as_mut() else { bail!("No current buffer") };
+    let mut from = buffer.current_column(tab_width);
+    let target = column.max(from + minimum.unwrap_or(0));
+    let mut indent = String::new();
+    if use_tabs && target / tab_width > from / tab_width {
+        indent.push_str(&"\t".repeat(target / tab_width - from / tab_width));
+        from = target / tab_width * tab_width;
+    }
+    indent.push_str(&" ".repeat(target - from));
+    buffer.insert_str(&indent);
+    Ok(target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::{get_buffer_create, set_buffer},
+        core::gc::RootSet,
+        editfns::insert,
+        root,
+    };
+
+    #[test]
+    fn test_columns() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let buffer = get_buffer_create(cx.add("test_columns"), None, env, cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        cx.garbage_collect(true);
+        insert(&[cx.add("a\tb")], env).unwrap();
+        assert_eq!(current_column(env, cx).unwrap(), 9);
+        assert_eq!(move_to_column(4, None, env, cx).unwrap(), 8);
+        assert_eq!(indent_to(12, None, env, cx).unwrap(), 12);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "a\t    b");
+        assert_eq!(indent_to(4, Some(8), env, cx).unwrap(), 20);
+        assert_eq!(env.current_buffer.as_ref().unwrap(), "a\t    \t    b");
+        assert_eq!(current_column(env, cx).unwrap(), 20);
+    }
+}
@@ -71,6 +71,9 @@ impl Interpreter<'_> {
                 sym::THROW => self.throw(forms.bind(cx), cx),
                 sym::CONDITION_CASE => self.condition_case(forms, cx),
                 sym::UNWIND_PROTECT => self.unwind_protect(forms, cx),
+                sym::SAVE_EXCURSION => self.eval_saving(forms, Rt::save_excursion, cx),
+                sym::SAVE_RESTRICTION => self.eval_saving(forms, Rt::save_restriction, cx),
+                sym::SAVE_CURRENT_BUFFER => self.eval_saving(forms, Rt::save_current_buffer, cx),
                 _ => {
                     root!(sym, cx);
                     self.eval_call(sym, forms, cx)
@@ -85,6 +88,7 @@ impl Interpreter<'_> {
         let Some(tag) = forms.next() else { bail_err!(ArgError::new(1, 0, "catch")) };
         // push this tag on the catch stack
         self.env.catch_stack.push(tag);
+        let depth = self.env.binding_depth();
         let result = match self.implicit_progn(forms, cx) {
             Ok(x) => Ok(rebind!(x, cx)),
             Err(e) => {
@@ -92,7 +96,9 @@ impl Interpreter<'_> {
                     if let Some((throw_tag, data)) = self.env.get_exception(id) {
                         let catch_tag = self.env.catch_stack.last().unwrap();
                         if catch_tag == throw_tag {
-                            return Ok(data.bind(cx));
+                            let data = data.bind(cx);
+                            self.env.unbind_to(depth, cx);
+                            return Ok(data);
                         }
                     }
                 }
@@ -350,8 +356,8 @@ impl Interpreter<'_> {
             let mut iter = self.vars.iter().rev();
             match iter.find_map(|cons| (cons.car(cx) == sym).then(|| cons.cdr(cx))) {
                 Some(value) => Ok(value),
-                None => match self.env.vars.get(sym) {
-                    Some(v) => Ok(v.bind(cx)),
+                None => match self.env.var(sym, cx) {
+                    Some(v) => Ok(v),
                     None => Err(error!("Void variable: {sym}")),
                 },
             }
@@ -365,7 +371,7 @@ impl Interpreter<'_> {
                 value.bind(cx).set_cdr(new_value).expect("variables should never be immutable");
                 Ok(())
             }
-            None => self.env.set_var(name, new_value),
+            None => self.env.set_var(name, new_value, cx),
         }
     }
 
@@ -511,11 +517,36 @@ impl Interpreter<'_> {
         }
     }
 
+    /// Evaluate `obj` as a progn after saving some state with `save`. The state
+    /// is restored however the body exits.
+    fn eval_saving<'ob>(
+        &mut self,
+        obj: &Rt<GcObj>,
+        save: fn(&mut Rt<Env>, &Context) -> AnyResult<()>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        let depth = self.env.binding_depth();
+        save(self.env, cx)?;
+        rooted_iter!(forms, obj, cx);
+        match self.implicit_progn(forms, cx) {
+            Ok(x) => {
+                root!(x, cx);
+                self.env.unbind_to(depth, cx);
+                Ok(x.bind(cx))
+            }
+            Err(e) => {
+                self.env.unbind_to(depth, cx);
+                Err(e)
+            }
+        }
+    }
+
     fn condition_case<'ob>(&mut self, form: &Rt<GcObj>, cx: &'ob mut Context) -> EvalResult<'ob> {
         rooted_iter!(forms, form, cx);
         let Some(var) = forms.next() else { bail_err!(ArgError::new(2, 0, "condition-case")) };
         root!(var, cx);
         let Some(bodyform) = forms.next() else { bail_err!(ArgError::new(2, 1, "condition-case")) };
+        let depth = self.env.binding_depth();
         let err = match self.eval_form(bodyform, cx) {
             Ok(x) => return Ok(rebind!(x, cx)),
             Err(e) => e,
@@ -541,6 +572,8 @@ impl Interpreter<'_> {
                         }
                         _ => bail_err!("Invalid condition handler: {condition}"),
                     }
+                    // Undo what the body left bound when it exited
+                    self.env.unbind_to(depth, cx);
                     // Call handlers with error
                     let error = if let ErrorType::Signal(id) = err.error {
                         let Some((sym, data)) = self.env.get_exception(id) else {
@@ -1007,4 +1040,130 @@ mod test {
         check_error("(throw 1 2)", cx);
         check_error("(catch 2 (throw 3 4))", cx);
     }
+
+    #[test]
+    fn test_buffer_locals() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let buffer =
+            |name| format!("(set-buffer (get-buffer-create \"test_buffer_locals_{name}\"))");
+        let (a, b) = (buffer("a"), buffer("b"));
+        check_interpreter(
+            &format!("(progn (defvar local_test1 1) {a} (make-local-variable 'local_test1) (setq local_test1 2) {b} local_test1)"),
+            1,
+            cx,
+        );
+        let list = list!(2, false, true, 2; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn (defvar local_test2 1) {a} (make-local-variable 'local_test2) (setq local_test2 2) {b} (list (buffer-local-value 'local_test2 (get-buffer-create \"test_buffer_locals_a\")) (local-variable-p 'local_test2) (local-variable-p 'local_test2 (get-buffer-create \"test_buffer_locals_a\")) (progn {a} local_test2)))"),
+            list,
+            cx,
+        );
+        // The local binding is restored in the buffer it was made in
+        let list = list!(4, 2; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn (defvar local_test3 1) {a} (make-local-variable 'local_test3) (setq local_test3 2) (let ((local_test3 3)) {b} (setq local_test3 4)) (list local_test3 (buffer-local-value 'local_test3 (get-buffer-create \"test_buffer_locals_a\"))))"),
+            list,
+            cx,
+        );
+        let list = list!(2, 3, 3; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn (defvar local_test4 1) (make-variable-buffer-local 'local_test4) {a} (setq local_test4 2) (set-default 'local_test4 3) (list local_test4 (default-value 'local_test4) (progn (kill-local-variable 'local_test4) local_test4)))"),
+            list,
+            cx,
+        );
+        // Setting a variable bound by let does not make it local
+        let list = list!(1, false; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn (defvar local_test5 1) (make-variable-buffer-local 'local_test5) {a} (let ((local_test5 2)) (setq local_test5 3)) (list local_test5 (local-variable-p 'local_test5)))"),
+            list,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_kill_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let buffer = |name| format!("(set-buffer (get-buffer-create \"test_kill_buffer_{name}\"))");
+        let other = buffer("other");
+        let list = list!(true, false, false, false; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn {other} (let ((b (get-buffer-create \"test_kill_buffer\"))) (list (kill-buffer b) (buffer-name b) (buffer-live-p b) (get-buffer \"test_kill_buffer\"))))"),
+            list,
+            cx,
+        );
+        let list = list!(false, true; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn {other} (get-buffer-create \"test_kill_buffer_query\") (setq kill-buffer-query-functions (list #'(lambda () nil))) (list (kill-buffer \"test_kill_buffer_query\") (progn (setq kill-buffer-query-functions nil) (kill-buffer \"test_kill_buffer_query\"))))"),
+            list,
+            cx,
+        );
+        check_interpreter(
+            &format!("(progn (defvar kill_buffer_test1 nil) {other} (get-buffer-create \"test_kill_buffer_hook\") (setq kill-buffer-hook (list #'(lambda () (setq kill_buffer_test1 (buffer-name))))) (kill-buffer \"test_kill_buffer_hook\") kill_buffer_test1)"),
+            "test_kill_buffer_hook",
+            cx,
+        );
+        // Killing the current buffer makes another buffer current, and markers
+        // in the killed buffer point nowhere
+        let list = list!(false, false, "test_kill_buffer_other"; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn {other} {} (let ((m (point-marker))) (kill-buffer) (list (marker-buffer m) (marker-position m) (buffer-name))))", buffer("marker")),
+            list,
+            cx,
+        );
+        let list = list!("test_kill_buffer_rename<3>", "test_kill_buffer_other<2>", "test_kill_buffer_other<2>"; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn {other} {} (get-buffer-create \"test_kill_buffer_rename<2>\") (list (generate-new-buffer-name \"test_kill_buffer_rename\") (rename-buffer \"test_kill_buffer_other\" t) (buffer-name)))", buffer("rename")),
+            list,
+            cx,
+        );
+        let list = list!(false, true, false; cx);
+        root!(list, cx);
+        check_interpreter(
+            &format!("(progn {} (list (buffer-modified-p) (progn (insert \"x\") (buffer-modified-p)) (progn (set-buffer-modified-p nil) (buffer-modified-p))))", buffer("modified")),
+            list,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_save_forms() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        // Each form gets a fresh buffer so that they don't see each other's text
+        let setup = |name| {
+            format!(
+                "(set-buffer (get-buffer-create \"test_save_forms_{name}\")) (insert \"hello\")"
+            )
+        };
+        check_interpreter(
+            &format!("(progn {} (save-excursion (goto-char 2)) (point))", setup("excursion")),
+            5,
+            cx,
+        );
+        check_interpreter(
+            &format!("(progn {} (goto-char 2) (condition-case nil (save-excursion (goto-char 4) (if)) (error nil)) (point))", setup("error")),
+            2,
+            cx,
+        );
+        check_interpreter(
+            &format!("(progn {} (catch 1 (save-restriction (narrow-to-region 1 3) (throw 1 nil))) (point-max))", setup("restriction")),
+            5,
+            cx,
+        );
+        check_interpreter(
+            &format!("(progn {} (save-current-buffer (set-buffer (get-buffer-create \"test_save_forms_other\")) (insert \"x\")) (point-max))", setup("current")),
+            5,
+            cx,
+        );
+    }
 }
@@ -50,6 +50,7 @@ mod arith;
 mod buffer;
 mod bytecode;
 mod character;
+mod cmds;
 mod data;
 mod editfns;
 mod emacs;
@@ -59,9 +60,11 @@ mod floatfns;
 mod fns;
 mod gui;
 mod hashmap;
+mod indent;
 mod interpreter;
 mod keymap;
 mod lread;
+mod marker;
 mod print;
 mod reader;
 mod search;
@@ -118,7 +121,7 @@ fn load(env: &mut Rt<Env>, cx: &mut Context) {
     crate::core::env::init_variables(cx, env);
     crate::data::defalias(intern("not", cx), (crate::core::env::sym::NULL).into(), None)
         .expect("null should be defined");
-    buffer::get_buffer_create(cx.add("*scratch*"), core::object::nil(), cx).unwrap();
+    buffer::get_buffer_create(cx.add("*scratch*"), None, env, cx).unwrap();
 
     let buffer = String::from(r#"(load "lisp/bootstrap.el")"#);
 
@@ -0,0 +1,191 @@
+use crate::{
+    buffer::target_buffer,
+    core::{
+        env::Env,
+        gc::{Context, Rt},
+        object::{nil, Gc, GcObj, IntOrMarker, IntoObject, LispMarker},
+    },
+};
+use anyhow::{bail, Result};
+use fn_macros::defun;
+use text_buffer::InsertionType;
+
+fn insertion_type(advance: bool) -> InsertionType {
+    if advance {
+        InsertionType::Advance
+    } else {
+        InsertionType::Stay
+    }
+}
+
+#[defun]
+fn make_marker<'ob>(cx: &'ob Context) -> GcObj<'ob> {
+    cx.add(LispMarker::new(InsertionType::Stay))
+}
+
+#[defun]
+pub(crate) fn set_marker<'ob>(
+    marker: &'ob LispMarker,
+    position: Option<Gc<IntOrMarker>>,
+    buffer: Option<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let position = match position.map(Gc::untag) {
+        // Positions before the start of the buffer are clamped to it
+        Some(IntOrMarker::Int(pos)) => Some(usize::try_from(pos).unwrap_or(0)),
+        Some(IntOrMarker::Marker(other)) => match other.position() {
+            Some(pos) => Some(pos),
+            None => bail!("Marker does not point anywhere"),
+        },
+        None => None,
+    };
+    let Some(position) = position else {
+        marker.detach();
+        return Ok(cx.add(marker));
+    };
+    let buffer = target_buffer(buffer, env, cx)?;
+    if !marker.set_position_in(buffer, position) {
+        let kind = marker.insertion_type();
+        match env.with_buffer(buffer, |b| b.map(|b| b.create_marker(position, kind))) {
+            Some(text_marker) => marker.set(buffer, text_marker),
+            // A killed buffer can't hold markers
+            None => marker.detach(),
+        }
+    }
+    Ok(cx.add(marker))
+}
+
+#[defun]
+fn marker_position<'ob>(marker: &LispMarker, cx: &'ob Context) -> GcObj<'ob> {
+    match marker.position() {
+        Some(pos) => cx.add(pos),
+        None => nil(),
+    }
+}
+
+#[defun]
+fn marker_buffer<'ob>(marker: &LispMarker, cx: &'ob Context) -> GcObj<'ob> {
+    match marker.buffer() {
+        Some(buffer) => cx.add(buffer),
+        None => nil(),
+    }
+}
+
+#[defun]
+fn copy_marker<'ob>(
+    marker: Option<Gc<IntOrMarker>>,
+    kind: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let new = LispMarker::new(insertion_type(kind.is_some())).into_obj(cx).untag();
+    // An integer is a position in the current buffer
+    let buffer = match marker.map(Gc::untag) {
+        Some(IntOrMarker::Marker(marker)) => match marker.buffer() {
+            Some(buffer) => Some(cx.add(buffer)),
+            // A copy of a marker that points nowhere points nowhere as well
+            None => return Ok(cx.add(new)),
+        },
+        _ => None,
+    };
+    set_marker(new, marker, buffer, env, cx)
+}
+
+#[defun]
+fn point_marker<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Result<GcObj<'ob>> {
+    let buffer = target_buffer(None, env, cx)?;
+    let Some(current) = env.current_buffer.as_ref() else { bail!("No current buffer") };
+    let text_marker = current.create_marker(current.point(), InsertionType::Stay);
+    let marker = LispMarker::new(InsertionType::Stay).into_obj(cx).untag();
+    marker.set(buffer, text_marker);
+    Ok(cx.add(marker))
+}
+
+#[defun]
+fn set_marker_insertion_type<'ob>(marker: &LispMarker, kind: GcObj<'ob>) -> GcObj<'ob> {
+    marker.set_insertion_type(insertion_type(!kind.nil()));
+    kind
+}
+
+#[defun]
+fn marker_insertion_type(marker: &LispMarker) -> bool {
+    marker.insertion_type() == InsertionType::Advance
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::{get_buffer_create, kill_buffer, set_buffer},
+        core::gc::RootSet,
+        editfns::insert,
+        root,
+    };
+
+    #[test]
+    fn test_markers() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let buffer = get_buffer_create(cx.add("test_markers"), None, env, cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        insert(&[cx.add("hello world")], env).unwrap();
+
+        let marker = make_marker(cx);
+        let marker: &LispMarker = marker.try_into().unwrap();
+        assert_eq!(marker_position(marker, cx), nil());
+        assert_eq!(marker_buffer(marker, cx), nil());
+
+        set_marker(marker, Some(cx.add(5).try_into().unwrap()), None, env, cx).unwrap();
+        assert_eq!(marker_position(marker, cx), 5);
+        assert_eq!(marker_buffer(marker, cx), buffer);
+
+        // A marker used as a position
+        let copy = copy_marker(Some(cx.add(marker).try_into().unwrap()), Some(()), env, cx);
+        let copy: &LispMarker = copy.unwrap().try_into().unwrap();
+        assert_eq!(marker_position(copy, cx), 5);
+        assert!(marker_insertion_type(copy));
+        assert!(!marker_insertion_type(marker));
+
+        // Positions past the end are clamped
+        set_marker(marker, Some(cx.add(100).try_into().unwrap()), None, env, cx).unwrap();
+        assert_eq!(marker_position(marker, cx), 11);
+
+        set_marker(marker, None, None, env, cx).unwrap();
+        assert_eq!(marker_position(marker, cx), nil());
+        assert_eq!(marker_buffer(marker, cx), nil());
+        // The copy is independent of the original
+        assert_eq!(marker_position(copy, cx), 5);
+
+        // A marker that points nowhere is not a position
+        let position = Some(cx.add(marker).try_into().unwrap());
+        assert!(set_marker(copy, position, None, env, cx).is_err());
+        assert_eq!(marker_position(copy, cx), 5);
+        let position = Some(cx.add(marker).try_into().unwrap());
+        let copy: &LispMarker = copy_marker(position, None, env, cx).unwrap().try_into().unwrap();
+        assert_eq!(marker_position(copy, cx), nil());
+    }
+
+    #[test]
+    fn test_marker_in_killed_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let name = cx.add("test_marker_in_killed_buffer");
+        let buffer = get_buffer_create(name, None, env, cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        insert(&[cx.add("hello world")], env).unwrap();
+
+        let marker = make_marker(cx);
+        root!(marker, cx);
+        let position = Some(cx.add(5).try_into().unwrap());
+        set_marker(marker.bind(cx).try_into().unwrap(), position, None, env, cx).unwrap();
+        assert!(kill_buffer(None, env, cx).unwrap());
+
+        // Killing the buffer makes its markers point nowhere
+        let marker: &LispMarker = marker.bind(cx).try_into().unwrap();
+        assert_eq!(marker_buffer(marker, cx), nil());
+        assert_eq!(marker_position(marker, cx), nil());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::core::{
     env::Env,
     gc::{Context, Rt},
-    object::{nil, Gc, GcObj, List},
+    object::{nil, Gc, GcObj, List, Object},
 };
 use anyhow::{ensure, Result};
 use fancy_regex::Regex;
@@ -65,11 +65,28 @@ fn match_data<'ob>(
 }
 
 #[defun]
-fn set_match_data<'ob>(list: Gc<List>, _reseat: Option<()>, env: &mut Rt<Env>) -> GcObj<'ob> {
-    // TODO: add reseat when markers implemented
-    let obj: GcObj = list.into();
-    env.match_data.set(obj);
-    nil()
+fn set_match_data<'ob>(
+    list: Gc<List<'ob>>,
+    reseat: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    // Match data is stored as positions, so markers are replaced by theirs
+    let mut data = Vec::new();
+    for elem in list.elements() {
+        let elem = elem?;
+        match elem.untag() {
+            Object::Marker(marker) => {
+                data.push(marker.position().map_or_else(nil, |pos| cx.add(pos)));
+                if reseat.is_some() {
+                    marker.detach();
+                }
+            }
+            _ => data.push(elem),
+        }
+    }
+    env.match_data.set(crate::fns::slice_into_list(&data, None, cx));
+    Ok(nil())
 }
 
 #[defun]